@@ -0,0 +1,49 @@
+use std::time::Duration;
+use alloy_in_action_common::anvil::spawn_for_test;
+use alloy_in_action_common::{http_provider, Anvil, SampleContract};
+use alloy_primitives::U256;
+use alloy_provider::Provider;
+use alloy_rpc_types::Filter;
+use alloy_sol_types::SolEventInterface;
+use eyre::Result;
+use SampleContract::SampleContractEvents;
+
+#[tokio::test]
+async fn deploys_interacts_and_decodes() -> Result<()> {
+    let Some(anvil) = spawn_for_test(Anvil::new()) else { return Ok(()) };
+
+    // Run the example against the node
+    let mut command = tokio::process::Command::from(anvil.command(env!("CARGO_BIN_EXE_deploy_interact_decode")));
+    let output = tokio::time::timeout(Duration::from_secs(60), command.output()).await??;
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(output.status.success(), "example failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    assert!(stdout.contains("🔍 Initial value retrieved from contract: 1"), "{stdout}");
+    assert!(stdout.contains("🔍 Updated value retrieved from contract: 2"), "{stdout}");
+    assert!(stdout.contains("🔍 Contract balance after deposit: 0.001000000000000000 Ξ"), "{stdout}");
+    assert!(stdout.contains("⚠️ Call reverted with SampleError: \"hello from revert!\""), "{stdout}");
+
+    // The contract is the signer's first deployment on the fresh node
+    let config = anvil.config()?;
+    let provider = http_provider(&config);
+    let contract = SampleContract::new(config.signer.address().create(0), &provider);
+    assert_eq!(contract.getValue().call().await?.currentValue, U256::from(2));
+    assert_eq!(contract.getBalance().call().await?.balance, U256::from(1_000_000_000_000_000u64));
+
+    // Both state changes emitted their events
+    let logs = provider.get_logs(&Filter::new().address(*contract.address()).from_block(0)).await?;
+    let events = logs
+        .iter()
+        .map(|log| Ok(SampleContractEvents::decode_log(&log.inner, true)?.data))
+        .collect::<Result<Vec<_>>>()?;
+    assert!(matches!(
+        &events[..],
+        [SampleContractEvents::ValueChanged(changed), SampleContractEvents::EtherReceived(received)]
+            if changed.updater == config.signer.address()
+                && changed.oldValue == U256::from(1)
+                && changed.newValue == U256::from(2)
+                && received.amount == U256::from(1_000_000_000_000_000u64)
+    ), "{events:?}");
+
+    Ok(())
+}
@@ -1,92 +1,49 @@
-use std::path::Path;
 use alloy_chains::NamedChain;
-use alloy_network::EthereumWallet;
-use alloy_network::primitives::BlockTransactionsKind;
-use alloy_primitives::{utils, TxKind, U256};
+use alloy_in_action_common::{
+    wallet, Config, Confirmation, ConfirmationPolicy, ConfirmationTracker, FeeEstimator, FeeStrategy, NonceManager,
+    ReplacingSender, SampleContract,
+};
+use alloy_primitives::utils::parse_ether;
+use alloy_primitives::{TxHash, TxKind, U256};
 use alloy_provider::{Provider, ProviderBuilder, WsConnect};
-use alloy_signer_local::PrivateKeySigner;
-use alloy_sol_macro::sol;
 use alloy_sol_types::{SolCall, SolConstructor};
-use eyre::Result;
-use url::Url;
-use alloy_network::TransactionBuilder;
-use alloy_rpc_types::{BlockId, TransactionRequest};
+use alloy_transport::Transport;
+use eyre::{bail, eyre, Result};
+use futures::{pin_mut, StreamExt};
+use alloy_network::{Ethereum, TransactionBuilder};
+use alloy_rpc_types::{TransactionReceipt, TransactionRequest};
 use alloy_sol_types::private::Bytes;
-use utils::parse_units;
-
-sol! {
-    // source/reference contract in solidity-smart-contracts/src/SampleContract.sol
-    // bytecode via `solc SampleContract.sol --bin --via-ir --optimize --optimize-runs 1`
-    #[sol(rpc, bytecode = "608034604d57601f61028038819003918201601f19168301916001600160401b03831184841017605157808492602094604052833981010312604d57515f5560405161021a90816100668239f35b5f80fd5b634e487b7160e01b5f52604160045260245ffdfe6080806040526004361015610012575f80fd5b5f3560e01c90816312065fe0146101cc5750806320965255146101405780633ccfd60b1461015c5780633fa4f2451461014057806355241077146100f857806357eca1a5146100ad5763d0e30db014610069575f80fd5b5f3660031901126100a957476040519034825260208201527f1d57945c1033a96907a78f6e0ebf6a03815725dac25f33cc806558670344ac8860403392a2005b5f80fd5b346100a9575f3660031901126100a9576040516335fdd7ab60e21b815260206004820152601260248201527168656c6c6f2066726f6d207265766572742160701b6044820152606490fd5b346100a95760203660031901126100a9576004355f5490805f556040519081527fe435f0fbe584e62b62f48f4016a57ef6c95e4c79f5babbe6ad3bb64f3281d26160203392a3005b346100a9575f3660031901126100a95760205f54604051908152f35b346100a9575f3660031901126100a95747805f81156101c3575b5f80809381933390f1156101b8576040519081525f60208201527fd5ca65e1ec4f4864fea7b9c5cb1ec3087a0dbf9c74641db3f6458edf445c405160403392a2005b6040513d5f823e3d90fd5b506108fc610176565b346100a9575f3660031901126100a957602090478152f3fea2646970667358221220cae439afc02e7259cc99c579d322222052f82f79b377ffd437d0523157cb795f64736f6c634300081b0033")]
-    contract SampleContract {
-        // Events
-        event ValueChanged(address indexed updater, uint256 indexed oldValue, uint256 newValue);
-        event EtherReceived(address indexed sender, uint256 amount, uint256 newBalance);
-        event EtherWithdrawn(address indexed recipient, uint256 amount, uint256 remainingBalance);
-
-        // Errors
-        error SampleError(string cause);
-
-        // Constructor
-        constructor(uint256 _initialValue);
-
-        // Functions
-        /// @notice Sets a new value for the 'value' state variable
-        /// @param _value The new value to be set
-        function setValue(uint256 _value) external;
-
-        /// @notice Retrieves the current value of the 'value' state variable
-        /// @return currentValue The current value stored in 'value'
-        function getValue() external view returns (uint256 currentValue);
-
-        /// @notice Accepts Ether deposits and logs the sender and amount
-        function deposit() external payable;
-
-        /// @notice Withdraws the entire balance of the contract to the caller
-        function withdraw() external;
-
-        /// @notice Retrieves the contract's current Ether balance
-        /// @return balance The current balance of the contract in wei
-        function getBalance() external view returns (uint256 balance);
-
-        /// @notice Reverts the transaction with a custom error message
-        /// @dev Used to demonstrate custom error handling in Solidity
-        function revertWithError() external pure;
-    }
-}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Load root .env and initialize environment variables
-    let env_path =
-        Path::new(env!("CARGO_MANIFEST_DIR"))
-            .parent()
-            .unwrap()
-            .join(".env");
-    dotenv::from_path(env_path).ok();
+    // Load the configuration (config file, root .env and command-line overrides)
+    let config = Config::from_cli()?;
 
     // Initialize tracing subscriber for logging
     tracing_subscriber::fmt::init();
 
-    // Create signer and wallet
-    let private_key = std::env::var("ANVIL_PRIVATE_KEY")?;
-    let signer: PrivateKeySigner = private_key.parse()?;
-    let signer_address = signer.address();
-    let wallet: EthereumWallet = EthereumWallet::from(signer);
-
+    // Create wallet for the primary signer
+    let signer_address = config.signer.address();
+    let wallet = wallet(&config.signer);
 
     // Set up provider with chain ID, wallet, and network details (using WebSocket)
-    let ws_url = std::env::var("ANVIL_WS_URL")?;
-    let ws_url = Url::parse(&ws_url)?;
     let provider = ProviderBuilder::new()
         .with_chain(NamedChain::AnvilHardhat)
-        .with_chain_id(31337)
+        .with_chain_id(config.chain_id)
         .wallet(wallet)
-        .on_ws(WsConnect::new(ws_url)).await?;
+        .on_ws(WsConnect::new(config.ws_url()?.clone())).await?;
 
     // Set the number of confirmations to wait for a transaction to be "confirmed"
     // (6-12) for high value transactions, (1-3) for low value transactions
-    let confirmations = 3u64;
+    let confirmation_tracker = ConfirmationTracker::new(
+        ConfirmationPolicy::fixed(3)
+            .with_tier(parse_ether("1")?, 6)
+            .with_tier(parse_ether("100")?, 12),
+    );
+
+    // Replace transactions that are not mined within 30 seconds with 10% higher fees
+    let fee_estimator = FeeEstimator::new(FeeStrategy::Normal);
+    let sender = ReplacingSender::new().with_estimator(fee_estimator.clone());
 
     // Prepare contract deployment bytecode with initialization of value to 1
     let initial_value = U256::from(1);
@@ -97,97 +54,68 @@ async fn main() -> Result<()> {
         .concat()
         .into();
 
-    let nonce = provider.get_transaction_count(signer_address).pending().await?;
-
-    // Fetch the latest block to obtain current gas parameters
-    let latest_block = provider
-        .get_block(BlockId::latest(), BlockTransactionsKind::Hashes)
-        .await?
-        .unwrap();
-
-    // Calculate next block's base fee based on the latest block
-    let base_fee = calculate_base_fee_per_gas(
-        latest_block.header.base_fee_per_gas.unwrap(),
-        latest_block.header.gas_used,
-        latest_block.header.gas_limit
-    );
+    // Hand out the signer's nonces locally, so several transactions can be in flight at once
+    let nonce_manager = NonceManager::default();
 
-    // We set a fixed tip of 2.5 Gwei for simplicity.
-    let tip = parse_units("2.5", "gwei")?.try_into()?;
+    // Estimate EIP-1559 fees: the tip is the median of the tips paid in recent blocks
+    // (eth_feeHistory), the base fee is projected a few blocks ahead from the latest block
+    let fees = fee_estimator.estimate(&provider).await?;
 
     let tx_base = TransactionRequest::default()
-        .with_deploy_code(deploy_bytecode)
         // .with_input(deploy_bytecode)
         // .with_kind(TxKind::Create)
-        .with_nonce(nonce);
+        .with_deploy_code(deploy_bytecode);
 
     let estimated_gas = provider.estimate_gas(&tx_base).await?;
 
     let tx = tx_base.with_gas_limit(estimated_gas)
-        .with_max_priority_fee_per_gas(tip)
-        .with_max_fee_per_gas(base_fee as u128 + tip);
-
-    // Send deployment transaction
-    let tx_builder = provider.send_transaction(tx).await?;
-    println!("🔄 Transaction sent ({:#x}).", tx_builder.tx_hash());
-
-    // Await confirmation
-    let tx_hash = tx_builder.with_required_confirmations(confirmations).watch().await?;
-    println!("✅ Transaction confirmed ({:#x}).", tx_hash);
+        .with_max_priority_fee_per_gas(fees.max_priority_fee_per_gas)
+        .with_max_fee_per_gas(fees.max_fee_per_gas);
+
+    // Send deployment transaction with the next nonce, replacing it while it is not mined
+    let sent = nonce_manager
+        .send_with_nonce(&provider, signer_address, |nonce| sender.send(&provider, tx.clone().with_nonce(nonce)))
+        .await?;
+    for tx_hash in &sent.hashes {
+        println!("🔄 Transaction sent ({:#x}).", tx_hash);
+    }
 
-    // Retrieve transaction receipt
-    let receipt = provider
-        .get_transaction_receipt(tx_hash)
-        .await?
-        .expect("Deploy transaction receipt not found");
+    // Await confirmation, following the transaction through reorgs
+    let receipt = await_confirmations(&confirmation_tracker, &provider, sent.tx_hash()).await?;
+    println!("✅ Transaction confirmed ({:#x}).", receipt.transaction_hash);
     println!("🧾 Deploy transaction receipt obtained ({:#x}).", receipt.transaction_hash);
 
     let deploy_address = receipt.contract_address.unwrap();
     println!("📍 Contract deployed at address ({:#x}).", deploy_address);
 
-    // Fetch the latest block to obtain current block gas parameters
-    let latest_block = provider
-        .get_block(BlockId::latest(), BlockTransactionsKind::Hashes)
-        .await?
-        .unwrap();
-
-    // calculate next block's base fee
-    let base_fee = calculate_base_fee_per_gas(
-        latest_block.header.base_fee_per_gas.unwrap(),
-        latest_block.header.gas_used,
-        latest_block.header.gas_limit
-    );
+    // Re-estimate fees against the latest block
+    let fees = fee_estimator.estimate(&provider).await?;
 
     // Prepare setValue transaction to update the value to 2
     let tx_data = SampleContract::setValueCall { _value: U256::from(2u64) }.abi_encode();
-    let nonce = provider.get_transaction_count(signer_address).pending().await?;
-
     let tx_base = TransactionRequest::default()
         .with_input(tx_data)
         .with_to(deploy_address)
         .with_from(signer_address)
-        .with_nonce(nonce)
         .with_kind(TxKind::Call(deploy_address));
 
     let estimated_gas = provider.estimate_gas(&tx_base).await?;
 
     let tx = tx_base.with_gas_limit(estimated_gas)
-        .with_max_priority_fee_per_gas(tip)
-        .with_max_fee_per_gas(base_fee as u128 + tip);
-
-    // Send setValue transaction
-    let tx_builder = provider.send_transaction(tx).await?;
-    println!("🔄 setValue transaction sent ({:#x}).", tx_builder.tx_hash());
-
-    // Await confirmation
-    let tx_hash = tx_builder.with_required_confirmations(confirmations).watch().await?;
-    println!("✅ setValue transaction confirmed ({:#x}).", tx_hash);
+        .with_max_priority_fee_per_gas(fees.max_priority_fee_per_gas)
+        .with_max_fee_per_gas(fees.max_fee_per_gas);
+
+    // Send setValue transaction with the next nonce, replacing it while it is not mined
+    let sent = nonce_manager
+        .send_with_nonce(&provider, signer_address, |nonce| sender.send(&provider, tx.clone().with_nonce(nonce)))
+        .await?;
+    for tx_hash in &sent.hashes {
+        println!("🔄 setValue transaction sent ({:#x}).", tx_hash);
+    }
 
-    // Retrieve transaction receipt for setValue
-    let receipt = provider
-        .get_transaction_receipt(tx_hash)
-        .await?
-        .expect("setValue transaction receipt not found");
+    // Await confirmation, following the transaction through reorgs
+    let receipt = await_confirmations(&confirmation_tracker, &provider, sent.tx_hash()).await?;
+    println!("✅ setValue transaction confirmed ({:#x}).", receipt.transaction_hash);
     println!("🧾 setValue transaction receipt obtained ({:#x}).", receipt.transaction_hash);
 
     // Prepare getValue call to fetch the current value
@@ -208,54 +136,33 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-/// Calculates the base fee per gas for the next block based on EIP-1559 specifications.
-///
-/// This function adjusts the base fee according to the gas usage of the current block.
-/// If the gas used is higher than the target (50% of the gas limit), the base fee increases.
-/// If it's lower, the base fee decreases. The change is capped at a maximum of ±12.5% per block.
-///
-/// # Arguments
-///
-/// * `current_base_fee` - The base fee per gas of the current block (in wei).
-/// * `current_gas_used` - The total gas used in the current block.
-/// * `current_gas_limit` - The gas limit of the current block.
-///
-/// # Returns
-///
-/// * `u64` - The calculated base fee per gas for the next block.
-pub fn calculate_base_fee_per_gas(
-    current_base_fee: u64,
-    current_gas_used: u64,
-    current_gas_limit: u64,
-) -> u64 {
-    // Calculate the target gas usage (50% of the gas limit)
-    let gas_target = current_gas_limit / 2;
-
-    // Calculate the difference between gas used and gas target
-    let gas_delta = current_gas_used as i64 - gas_target as i64;
-
-    // Maximum base fee change is 12.5% of the current base fee
-    let max_base_fee_change = current_base_fee / 8;
-
-    // If gas usage is exactly at the target, base fee remains the same
-    if gas_delta == 0 {
-        return current_base_fee;
-    }
-
-    // Calculate the absolute value of gas delta for adjustment calculation
-    let gas_delta_abs = gas_delta.unsigned_abs();
-
-    // Compute the base fee change
-    // Using u128 to prevent potential overflow in intermediate calculations
-    let base_fee_change = ((max_base_fee_change as u128 * gas_delta_abs as u128)
-        / gas_target as u128) as u64;
-
-    if gas_delta > 0 {
-        // Increase base fee by the calculated change
-        current_base_fee + base_fee_change
-    } else {
-        // Decrease base fee by the calculated change, ensuring it doesn't go below zero
-        current_base_fee.saturating_sub(base_fee_change)
+/// Waits for the confirmations required by the tracker's policy, printing each state transition.
+async fn await_confirmations<P, T>(
+    tracker: &ConfirmationTracker,
+    provider: &P,
+    tx_hash: TxHash,
+) -> Result<TransactionReceipt>
+where
+    P: Provider<T, Ethereum>,
+    T: Transport + Clone,
+{
+    let transitions = tracker.track(provider, tx_hash);
+    pin_mut!(transitions);
+
+    let mut receipt = None;
+    while let Some(transition) = transitions.next().await {
+        match transition? {
+            Confirmation::Included(included) => {
+                println!("📥 Included in block {}.", included.block_number.unwrap_or_default());
+                receipt = Some(*included);
+            }
+            Confirmation::Confirmed(confirmations) => println!("⏳ {} confirmation(s).", confirmations),
+            Confirmation::Reorged { block_number, .. } => {
+                println!("🔀 Block {} was reorganized away, waiting for re-inclusion.", block_number);
+                receipt = None;
+            }
+            Confirmation::Dropped => bail!("transaction {:#x} was dropped", tx_hash),
+        }
     }
+    receipt.ok_or_else(|| eyre!("transaction {:#x} was not confirmed", tx_hash))
 }
-
@@ -0,0 +1,43 @@
+use std::time::Duration;
+use alloy_in_action_common::anvil::spawn_for_test;
+use alloy_in_action_common::{http_provider, Anvil, ReceiptExt, SampleContract};
+use alloy_primitives::U256;
+use alloy_provider::Provider;
+use alloy_rpc_types::{BlockNumberOrTag, Filter};
+use eyre::Result;
+use SampleContract::ValueChanged;
+
+#[tokio::test]
+async fn composes_and_confirms_transactions() -> Result<()> {
+    // The example waits for 3 confirmations, so blocks must be mined without transactions too
+    let Some(anvil) = spawn_for_test(Anvil::new().block_time(1)) else { return Ok(()) };
+
+    // Run the example against the node
+    let mut command = tokio::process::Command::from(anvil.command(env!("CARGO_BIN_EXE_advanced_transaction_composition")));
+    let output = tokio::time::timeout(Duration::from_secs(60), command.output()).await??;
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(output.status.success(), "example failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("🔍 Current value from contract: 2"), "{stdout}");
+
+    // The contract is the signer's first deployment on the fresh node
+    let config = anvil.config()?;
+    let provider = http_provider(&config);
+    let contract = SampleContract::new(config.signer.address().create(0), &provider);
+    assert_eq!(contract.getValue().call().await?.currentValue, U256::from(2));
+
+    // setValue emitted its event and paid at least the base fee of its block
+    let logs = provider.get_logs(&Filter::new().address(*contract.address()).from_block(0)).await?;
+    assert_eq!(logs.len(), 1);
+    let tx_hash = logs[0].transaction_hash.expect("mined log");
+    let receipt = provider.get_transaction_receipt(tx_hash).await?.expect("receipt");
+    let event = receipt.expect_event::<ValueChanged>();
+    assert_eq!((event.updater, event.oldValue, event.newValue), (config.signer.address(), U256::from(1), U256::from(2)));
+
+    let block = provider
+        .get_block_by_number(BlockNumberOrTag::Number(receipt.block_number.expect("mined receipt")), false.into())
+        .await?
+        .expect("block");
+    assert!(receipt.effective_gas_price >= block.header.base_fee_per_gas.expect("EIP-1559 block") as u128);
+
+    Ok(())
+}
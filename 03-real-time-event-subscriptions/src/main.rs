@@ -1,86 +1,81 @@
-use std::{io::stdin, path::Path};
-use eyre::Result;
+use std::time::Duration;
+use eyre::{bail, Result};
 use futures::StreamExt;
-use url::Url;
-use alloy_network::EthereumWallet;
-use alloy_primitives::{Address, B256, U256, utils::Unit};
-use alloy_provider::{Provider, ProviderBuilder, WalletProvider, WsConnect};
+use alloy_in_action_common::event_stream::DEFAULT_POLL_INTERVAL;
+use alloy_in_action_common::{
+    encode_topics, http_provider, ws_provider, Checkpoint, CheckpointStore, Completion, Config, EventQueue, EventStream,
+    HandlerRegistry, JsonCheckpointStore, OverflowPolicy, Progress, RawEvent, ReconnectPolicy, SampleContract, StopReason, StreamEvent, Supervisor,
+    TaskOutcome,
+};
+use alloy_contract::Event;
+use alloy_network::{Ethereum, EthereumWallet};
+use alloy_primitives::{Address, U256, utils::Unit};
+use alloy_provider::{Provider, WalletProvider};
 use alloy_rpc_types::{BlockNumberOrTag, Filter};
-use alloy_signer_local::PrivateKeySigner;
-use alloy_sol_macro::sol;
-use alloy_sol_types::{SolEventInterface, SolEvent};
-use crate::SampleContract::{EtherReceived, EtherWithdrawn, SampleContractEvents, ValueChanged};
-
-sol! {
-    // source/reference contract in solidity-smart-contracts/src/SampleContract.sol
-    // bytecode via `solc SampleContract.sol --bin --via-ir --optimize --optimize-runs 1`
-    #[sol(rpc, bytecode = "608034604d57601f61028038819003918201601f19168301916001600160401b03831184841017605157808492602094604052833981010312604d57515f5560405161021a90816100668239f35b5f80fd5b634e487b7160e01b5f52604160045260245ffdfe6080806040526004361015610012575f80fd5b5f3560e01c90816312065fe0146101cc5750806320965255146101405780633ccfd60b1461015c5780633fa4f2451461014057806355241077146100f857806357eca1a5146100ad5763d0e30db014610069575f80fd5b5f3660031901126100a957476040519034825260208201527f1d57945c1033a96907a78f6e0ebf6a03815725dac25f33cc806558670344ac8860403392a2005b5f80fd5b346100a9575f3660031901126100a9576040516335fdd7ab60e21b815260206004820152601260248201527168656c6c6f2066726f6d207265766572742160701b6044820152606490fd5b346100a95760203660031901126100a9576004355f5490805f556040519081527fe435f0fbe584e62b62f48f4016a57ef6c95e4c79f5babbe6ad3bb64f3281d26160203392a3005b346100a9575f3660031901126100a95760205f54604051908152f35b346100a9575f3660031901126100a95747805f81156101c3575b5f80809381933390f1156101b8576040519081525f60208201527fd5ca65e1ec4f4864fea7b9c5cb1ec3087a0dbf9c74641db3f6458edf445c405160403392a2005b6040513d5f823e3d90fd5b506108fc610176565b346100a9575f3660031901126100a957602090478152f3fea2646970667358221220cae439afc02e7259cc99c579d322222052f82f79b377ffd437d0523157cb795f64736f6c634300081b0033")]
-    contract SampleContract {
-        // Events
-        event ValueChanged(address indexed updater, uint256 indexed oldValue, uint256 newValue);
-        event EtherReceived(address indexed sender, uint256 amount, uint256 newBalance);
-        event EtherWithdrawn(address indexed recipient, uint256 amount, uint256 remainingBalance);
-
-        // Errors
-        error SampleError(string cause);
-
-        // Constructor
-        constructor(uint256 _initialValue);
-
-        // Functions
-        /// @notice Sets a new value for the 'value' state variable
-        /// @param _value The new value to be set
-        function setValue(uint256 _value) external;
-
-        /// @notice Retrieves the current value of the 'value' state variable
-        /// @return currentValue The current value stored in 'value'
-        function getValue() external view returns (uint256 currentValue);
-
-        /// @notice Accepts Ether deposits and logs the sender and amount
-        function deposit() external payable;
-
-        /// @notice Withdraws the entire balance of the contract to the caller
-        function withdraw() external;
-
-        /// @notice Retrieves the contract's current Ether balance
-        /// @return balance The current balance of the contract in wei
-        function getBalance() external view returns (uint256 balance);
-
-        /// @notice Reverts the transaction with a custom error message
-        /// @dev Used to demonstrate custom error handling in Solidity
-        function revertWithError() external pure;
-    }
+use alloy_sol_types::{sol_data, SolEvent};
+use alloy_transport::Transport;
+use SampleContract::{EtherReceived, EtherWithdrawn, ValueChanged};
+
+/// Number of blocks an event must be buried under before it is final.
+const FINALITY_DEPTH: u64 = 3;
+
+/// Number of setValue, deposit and withdraw rounds, one per signer.
+const ROUNDS: u64 = 2;
+
+/// Number of combined events queued for the handlers before the subscription is paused.
+const EVENT_QUEUE_CAPACITY: usize = 64;
+
+/// Name under which the events of the ValueChanged subscription are counted.
+const VALUE_CHANGED_SUBSCRIPTION: &str = "ValueChanged subscription";
+
+/// Name of the checkpoint file of the combined events, in the temporary directory.
+const CHECKPOINT_FILE: &str = "real-time-event-subscriptions.checkpoints.json";
+
+/// How the example listens for events.
+#[derive(Clone, Copy, Debug)]
+enum Listen {
+    /// Subscribe over WebSocket.
+    Subscribe,
+    /// Poll the HTTP endpoint at the given interval.
+    Poll(Duration),
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Load root .env and initialize environment variables
-    let env_path =
-        Path::new(env!("CARGO_MANIFEST_DIR"))
-            .parent()
-            .unwrap()
-            .join(".env");
-    dotenv::from_path(env_path).ok();
+    // Load the configuration (config file, root .env and command-line overrides)
+    let config = Config::from_cli()?;
 
     // Initialize tracing subscriber for logging
     tracing_subscriber::fmt::init();
 
-    // Initialize signers and wallet
-    let private_key = std::env::var("ANVIL_PRIVATE_KEY")?;
-    let secondary_private_key = std::env::var("ANVIL_SECONDARY_PRIVATE_KEY")?;
-    let signer: PrivateKeySigner = private_key.parse()?;
-    let secondary_signer: PrivateKeySigner = secondary_private_key.parse()?;
-    let signer_address = signer.address();
+    // Prefer WebSocket subscriptions, fall back to polling over HTTP when no WebSocket endpoint
+    // is configured or it cannot be reached
+    match ws_provider(&config).await {
+        Ok(provider) => {
+            println!("🔌 Connected over WebSocket.");
+            run(&config, provider, Listen::Subscribe).await
+        }
+        Err(e) => {
+            let poll_interval = config.poll_interval().unwrap_or(DEFAULT_POLL_INTERVAL);
+            println!("🔌 WebSocket unavailable ({}), polling over HTTP every {:?}.", e, poll_interval);
+            run(&config, http_provider(&config), Listen::Poll(poll_interval)).await
+        }
+    }
+}
+
+/// Deploys the contract, listens for its events and sends transactions from both signers.
+async fn run<P, T>(config: &Config, mut provider: P, listen: Listen) -> Result<()>
+where
+    P: Provider<T, Ethereum> + WalletProvider<Ethereum, Wallet = EthereumWallet> + Clone + 'static,
+    T: Transport + Clone,
+{
+    // Initialize signers
+    let secondary_signer = config.secondary_signer()?.clone();
+    let signer_address = config.signer.address();
     let secondary_signer_address = secondary_signer.address();
-    let wallet: EthereumWallet = EthereumWallet::from(signer);
 
-    // Set up provider using WebSocket
-    let ws_url = std::env::var("ANVIL_WS_URL")?;
-    let ws_url = Url::parse(&ws_url)?;
-    let mut provider = ProviderBuilder::new()
-        .with_recommended_fillers()
-        .wallet(wallet)
-        .on_ws(WsConnect::new(ws_url)).await?;
+    // Remember where the contract's history starts
+    let deploy_block = provider.get_block_number().await?;
 
     // Deploy the contract with an initial value of 1
     let initial_value = U256::from(1);
@@ -91,102 +86,228 @@ async fn main() -> Result<()> {
         &contract_address, initial_value
     );
 
-    // Set up event filters
-    let address_filter: Vec<B256> = vec![
-        B256::left_padding_from(&signer_address.0.0),
-        B256::left_padding_from(&secondary_signer_address.0.0)
-    ];
+    // Set up event filters for the events of both signers
+    let signers = [signer_address, secondary_signer_address];
 
-    // Create a filter for the ValueChanged event starting from the latest block
-    let value_changed_filter = contract
-        .ValueChanged_filter()
-        .topic1(address_filter.clone())
+    // Create a filter for the ValueChanged events whose indexed updater is one of the signers,
+    // starting from the latest block
+    let value_changed_filter = ValueChanged::filter().address(contract_address).updater_in(signers);
+    let value_changed_filter = Event::<_, _, ValueChanged>::new(&provider, value_changed_filter.into_filter())
         .from_block(BlockNumberOrTag::Latest);
 
-    // Subscribe to the ValueChanged event logs
-    let value_changed_subscription = value_changed_filter.subscribe().await?;
-    println!("📡 Subscribed to ValueChanged events.");
+    // Subscribe to the ValueChanged event logs, or watch them with eth_newFilter and
+    // eth_getFilterChanges over HTTP, and convert them into a stream for processing
+    let mut value_changed_stream = match listen {
+        Listen::Subscribe => {
+            let value_changed_subscription = value_changed_filter.subscribe().await?;
+            println!("📡 Subscribed to ValueChanged events.");
+            value_changed_subscription.into_stream().boxed()
+        }
+        Listen::Poll(poll_interval) => {
+            let mut value_changed_poller = value_changed_filter.watch().await?;
+            value_changed_poller.poller.set_poll_interval(poll_interval);
+            println!("📡 Watching ValueChanged events.");
+            value_changed_poller.into_stream().boxed()
+        }
+    };
 
-    // Convert the subscription into a stream for processing
-    let mut value_changed_stream = value_changed_subscription.into_stream();
+    // Supervise the listeners until every event of the transactions below was handled by both
+    // subscriptions, or until Ctrl+C/SIGTERM
+    let mut supervisor = Supervisor::new()
+        .until(Completion::named(VALUE_CHANGED_SUBSCRIPTION, ROUNDS))
+        .until(Completion::events::<ValueChanged>(ROUNDS))
+        .until(Completion::events::<EtherReceived>(ROUNDS))
+        .until(Completion::events::<EtherWithdrawn>(ROUNDS));
+    let progress = supervisor.progress();
 
     // Spawn a task to handle incoming ValueChanged events
-    tokio::spawn(async move {
+    let value_changed_progress = progress.clone();
+    supervisor.spawn("ValueChanged listener", |mut shutdown| async move {
         println!("👂 Listening for ValueChanged events...");
-        while let Some(result) = value_changed_stream.next().await {
-            match result {
-                Ok((event, log)) => {
-                    // Print details of the ValueChanged event
-                    println!(
-                        "⚡️ |ValueChanged| - updater: {}, oldValue: {}, newValue: {} [{}]",
-                        event.updater, event.oldValue, event.newValue, log.address()
-                    );
-                }
-                Err(e) => {
-                    eprintln!("⚠️ Error processing event: {:?}", e)
-                    // Handle error ...
-                }
+        loop {
+            tokio::select! {
+                _ = shutdown.requested() => return Ok(()),
+                result = value_changed_stream.next() => match result {
+                    Some(Ok((event, log))) if log.removed => {
+                        // The block of the event was reorganized away, so it no longer counts
+                        println!("↩️ |ValueChanged| - updater: {} was reorganized away", event.updater);
+                        value_changed_progress.retract_named(VALUE_CHANGED_SUBSCRIPTION);
+                    }
+                    Some(Ok((event, log))) => {
+                        // Print details of the ValueChanged event
+                        println!(
+                            "⚡️ |ValueChanged| - updater: {}, oldValue: {}, newValue: {} [{}]",
+                            event.updater, event.oldValue, event.newValue, log.address()
+                        );
+                        value_changed_progress.record_named(VALUE_CHANGED_SUBSCRIPTION, log.block_number.unwrap_or_default());
+                    }
+                    Some(Err(e)) => {
+                        eprintln!("⚠️ Error processing event: {:?}", e)
+                        // Handle error ...
+                    }
+                    // The subscription ends when the connection drops (see the combined events below)
+                    None => bail!("ValueChanged stream closed"),
+                },
             }
         }
     });
 
-    // Create a combined filter for multiple events
+    // Register a handler per event type, and a fallback for logs none of them can decode.
+    // A failing handler is reported without stopping the others or the listener.
+    let (value_changed, ether_received, ether_withdrawn) = (progress.clone(), progress.clone(), progress.clone());
+    let handlers = HandlerRegistry::new()
+        .on(move |e: ValueChanged, log| {
+            let progress = value_changed.clone();
+            async move {
+                println!(
+                    "⚡️ ValueChanged   - updater: {}, oldValue: {}, newValue: {} [{}] ",
+                    e.updater, e.oldValue, e.newValue, log.address
+                );
+                progress.record::<ValueChanged>(log.block_number);
+                Ok(())
+            }
+        })
+        .on(move |e: EtherReceived, log| {
+            let progress = ether_received.clone();
+            async move {
+                println!(
+                    "⚡️ EtherReceived  - sender: {}, amount: {}, newBalance: {} [{}]",
+                    e.sender, e.amount, e.newBalance, log.address
+                );
+                progress.record::<EtherReceived>(log.block_number);
+                Ok(())
+            }
+        })
+        .on(move |e: EtherWithdrawn, log| {
+            let progress = ether_withdrawn.clone();
+            async move {
+                println!(
+                    "⚡️ EtherWithdrawn - recipient: {}, amount: {}, remainingBalance: {} [{}]",
+                    e.recipient, e.amount, e.remainingBalance, log.address
+                );
+                progress.record::<EtherWithdrawn>(log.block_number);
+                Ok(())
+            }
+        })
+        .on_unknown(|log| async move {
+            eprintln!(
+                "⚠️ Unknown event received in block {}, log index {}: {:?}",
+                log.block_number, log.log_index, log.data.0
+            );
+            Ok(())
+        });
+
+    // Create a combined filter for the events of the registered handlers. Their first indexed
+    // argument (updater, sender and recipient) is an address, which must be one of the signers
     let events_filter = Filter::new()
         .address(contract_address)
-        .topic1(address_filter.clone())
-        .event_signature(vec![
-            ValueChanged::SIGNATURE_HASH,
-            EtherReceived::SIGNATURE_HASH,
-            EtherWithdrawn::SIGNATURE_HASH
-        ])
-        .from_block(BlockNumberOrTag::Latest);
+        .topic1(encode_topics::<sol_data::Address>(signers))
+        .event_signature(handlers.event_signatures());
+
+    // Load the checkpoint of the combined events: the last finalized event a previous run of
+    // this deployment processed. A checkpoint beyond the current head belongs to an earlier
+    // chain (e.g. a restarted Anvil node with the same contract address), so it is ignored.
+    let checkpoints = JsonCheckpointStore::new(std::env::temp_dir().join(CHECKPOINT_FILE));
+    let subscription = format!("combined events of {}", contract_address);
+    let head = provider.get_block_number().await?;
+    let checkpoint = checkpoints.load(&subscription)?.filter(|checkpoint| checkpoint.block_number <= head);
 
-    // Subscribe to the combined events filter
-    let events_subscription = provider.subscribe_logs(&events_filter).await?;
-    println!("📡 Subscribed to combined events.");
+    // Subscribe to the combined events filter, tracking the block hash of each event so that
+    // events of blocks reorganized away are retracted, and events are final 3 blocks deep.
+    // If the connection drops, reconnect with backoff and backfill the missed events.
+    // Start with all events since the deployment (or right after the checkpoint), then switch
+    // to the live ones.
+    let events_stream = EventStream::new(events_filter)
+        .from_block(deploy_block)
+        .with_finality_depth(FINALITY_DEPTH)
+        .with_reconnect_policy(ReconnectPolicy::new(Duration::from_millis(500), Duration::from_secs(30)));
+    let events_stream = match checkpoint {
+        Some(checkpoint) => {
+            println!("💾 Resuming combined events after {}.", checkpoint);
+            events_stream.resume_after(checkpoint)
+        }
+        None => events_stream,
+    };
+    let events = match listen {
+        Listen::Subscribe => {
+            let reconnect_config = config.clone();
+            let events = events_stream
+                .subscribe_with_reconnect::<RawEvent, _, _, _, _, _>(move || {
+                    let config = reconnect_config.clone();
+                    async move { ws_provider(&config).await }
+                })
+                .await?;
+            println!("📡 Subscribed to combined events.");
+            events.boxed()
+        }
+        Listen::Poll(poll_interval) => {
+            // Poll for new blocks and fetch their logs with eth_getLogs
+            let events = events_stream
+                .with_poll_interval(poll_interval)
+                .poll::<RawEvent, _, _>(provider.clone())
+                .await?;
+            println!("📡 Polling combined events.");
+            events.boxed()
+        }
+    };
 
-    // Convert the subscription into a stream for processing
-    let mut events_stream = events_subscription.into_stream();
+    // Read the stream on a task of its own, so that slow handlers do not hold up the
+    // subscription. When the handlers fall behind by more than the queue's capacity, stop
+    // reading until they caught up (OverflowPolicy::DropOldest or spawn_spilling would keep reading)
+    let mut events = EventQueue::new(EVENT_QUEUE_CAPACITY)
+        .with_overflow(OverflowPolicy::Block)
+        .spawn(events);
 
-    // Spawn a task to listen and decode all contract events into their specific types
-    tokio::spawn(async move {
+    // Spawn a task to listen for all contract events, dispatched to the handlers of their types.
+    // An event received before the shutdown is handled completely before the task stops.
+    supervisor.spawn("combined listener", move |mut shutdown| async move {
         println!("👂 Listening for events...");
-        while let Some(log) = events_stream.next().await {
-            // Decode the log into SampleContractEvents enum
-            match SampleContractEvents::decode_log(log.as_ref(), true) {
-                Ok(event) => {
-                    match event.data {
-                        SampleContractEvents::ValueChanged(e) => {
-                            println!(
-                                "⚡️ ValueChanged   - updater: {}, oldValue: {}, newValue: {} [{}] ",
-                                e.updater, e.oldValue, e.newValue, log.address()
-                            );
-                        }
-                        SampleContractEvents::EtherReceived(e) => {
-                            println!(
-                                "⚡️ EtherReceived  - sender: {}, amount: {}, newBalance: {} [{}]",
-                                e.sender, e.amount, e.newBalance, log.address()
-                            );
-                        }
-                        SampleContractEvents::EtherWithdrawn(e) => {
-                            println!(
-                                "⚡️ EtherWithdrawn - recipient: {}, amount: {}, remainingBalance: {} [{}]",
-                                e.recipient, e.amount, e.remainingBalance, log.address()
-                            );
-                        }
+        loop {
+            let result = tokio::select! {
+                _ = shutdown.requested() => {
+                    let metrics = events.metrics();
+                    println!(
+                        "📥 Event queue: {} waiting, {} block(s) behind, {} dropped",
+                        metrics.depth, metrics.lag_blocks, metrics.dropped
+                    );
+                    return Ok(());
+                }
+                result = events.recv() => result,
+            };
+            match result {
+                Some(Ok(StreamEvent::Observed(log))) => {
+                    for error in handlers.dispatch(log).await {
+                        eprintln!("⚠️ {}", error);
                     }
                 }
-                Err(_) => {
-                    eprintln!("⚠️ Unknown event received.")
-                    // Handle error ...
+                Some(Ok(StreamEvent::Retracted(log))) => {
+                    // Undo whatever was done for the observed event, so that it no longer counts
+                    retract(&progress, &log.data);
+                    println!(
+                        "↩️ Retracted      - block {} ({}), log index {} was reorganized away",
+                        log.block_number, log.block_hash, log.log_index
+                    );
+                }
+                Some(Ok(StreamEvent::Finalized(log))) => {
+                    println!(
+                        "✅ Finalized      - block {} ({}), log index {}",
+                        log.block_number, log.block_hash, log.log_index
+                    );
+                    // The event can no longer be retracted, so a restart resumes after it
+                    if let Err(e) = checkpoints.save(&subscription, Checkpoint::of(&log)) {
+                        eprintln!("⚠️ {}", e);
+                    }
                 }
+                // Only raised once reconnecting (or retrying to poll) failed for good
+                Some(Err(e)) => bail!("error processing events: {}", e),
+                None => return Ok(()),
             }
         }
     });
 
     // Send Transactions
 
-    for i in 0..2 {
+    for i in 0..ROUNDS {
         // 1. Set the contract value to (i + 1) to trigger the ValueChanged event
         println!("🔄 Sending transaction to set new value.");
         let new_value = U256::from(i + 2);
@@ -205,9 +326,34 @@ async fn main() -> Result<()> {
         contract = SampleContract::new(contract_address, provider.clone());
     }
 
-    // Keep the main function alive until all expected events are processed
-    println!("⏳ All transactions sent. Waiting for events. Press Enter to exit.");
-    stdin().read_line(&mut String::new())?;
+    // Wait until all expected events are processed (or Ctrl+C), then drain the listeners
+    println!("⏳ All transactions sent. Waiting for events. Press Ctrl+C to exit.");
+    let summary = supervisor.run().await;
+
+    println!("🏁 Listeners stopped: {}.", summary.reason);
+    for (event, count) in &summary.events {
+        println!("📊 {}: {} event(s)", event, count);
+    }
+    for (task, outcome) in &summary.tasks {
+        match outcome {
+            TaskOutcome::Finished => println!("🧵 {}: finished", task),
+            TaskOutcome::Failed(error) => println!("🧵 {}: failed ({})", task, error),
+            TaskOutcome::Aborted => println!("🧵 {}: aborted while draining", task),
+        }
+    }
+    if summary.reason == StopReason::TasksExited {
+        bail!("listeners exited before all events were processed");
+    }
 
     Ok(())
 }
+
+/// Takes back the progress recorded by the handler of a retracted event.
+fn retract(progress: &Progress, event: &RawEvent) {
+    match event.0.topics().first() {
+        Some(topic) if *topic == ValueChanged::SIGNATURE_HASH => progress.retract::<ValueChanged>(),
+        Some(topic) if *topic == EtherReceived::SIGNATURE_HASH => progress.retract::<EtherReceived>(),
+        Some(topic) if *topic == EtherWithdrawn::SIGNATURE_HASH => progress.retract::<EtherWithdrawn>(),
+        _ => {}
+    }
+}
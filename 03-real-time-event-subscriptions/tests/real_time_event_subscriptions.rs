@@ -0,0 +1,97 @@
+use std::process::Stdio;
+use std::time::Duration;
+use alloy_in_action_common::anvil::spawn_for_test;
+use alloy_in_action_common::{http_provider, Anvil, AnvilInstance, SampleContract};
+use alloy_primitives::U256;
+use alloy_provider::Provider;
+use alloy_rpc_types::Filter;
+use alloy_sol_types::SolEvent;
+use eyre::Result;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use SampleContract::ValueChanged;
+
+/// Prefixes of the lines printed for each received event, with the expected counts: two rounds
+/// of setValue, deposit and withdraw, seen by the ValueChanged and the combined subscription.
+const EXPECTED_EVENTS: [(&str, usize); 4] = [
+    ("⚡️ |ValueChanged|", 2),
+    ("⚡️ ValueChanged   -", 2),
+    ("⚡️ EtherReceived  -", 2),
+    ("⚡️ EtherWithdrawn -", 2),
+];
+
+#[tokio::test]
+async fn streams_events_from_both_signers() -> Result<()> {
+    let Some(anvil) = spawn_for_test(Anvil::new()) else { return Ok(()) };
+
+    // Run the example against the node
+    let command = anvil.command(env!("CARGO_BIN_EXE_real-time-event-subscriptions"));
+    run_example(&anvil, command).await
+}
+
+#[tokio::test]
+async fn falls_back_to_polling_without_websocket() -> Result<()> {
+    let Some(anvil) = spawn_for_test(Anvil::new()) else { return Ok(()) };
+
+    // Point the WebSocket endpoint at a closed port, so the example polls over HTTP
+    let mut command = std::process::Command::new(env!("CARGO_BIN_EXE_real-time-event-subscriptions"));
+    for pair in anvil.args().chunks(2) {
+        match pair {
+            [flag, _] if flag == "--ws-url" => command.args([flag.as_str(), "ws://127.0.0.1:9"]),
+            _ => command.args(pair),
+        };
+    }
+    command.args(["--poll-interval-ms", "100"]);
+    run_example(&anvil, command).await
+}
+
+/// Runs the example until it printed all expected events and exited on its own, then checks
+/// the contract's state.
+async fn run_example(anvil: &AnvilInstance, command: std::process::Command) -> Result<()> {
+    let mut command = tokio::process::Command::from(command);
+    let mut child = command.stdout(Stdio::piped()).kill_on_drop(true).spawn()?;
+
+    // Count the printed events until the example exits after the last expected one
+    let mut lines = BufReader::new(child.stdout.take().expect("piped stdout")).lines();
+    let mut counts = [0; EXPECTED_EVENTS.len()];
+    let mut summary = None;
+    tokio::time::timeout(Duration::from_secs(60), async {
+        while let Some(line) = lines.next_line().await? {
+            for (count, (prefix, _)) in counts.iter_mut().zip(EXPECTED_EVENTS) {
+                if line.starts_with(prefix) {
+                    *count += 1;
+                }
+            }
+            if line.starts_with("🏁") {
+                summary = Some(line);
+            }
+        }
+        eyre::Ok(())
+    })
+    .await??;
+    assert!(child.wait().await?.success());
+    assert_eq!(summary.as_deref(), Some("🏁 Listeners stopped: completed."));
+    for (count, (prefix, expected)) in counts.iter().zip(EXPECTED_EVENTS) {
+        assert_eq!(*count, expected, "{prefix}");
+    }
+
+    // Both signers updated the value of the signer's first deployment
+    let config = anvil.config()?;
+    let provider = http_provider(&config);
+    let contract = SampleContract::new(config.signer.address().create(0), &provider);
+    assert_eq!(contract.getValue().call().await?.currentValue, U256::from(3));
+    assert_eq!(contract.getBalance().call().await?.balance, U256::ZERO);
+
+    let filter = Filter::new()
+        .address(*contract.address())
+        .event_signature(ValueChanged::SIGNATURE_HASH)
+        .from_block(0);
+    let updaters = provider
+        .get_logs(&filter)
+        .await?
+        .iter()
+        .map(|log| Ok(ValueChanged::decode_log_data(log.data(), true)?.updater))
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(updaters, [config.signer.address(), config.secondary_signer()?.address()]);
+
+    Ok(())
+}
@@ -0,0 +1,339 @@
+//! Local Anvil node for integration tests.
+//!
+//! [`Anvil`] spawns the `anvil` binary from Foundry on a random port and [`AnvilInstance`]
+//! exposes its endpoints, chain ID and dev accounts as a [`Config`] (or as [`ConfigOverrides`]
+//! to pass to an example binary as command-line flags). The node is killed when the instance
+//! is dropped.
+//!
+//! Integration tests get a node, a provider for its first dev account and a deployed
+//! `SampleContract` from a [`TestNode`], which skips the test with a notice when `anvil` is
+//! not installed:
+//!
+//! ```ignore
+//! let Some(node) = TestNode::ws(Anvil::new()).await? else { return Ok(()) };
+//! let contract = node.deploy().await?;
+//! ```
+
+use std::ffi::OsStr;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use alloy_network::Ethereum;
+use alloy_primitives::U256;
+use alloy_provider::Provider;
+use alloy_transport::Transport;
+use thiserror::Error;
+use crate::bindings::SampleContract::{self, SampleContractInstance};
+use crate::config::{Config, ConfigError, ConfigFile, ConfigOverrides};
+use crate::provider::{http_provider, ws_provider, HttpProvider, WsProvider};
+
+/// Environment variable pointing to the `anvil` binary, `anvil` on the `PATH` by default.
+pub const ANVIL_BIN_ENV: &str = "ANVIL_BIN";
+
+/// Environment variable that turns a missing `anvil` binary into a test failure, e.g. in CI.
+pub const REQUIRE_ANVIL_ENV: &str = "ALLOY_REQUIRE_ANVIL";
+
+/// Chain ID Anvil uses unless configured otherwise.
+pub const DEFAULT_CHAIN_ID: u64 = 31337;
+
+/// How long to wait for Anvil to start listening by default.
+pub const DEFAULT_STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Errors raised while spawning Anvil.
+#[derive(Debug, Error)]
+pub enum AnvilError {
+    /// The `anvil` binary cannot be found.
+    #[error("`{program}` not found: install Foundry (https://getfoundry.sh) or set {ANVIL_BIN_ENV}")]
+    NotInstalled { program: PathBuf },
+    /// The process cannot be started.
+    #[error("failed to spawn `{program}`: {source}")]
+    Spawn {
+        program: PathBuf,
+        source: std::io::Error,
+    },
+    /// The process exited before it started listening.
+    #[error("anvil exited before listening")]
+    Exited,
+    /// The process did not start listening in time.
+    #[error("anvil did not start listening within {0:?}")]
+    Timeout(Duration),
+}
+
+/// Builder for a local Anvil node.
+#[derive(Clone, Debug)]
+pub struct Anvil {
+    program: PathBuf,
+    chain_id: u64,
+    block_time: Option<u64>,
+    args: Vec<String>,
+    timeout: Duration,
+}
+
+impl Default for Anvil {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Anvil {
+    /// Creates a builder for `$ANVIL_BIN` (or `anvil`) with the default chain ID and instant mining.
+    pub fn new() -> Self {
+        Self {
+            program: std::env::var_os(ANVIL_BIN_ENV).map(PathBuf::from).unwrap_or_else(|| "anvil".into()),
+            chain_id: DEFAULT_CHAIN_ID,
+            block_time: None,
+            args: Vec::new(),
+            timeout: DEFAULT_STARTUP_TIMEOUT,
+        }
+    }
+
+    /// Sets the chain ID of the node.
+    pub fn chain_id(mut self, chain_id: u64) -> Self {
+        self.chain_id = chain_id;
+        self
+    }
+
+    /// Mines a block every `seconds` seconds in addition to mining on each transaction, needed
+    /// when waiting for more than one confirmation.
+    pub fn block_time(mut self, seconds: u64) -> Self {
+        self.block_time = Some(seconds);
+        self
+    }
+
+    /// Passes an extra command-line argument to `anvil`.
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Sets how long to wait for the node to start listening.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Starts the node on a random port and waits until it accepts connections.
+    pub fn spawn(self) -> Result<AnvilInstance, AnvilError> {
+        let mut command = Command::new(&self.program);
+        command
+            .args(["--host", "127.0.0.1", "--port", "0"])
+            .args(["--chain-id", &self.chain_id.to_string()])
+            .args(&self.args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null());
+        if let Some(block_time) = self.block_time {
+            command.args(["--block-time", &block_time.to_string()]);
+        }
+
+        let mut child = command.spawn().map_err(|source| match source.kind() {
+            std::io::ErrorKind::NotFound => AnvilError::NotInstalled { program: self.program.clone() },
+            _ => AnvilError::Spawn { program: self.program.clone(), source },
+        })?;
+
+        // Read the banner on a separate thread, which keeps draining stdout afterwards so that
+        // Anvil never blocks on a full pipe
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let (lines_tx, lines_rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                let _ = lines_tx.send(line);
+            }
+        });
+
+        let deadline = Instant::now() + self.timeout;
+        let mut private_keys = Vec::new();
+        let mut in_private_keys = false;
+        let port = loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let line = match lines_rx.recv_timeout(remaining) {
+                Ok(line) => line,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    let _ = child.kill();
+                    return Err(AnvilError::Timeout(self.timeout));
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    let _ = child.wait();
+                    return Err(AnvilError::Exited);
+                }
+            };
+
+            // The dev accounts' keys are listed as `(0) 0xac09...` under "Private Keys"
+            if line.starts_with("Private Keys") {
+                in_private_keys = true;
+            } else if line.starts_with("Wallet") {
+                in_private_keys = false;
+            } else if in_private_keys {
+                if let Some((_, key)) = line.split_once(") ") {
+                    private_keys.push(key.trim().to_string());
+                }
+            }
+
+            if let Some(address) = line.strip_prefix("Listening on ") {
+                if let Some(port) = address.trim().rsplit(':').next().and_then(|port| port.parse().ok()) {
+                    break port;
+                }
+            }
+        };
+
+        Ok(AnvilInstance { child, port, chain_id: self.chain_id, private_keys })
+    }
+}
+
+/// A running Anvil node, killed on drop.
+#[derive(Debug)]
+pub struct AnvilInstance {
+    child: Child,
+    port: u16,
+    chain_id: u64,
+    private_keys: Vec<String>,
+}
+
+impl AnvilInstance {
+    /// Port the node listens on.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// HTTP RPC endpoint.
+    pub fn endpoint(&self) -> String {
+        format!("http://127.0.0.1:{}", self.port)
+    }
+
+    /// WebSocket RPC endpoint.
+    pub fn ws_endpoint(&self) -> String {
+        format!("ws://127.0.0.1:{}", self.port)
+    }
+
+    /// Chain ID of the node.
+    pub fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    /// Hex-encoded private keys of the funded dev accounts.
+    pub fn private_keys(&self) -> &[String] {
+        &self.private_keys
+    }
+
+    /// Command-line overrides selecting this node, its first dev account as the primary signer
+    /// and its second one as the secondary signer.
+    pub fn overrides(&self) -> ConfigOverrides {
+        ConfigOverrides {
+            private_key: self.private_keys.first().cloned(),
+            secondary_private_key: self.private_keys.get(1).cloned(),
+            rpc_url: Some(self.endpoint()),
+            ws_url: Some(self.ws_endpoint()),
+            chain_id: Some(self.chain_id.to_string()),
+            ..ConfigOverrides::default()
+        }
+    }
+
+    /// [`Self::overrides`] as command-line arguments for an example binary.
+    pub fn args(&self) -> Vec<String> {
+        let overrides = self.overrides();
+        [
+            ("--private-key", overrides.private_key),
+            ("--secondary-private-key", overrides.secondary_private_key),
+            ("--rpc-url", overrides.rpc_url),
+            ("--ws-url", overrides.ws_url),
+            ("--chain-id", overrides.chain_id),
+            ("--poll-interval-ms", overrides.poll_interval_ms),
+        ]
+        .into_iter()
+        .filter_map(|(flag, value)| Some([flag.to_string(), value?]))
+        .flatten()
+        .collect()
+    }
+
+    /// Command running `program` (e.g. an example binary) against this node.
+    pub fn command(&self, program: impl AsRef<OsStr>) -> Command {
+        let mut command = Command::new(program);
+        command.args(self.args());
+        command
+    }
+
+    /// Configuration for this node, independent of the configuration file and environment.
+    pub fn config(&self) -> Result<Config, ConfigError> {
+        Config::resolve(&ConfigFile::default(), &PathBuf::new(), |_| None, &self.overrides())
+    }
+}
+
+impl Drop for AnvilInstance {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Spawns `anvil` for an integration test.
+///
+/// Returns `None` after printing a notice if the `anvil` binary is not installed, so that the
+/// test can skip itself, unless `$ALLOY_REQUIRE_ANVIL` is set.
+///
+/// # Panics
+///
+/// Panics if Anvil is installed but fails to start, or is missing while required.
+pub fn spawn_for_test(anvil: Anvil) -> Option<AnvilInstance> {
+    match anvil.spawn() {
+        Ok(instance) => Some(instance),
+        Err(error @ AnvilError::NotInstalled { .. }) if std::env::var_os(REQUIRE_ANVIL_ENV).is_none() => {
+            // Tests run on a thread named after them
+            let thread = std::thread::current();
+            eprintln!("skipping {}: {error}", thread.name().unwrap_or("test"));
+            None
+        }
+        Err(error) => panic!("failed to spawn anvil: {error}"),
+    }
+}
+
+/// A local Anvil node for an integration test, with a provider for its first dev account.
+#[derive(Debug)]
+pub struct TestNode<P> {
+    /// The node, killed when the test node is dropped.
+    pub anvil: AnvilInstance,
+    /// Configuration selecting the node.
+    pub config: Config,
+    /// Provider signing with the configured signer.
+    pub provider: P,
+}
+
+impl TestNode<HttpProvider> {
+    /// Spawns `anvil` with [`spawn_for_test`] and connects to it over HTTP.
+    ///
+    /// # Returns
+    ///
+    /// * `eyre::Result<Option<Self>>` - The node, or `None` if the test is skipped.
+    pub fn http(anvil: Anvil) -> eyre::Result<Option<Self>> {
+        let Some(anvil) = spawn_for_test(anvil) else { return Ok(None) };
+        let config = anvil.config()?;
+        let provider = http_provider(&config);
+        Ok(Some(Self { anvil, config, provider }))
+    }
+}
+
+impl TestNode<WsProvider> {
+    /// Spawns `anvil` with [`spawn_for_test`] and connects to it over WebSocket.
+    ///
+    /// # Returns
+    ///
+    /// * `eyre::Result<Option<Self>>` - The node, or `None` if the test is skipped.
+    pub async fn ws(anvil: Anvil) -> eyre::Result<Option<Self>> {
+        let Some(anvil) = spawn_for_test(anvil) else { return Ok(None) };
+        let config = anvil.config()?;
+        let provider = ws_provider(&config).await?;
+        Ok(Some(Self { anvil, config, provider }))
+    }
+}
+
+impl<P> TestNode<P> {
+    /// Deploys a `SampleContract` with an initial value of 1.
+    pub async fn deploy<T>(&self) -> eyre::Result<SampleContractInstance<T, P, Ethereum>>
+    where
+        P: Provider<T, Ethereum> + Clone,
+        T: Transport + Clone,
+    {
+        Ok(SampleContract::deploy(self.provider.clone(), U256::from(1)).await?)
+    }
+}
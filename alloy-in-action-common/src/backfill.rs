@@ -0,0 +1,134 @@
+//! Paging through historical logs.
+//!
+//! Nodes and RPC providers cap `eth_getLogs` by block range or by number of results, and reject
+//! larger queries with provider specific messages. [`LogPager`] fetches a block range in pages,
+//! halving the page whenever the node rejects it as too large and growing it back after
+//! successful pages.
+
+use alloy_network::Ethereum;
+use alloy_provider::Provider;
+use alloy_rpc_types::{Filter, Log};
+use alloy_transport::{Transport, TransportError, TransportResult};
+
+/// Number of blocks queried per page by default.
+pub const DEFAULT_PAGE_SIZE: u64 = 2_000;
+
+/// Error messages (lowercase) with which nodes and RPC providers reject `eth_getLogs` queries
+/// that are too large, e.g. `query returned more than 10000 results` (code -32005).
+const RANGE_ERRORS: [&str; 9] = [
+    "query returned more than",
+    "too many results",
+    "log response size exceeded",
+    "response size should not",
+    "exceed maximum block range",
+    "block range is too large",
+    "block range too large",
+    "eth_getlogs is limited to",
+    "eth_getlogs and eth_newfilter are limited to",
+];
+
+/// Error messages (lowercase) of rate limits, which can mention block ranges and limits without
+/// the query being too large.
+const RATE_LIMIT_ERRORS: [&str; 4] = ["rate limit", "request rate", "too many requests", "daily request count"];
+
+/// Error codes of rate limits.
+const RATE_LIMIT_CODES: [i64; 2] = [429, -32029];
+
+/// Fetches logs in pages of adaptive block ranges.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogPager {
+    page_size: u64,
+    max_page_size: u64,
+}
+
+impl Default for LogPager {
+    fn default() -> Self {
+        Self::new(DEFAULT_PAGE_SIZE)
+    }
+}
+
+impl LogPager {
+    /// Creates a pager starting with (and growing back to at most) `page_size` blocks per page.
+    pub fn new(page_size: u64) -> Self {
+        let page_size = page_size.max(1);
+        Self { page_size, max_page_size: page_size }
+    }
+
+    /// Number of blocks the next page covers at most.
+    pub fn page_size(&self) -> u64 {
+        self.page_size
+    }
+
+    /// Fetches the logs matching `filter` of the first page of blocks `from..=to`, shrinking the
+    /// page until the node accepts it.
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - Provider to query.
+    /// * `filter` - Filter whose block range is replaced by the page.
+    /// * `from` - First block of the remaining range.
+    /// * `to` - Last block of the remaining range.
+    ///
+    /// # Returns
+    ///
+    /// * `TransportResult<(u64, Vec<Log>)>` - The last block of the fetched page and its logs, or
+    ///   the node's error if even a single block is too large or the query failed otherwise.
+    pub async fn next_page<P, T>(&mut self, provider: &P, filter: &Filter, from: u64, to: u64) -> TransportResult<(u64, Vec<Log>)>
+    where
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+    {
+        loop {
+            let end = from.saturating_add(self.page_size - 1).min(to);
+            let page = filter.clone().from_block(from).to_block(end);
+            match provider.get_logs(&page).await {
+                Ok(logs) => {
+                    self.page_size = self.page_size.saturating_mul(2).min(self.max_page_size);
+                    return Ok((end, logs));
+                }
+                Err(error) if error.is_range_error() && end > from => {
+                    // Make sure the next attempt covers fewer blocks than the rejected one
+                    self.page_size = (end - from).div_ceil(2);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    /// Fetches all logs matching `filter` in blocks `from..=to`, page by page.
+    pub async fn get_logs<P, T>(&mut self, provider: &P, filter: &Filter, from: u64, to: u64) -> TransportResult<Vec<Log>>
+    where
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+    {
+        let mut logs = Vec::new();
+        let mut from = from;
+        while from <= to {
+            let (end, page) = self.next_page(provider, filter, from, to).await?;
+            logs.extend(page);
+            if end == to {
+                break;
+            }
+            from = end + 1;
+        }
+        Ok(logs)
+    }
+}
+
+/// Detects `eth_getLogs` queries rejected for covering too many blocks or results.
+pub trait RangeErrorExt {
+    /// Returns `true` if the node rejected the query because its block range or result set is
+    /// too large.
+    fn is_range_error(&self) -> bool;
+}
+
+impl RangeErrorExt for TransportError {
+    fn is_range_error(&self) -> bool {
+        self.as_error_resp().is_some_and(|payload| {
+            let message = payload.message.to_ascii_lowercase();
+            let rate_limited = RATE_LIMIT_CODES.contains(&payload.code)
+                || RATE_LIMIT_ERRORS.iter().any(|error| message.contains(error));
+            !rate_limited && RANGE_ERRORS.iter().any(|error| message.contains(error))
+        })
+    }
+}
@@ -0,0 +1,26 @@
+//! Rust bindings for the `SampleContract` used throughout the examples.
+//!
+//! The bindings, deployment bytecode and deployed bytecode are generated from the Foundry
+//! artifact of `solidity-smart-contracts/src/SampleContract.sol`. After changing the contract,
+//! run `forge build` in `solidity-smart-contracts` and commit the updated artifact; the
+//! `artifact` test fails while the checked-in ABI and the Solidity source disagree.
+
+use alloy_sol_macro::sol;
+use alloy_sol_types::sol_data;
+use crate::event_filters;
+use SampleContract::{EtherReceived, EtherWithdrawn, ValueChanged};
+
+sol!(
+    // Derive `Debug`, `PartialEq`, `Eq` and `Hash` for the generated events and errors
+    #![sol(all_derives)]
+    #[sol(rpc)]
+    SampleContract,
+    "../solidity-smart-contracts/out/SampleContract.sol/SampleContract.json"
+);
+
+// Typed filters on the indexed arguments of the events, e.g. `ValueChanged::filter().updater(address)`
+event_filters! {
+    ValueChanged { updater: sol_data::Address, oldValue: sol_data::Uint<256> }
+    EtherReceived { sender: sol_data::Address }
+    EtherWithdrawn { recipient: sol_data::Address }
+}
@@ -0,0 +1,236 @@
+//! Persistent checkpoints for event consumers.
+//!
+//! A consumer records a [`Checkpoint`] (block number and log index) after it fully processed an
+//! event, typically a [`StreamEvent::Finalized`](crate::StreamEvent::Finalized) one, in a
+//! [`CheckpointStore`] under the name of its subscription. After a restart it loads the
+//! checkpoint and resumes the stream right after it with
+//! [`EventStream::resume_after`](crate::EventStream::resume_after).
+//!
+//! Events processed but not yet checkpointed when the process stopped are delivered again, so
+//! handlers see every event at least once and should deduplicate with
+//! [`EventLog::idempotency_key`](crate::EventLog::idempotency_key).
+//!
+//! Two backends are provided: [`JsonCheckpointStore`] keeps all subscriptions in one JSON file,
+//! `SqliteCheckpointStore` (with the `sqlite` feature) in a `checkpoints` table of a SQLite
+//! database.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+#[cfg(feature = "sqlite")]
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use crate::event_stream::EventLog;
+
+/// Position of the last fully processed event of a subscription.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Number of the block containing the event.
+    pub block_number: u64,
+    /// Index of the event's log in its block.
+    pub log_index: u64,
+}
+
+impl Checkpoint {
+    /// Creates a checkpoint at the given event position.
+    pub fn new(block_number: u64, log_index: u64) -> Self {
+        Self { block_number, log_index }
+    }
+
+    /// Creates a checkpoint at the position of `log`.
+    pub fn of<E>(log: &EventLog<E>) -> Self {
+        Self::new(log.block_number, log.log_index)
+    }
+
+    /// Returns `true` if `log` is at or before the checkpoint, i.e. was already processed.
+    pub fn covers<E>(&self, log: &EventLog<E>) -> bool {
+        Self::of(log) <= *self
+    }
+}
+
+impl fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "block {}, log index {}", self.block_number, self.log_index)
+    }
+}
+
+/// Errors raised by a [`CheckpointStore`].
+#[derive(Debug, Error)]
+pub enum CheckpointError {
+    /// Reading or writing the checkpoint file failed.
+    #[error("failed to access checkpoint file {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// The checkpoint file is not valid JSON or has an unexpected shape.
+    #[error("failed to parse checkpoint file {path}: {source}")]
+    Json {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    /// Querying the checkpoint database failed.
+    #[cfg(feature = "sqlite")]
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+}
+
+/// Stores the last checkpoint per subscription name.
+pub trait CheckpointStore {
+    /// Returns the checkpoint of `subscription`, or `None` if it has none yet.
+    fn load(&self, subscription: &str) -> Result<Option<Checkpoint>, CheckpointError>;
+
+    /// Durably replaces the checkpoint of `subscription`.
+    fn save(&self, subscription: &str, checkpoint: Checkpoint) -> Result<(), CheckpointError>;
+}
+
+/// Keeps the checkpoints of all subscriptions in one JSON file.
+///
+/// The file is rewritten on every save, through a temporary file that is flushed to disk and
+/// renamed over it, so a crash never leaves it half written.
+#[derive(Debug)]
+pub struct JsonCheckpointStore {
+    path: PathBuf,
+    /// Serializes read-modify-write cycles of the file.
+    lock: Mutex<()>,
+}
+
+impl JsonCheckpointStore {
+    /// Uses the JSON file at `path`, which is created on the first save.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), lock: Mutex::new(()) }
+    }
+
+    /// Path of the checkpoint file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn read(&self) -> Result<BTreeMap<String, Checkpoint>, CheckpointError> {
+        let contents = match std::fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(source) => return Err(CheckpointError::Io { path: self.path.clone(), source }),
+        };
+        serde_json::from_str(&contents).map_err(|source| CheckpointError::Json { path: self.path.clone(), source })
+    }
+}
+
+impl CheckpointStore for JsonCheckpointStore {
+    fn load(&self, subscription: &str) -> Result<Option<Checkpoint>, CheckpointError> {
+        let _lock = self.lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        Ok(self.read()?.get(subscription).copied())
+    }
+
+    fn save(&self, subscription: &str, checkpoint: Checkpoint) -> Result<(), CheckpointError> {
+        let _lock = self.lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut checkpoints = self.read()?;
+        checkpoints.insert(subscription.to_string(), checkpoint);
+
+        let contents = serde_json::to_string_pretty(&checkpoints)
+            .map_err(|source| CheckpointError::Json { path: self.path.clone(), source })?;
+        let io_error = |source| CheckpointError::Io { path: self.path.clone(), source };
+        let temporary = self.path.with_extension("json.tmp");
+        let mut file = File::create(&temporary).map_err(io_error)?;
+        file.write_all(contents.as_bytes()).and_then(|()| file.sync_all()).map_err(io_error)?;
+        std::fs::rename(&temporary, &self.path).map_err(io_error)?;
+        sync_parent(&self.path).map_err(io_error)
+    }
+}
+
+/// Flushes the directory containing `path` to disk, so that a rename over `path` survives a
+/// crash.
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> std::io::Result<()> {
+    let parent = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    File::open(parent)?.sync_all()
+}
+
+/// Directories cannot be opened, and need not be flushed, on other platforms.
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Keeps the checkpoints in a `checkpoints` table of a SQLite database.
+///
+/// The table can live in the same database as the consumer's own data, so that a checkpoint is
+/// only saved together with the effects of the events it covers.
+#[cfg(feature = "sqlite")]
+#[derive(Debug)]
+pub struct SqliteCheckpointStore {
+    connection: Mutex<Connection>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteCheckpointStore {
+    /// Opens (or creates) the database at `path` and creates the `checkpoints` table if needed.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, CheckpointError> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    /// Uses a private in-memory database, e.g. in tests.
+    pub fn in_memory() -> Result<Self, CheckpointError> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    /// Uses an already open database and creates the `checkpoints` table if needed.
+    pub fn with_connection(connection: Connection) -> Result<Self, CheckpointError> {
+        Self::create_table(&connection)?;
+        Ok(Self { connection: Mutex::new(connection) })
+    }
+
+    /// Creates the `checkpoints` table in `connection` if needed.
+    ///
+    /// Together with [`Self::load_from`] and [`Self::save_to`] this lets a consumer keep the
+    /// table in its own database and save checkpoints in the same transaction as its data.
+    pub fn create_table(connection: &Connection) -> rusqlite::Result<()> {
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS checkpoints (
+                subscription TEXT PRIMARY KEY,
+                block_number INTEGER NOT NULL,
+                log_index INTEGER NOT NULL
+            )",
+            (),
+        )?;
+        Ok(())
+    }
+
+    /// Returns the checkpoint of `subscription` in `connection`'s `checkpoints` table.
+    pub fn load_from(connection: &Connection, subscription: &str) -> rusqlite::Result<Option<Checkpoint>> {
+        connection
+            .query_row(
+                "SELECT block_number, log_index FROM checkpoints WHERE subscription = ?1",
+                params![subscription],
+                |row| Ok(Checkpoint::new(row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+    }
+
+    /// Replaces the checkpoint of `subscription` in `connection`'s `checkpoints` table.
+    pub fn save_to(connection: &Connection, subscription: &str, checkpoint: Checkpoint) -> rusqlite::Result<()> {
+        connection.execute(
+            "INSERT INTO checkpoints (subscription, block_number, log_index) VALUES (?1, ?2, ?3)
+             ON CONFLICT (subscription) DO UPDATE SET block_number = ?2, log_index = ?3",
+            params![subscription, checkpoint.block_number, checkpoint.log_index],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl CheckpointStore for SqliteCheckpointStore {
+    fn load(&self, subscription: &str) -> Result<Option<Checkpoint>, CheckpointError> {
+        let connection = self.connection.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        Ok(Self::load_from(&connection, subscription)?)
+    }
+
+    fn save(&self, subscription: &str, checkpoint: Checkpoint) -> Result<(), CheckpointError> {
+        let connection = self.connection.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        Ok(Self::save_to(&connection, subscription, checkpoint)?)
+    }
+}
@@ -0,0 +1,349 @@
+//! Typed configuration shared by the examples.
+//!
+//! Settings are grouped into named network profiles (e.g. `anvil`, `devnet`). For the selected
+//! profile every value is resolved from the following sources, later ones taking precedence:
+//!
+//! 1. the `[profiles.<name>]` table of the TOML configuration file (`alloy-in-action.toml` at the
+//!    workspace root by default),
+//! 2. `<NAME>_*` environment variables, e.g. `ANVIL_RPC_URL`, including those loaded from the
+//!    root `.env` file,
+//! 3. command-line flags, see [`ConfigOverrides`].
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use alloy_signer_local::PrivateKeySigner;
+#[cfg(feature = "cli")]
+use clap::Parser;
+use serde::Deserialize;
+use thiserror::Error;
+use url::Url;
+
+/// Name of the profile used when none is selected.
+pub const DEFAULT_PROFILE: &str = "anvil";
+
+/// Environment variable selecting the profile.
+pub const PROFILE_ENV: &str = "ALLOY_PROFILE";
+
+/// Environment variable pointing to the TOML configuration file.
+pub const CONFIG_FILE_ENV: &str = "ALLOY_CONFIG";
+
+/// Name of the TOML configuration file looked up at the workspace root.
+pub const CONFIG_FILE_NAME: &str = "alloy-in-action.toml";
+
+/// Errors raised while loading or validating the configuration.
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    /// A required setting is not provided by any source.
+    #[error("missing `{key}` for profile `{profile}`: set {env_key}, `profiles.{profile}.{key}` in the config file or --{flag}")]
+    Missing {
+        profile: String,
+        key: &'static str,
+        env_key: String,
+        flag: String,
+    },
+    /// A setting is present but cannot be parsed.
+    #[error("invalid `{key}` from {origin}: {reason}")]
+    Invalid {
+        key: &'static str,
+        origin: Origin,
+        reason: String,
+    },
+    /// The configuration file cannot be read.
+    #[error("failed to read config file {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// The configuration file is not valid TOML or has an unexpected shape.
+    #[error("failed to parse config file {path}: {source}")]
+    Toml {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+}
+
+/// Where a configuration value was read from, used in validation errors.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Origin {
+    /// `profiles.<profile>.<key>` in the given configuration file.
+    File { path: PathBuf, profile: String },
+    /// The given environment variable.
+    Env(String),
+    /// The given command-line flag.
+    Flag(String),
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::File { path, profile } => write!(f, "`profiles.{profile}` in {}", path.display()),
+            Origin::Env(key) => write!(f, "environment variable {key}"),
+            Origin::Flag(flag) => write!(f, "flag --{flag}"),
+        }
+    }
+}
+
+/// Command-line overrides for the configuration.
+///
+/// With the `cli` feature, can be parsed on its own with `Config::from_cli` or flattened into a
+/// larger `clap` parser.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "cli", derive(Parser))]
+#[cfg_attr(feature = "cli", command(about = None, long_about = None))]
+pub struct ConfigOverrides {
+    /// Network profile to use (defaults to $ALLOY_PROFILE, the config file's `default_profile` or `anvil`)
+    #[cfg_attr(feature = "cli", arg(long, global = true))]
+    pub profile: Option<String>,
+
+    /// Path to the TOML configuration file (defaults to $ALLOY_CONFIG or alloy-in-action.toml)
+    #[cfg_attr(feature = "cli", arg(long, global = true))]
+    pub config: Option<PathBuf>,
+
+    /// Private key of the primary signer
+    #[cfg_attr(feature = "cli", arg(long, global = true))]
+    pub private_key: Option<String>,
+
+    /// Private key of the secondary signer
+    #[cfg_attr(feature = "cli", arg(long, global = true))]
+    pub secondary_private_key: Option<String>,
+
+    /// HTTP RPC endpoint
+    #[cfg_attr(feature = "cli", arg(long, global = true))]
+    pub rpc_url: Option<String>,
+
+    /// WebSocket RPC endpoint
+    #[cfg_attr(feature = "cli", arg(long, global = true))]
+    pub ws_url: Option<String>,
+
+    /// Chain ID of the network
+    #[cfg_attr(feature = "cli", arg(long, global = true))]
+    pub chain_id: Option<String>,
+
+    /// Interval in milliseconds for polling the HTTP endpoint when no WebSocket is available
+    #[cfg_attr(feature = "cli", arg(long, global = true))]
+    pub poll_interval_ms: Option<String>,
+}
+
+/// Contents of the TOML configuration file.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    /// Profile used when none is selected on the command line or in the environment.
+    pub default_profile: Option<String>,
+    /// Network profiles by name.
+    #[serde(default)]
+    pub profiles: HashMap<String, ProfileFile>,
+}
+
+/// A `[profiles.<name>]` table of the configuration file.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileFile {
+    pub private_key: Option<String>,
+    pub secondary_private_key: Option<String>,
+    pub rpc_url: Option<String>,
+    pub ws_url: Option<String>,
+    pub chain_id: Option<u64>,
+    pub poll_interval_ms: Option<u64>,
+}
+
+impl ConfigFile {
+    /// Reads and parses the configuration file at `path`.
+    pub fn read(path: &Path) -> Result<Self, ConfigError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|source| ConfigError::Io { path: path.to_path_buf(), source })?;
+        toml::from_str(&contents)
+            .map_err(|source| ConfigError::Toml { path: path.to_path_buf(), source })
+    }
+}
+
+/// Validated connection and account settings for one network profile.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Name of the selected profile.
+    pub profile: String,
+    /// Primary signer (`<PROFILE>_PRIVATE_KEY`).
+    pub signer: PrivateKeySigner,
+    /// HTTP RPC endpoint (`<PROFILE>_RPC_URL`).
+    pub rpc_url: Url,
+    /// Chain ID of the network (`<PROFILE>_CHAIN_ID`).
+    pub chain_id: u64,
+    /// Secondary signer (`<PROFILE>_SECONDARY_PRIVATE_KEY`), see [`Config::secondary_signer`].
+    secondary_signer: Option<PrivateKeySigner>,
+    /// WebSocket RPC endpoint (`<PROFILE>_WS_URL`), see [`Config::ws_url`].
+    ws_url: Option<Url>,
+    /// Polling interval (`<PROFILE>_POLL_INTERVAL_MS`), see [`Config::poll_interval`].
+    poll_interval: Option<Duration>,
+}
+
+impl Config {
+    /// Loads the root `.env` file and resolves the configuration without command-line overrides.
+    pub fn load() -> Result<Self, ConfigError> {
+        Self::load_with(&ConfigOverrides::default())
+    }
+
+    /// Parses [`ConfigOverrides`] from the process arguments and resolves the configuration.
+    #[cfg(feature = "cli")]
+    pub fn from_cli() -> Result<Self, ConfigError> {
+        Self::load_with(&ConfigOverrides::parse())
+    }
+
+    /// Loads the root `.env` file and the configuration file, then resolves the configuration
+    /// with the given command-line overrides applied.
+    pub fn load_with(overrides: &ConfigOverrides) -> Result<Self, ConfigError> {
+        load_dotenv();
+
+        // An explicitly selected file must exist, the default one is optional
+        let (file, path) = match overrides.config.clone().or_else(|| std::env::var_os(CONFIG_FILE_ENV).map(PathBuf::from)) {
+            Some(path) => (ConfigFile::read(&path)?, path),
+            None => {
+                let path = workspace_root().join(CONFIG_FILE_NAME);
+                let file = if path.exists() { ConfigFile::read(&path)? } else { ConfigFile::default() };
+                (file, path)
+            }
+        };
+
+        Self::resolve(&file, &path, |key| std::env::var(key).ok(), overrides)
+    }
+
+    /// Resolves the configuration from an already parsed file, an environment lookup and
+    /// command-line overrides.
+    ///
+    /// `path` is only used to describe the origin of file values in errors.
+    pub fn resolve(
+        file: &ConfigFile,
+        path: &Path,
+        env: impl Fn(&str) -> Option<String>,
+        overrides: &ConfigOverrides,
+    ) -> Result<Self, ConfigError> {
+        let profile = overrides.profile.clone()
+            .or_else(|| env(PROFILE_ENV))
+            .or_else(|| file.default_profile.clone())
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+
+        let resolver = Resolver {
+            profile: &profile,
+            path,
+            file: file.profiles.get(&profile).cloned().unwrap_or_default(),
+            env: &env,
+        };
+
+        let signer = resolver.required("private_key", |f| f.private_key.clone(), &overrides.private_key, parse_signer)?;
+        let secondary_signer = resolver.optional("secondary_private_key", |f| f.secondary_private_key.clone(), &overrides.secondary_private_key, parse_signer)?;
+        let rpc_url = resolver.required("rpc_url", |f| f.rpc_url.clone(), &overrides.rpc_url, parse_url)?;
+        let ws_url = resolver.optional("ws_url", |f| f.ws_url.clone(), &overrides.ws_url, parse_url)?;
+        let chain_id = resolver.required("chain_id", |f| f.chain_id.map(|id| id.to_string()), &overrides.chain_id, parse_chain_id)?;
+        let poll_interval = resolver.optional("poll_interval_ms", |f| f.poll_interval_ms.map(|ms| ms.to_string()), &overrides.poll_interval_ms, parse_millis)?;
+
+        Ok(Self { profile, signer, rpc_url, chain_id, secondary_signer, ws_url, poll_interval })
+    }
+
+    /// Returns the secondary signer, naming the missing key if the profile does not define one.
+    pub fn secondary_signer(&self) -> Result<&PrivateKeySigner, ConfigError> {
+        self.secondary_signer.as_ref().ok_or_else(|| missing(&self.profile, "secondary_private_key"))
+    }
+
+    /// Returns the WebSocket endpoint, naming the missing key if the profile does not define one.
+    pub fn ws_url(&self) -> Result<&Url, ConfigError> {
+        self.ws_url.as_ref().ok_or_else(|| missing(&self.profile, "ws_url"))
+    }
+
+    /// Returns the interval for polling the HTTP endpoint, if the profile sets one.
+    pub fn poll_interval(&self) -> Option<Duration> {
+        self.poll_interval
+    }
+}
+
+/// Looks up the raw value of a setting in all sources of one profile.
+struct Resolver<'a, E> {
+    profile: &'a str,
+    path: &'a Path,
+    file: ProfileFile,
+    env: &'a E,
+}
+
+impl<E: Fn(&str) -> Option<String>> Resolver<'_, E> {
+    fn optional<T>(
+        &self,
+        key: &'static str,
+        from_file: impl Fn(&ProfileFile) -> Option<String>,
+        from_flag: &Option<String>,
+        parse: impl Fn(&str) -> Result<T, String>,
+    ) -> Result<Option<T>, ConfigError> {
+        let env_key = env_key(self.profile, key);
+        let raw = from_flag.clone().map(|value| (value, Origin::Flag(flag(key))))
+            .or_else(|| (self.env)(&env_key).map(|value| (value, Origin::Env(env_key))))
+            .or_else(|| from_file(&self.file).map(|value| {
+                (value, Origin::File { path: self.path.to_path_buf(), profile: self.profile.to_string() })
+            }));
+
+        match raw {
+            Some((value, origin)) => parse(value.trim())
+                .map(Some)
+                .map_err(|reason| ConfigError::Invalid { key, origin, reason }),
+            None => Ok(None),
+        }
+    }
+
+    fn required<T>(
+        &self,
+        key: &'static str,
+        from_file: impl Fn(&ProfileFile) -> Option<String>,
+        from_flag: &Option<String>,
+        parse: impl Fn(&str) -> Result<T, String>,
+    ) -> Result<T, ConfigError> {
+        self.optional(key, from_file, from_flag, parse)?
+            .ok_or_else(|| missing(self.profile, key))
+    }
+}
+
+/// Loads the `.env` file located at the workspace root, if present.
+///
+/// Variables already present in the environment take precedence over the `.env` file.
+pub fn load_dotenv() {
+    dotenv::from_path(workspace_root().join(".env")).ok();
+}
+
+/// Returns the environment variable name of `key` for `profile`, e.g. `ANVIL_RPC_URL`.
+pub fn env_key(profile: &str, key: &str) -> String {
+    format!("{}_{}", profile.replace('-', "_"), key).to_uppercase()
+}
+
+fn workspace_root() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap()
+}
+
+fn flag(key: &str) -> String {
+    key.replace('_', "-")
+}
+
+fn missing(profile: &str, key: &'static str) -> ConfigError {
+    ConfigError::Missing {
+        profile: profile.to_string(),
+        key,
+        env_key: env_key(profile, key),
+        flag: flag(key),
+    }
+}
+
+fn parse_signer(value: &str) -> Result<PrivateKeySigner, String> {
+    value.parse().map_err(|error| format!("not a valid private key ({error})"))
+}
+
+fn parse_url(value: &str) -> Result<Url, String> {
+    Url::parse(value).map_err(|error| format!("not a valid URL ({error})"))
+}
+
+fn parse_chain_id(value: &str) -> Result<u64, String> {
+    value.parse().map_err(|error| format!("not a valid chain ID ({error})"))
+}
+
+fn parse_millis(value: &str) -> Result<Duration, String> {
+    match value.parse() {
+        Ok(0) => Err("must be at least 1 millisecond".to_string()),
+        Ok(millis) => Ok(Duration::from_millis(millis)),
+        Err(error) => Err(format!("not a valid number of milliseconds ({error})")),
+    }
+}
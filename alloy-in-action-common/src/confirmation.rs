@@ -0,0 +1,269 @@
+//! Reorg-aware tracking of transaction confirmations.
+//!
+//! `watch()` with a fixed number of confirmations trusts a receipt once the chain has grown far
+//! enough past it, even if its block was reorganized away in the meantime. [`ConfirmationTracker`]
+//! follows new heads and re-checks at every head that the receipt's block is still canonical,
+//! reporting each state transition as a [`Confirmation`]. How deep a transaction must be buried
+//! is decided by a [`ConfirmationPolicy`], e.g. more confirmations for higher transferred values.
+
+use std::collections::VecDeque;
+use alloy_network::primitives::BlockTransactionsKind;
+use alloy_network::Ethereum;
+use alloy_primitives::{BlockHash, TxHash, U256};
+use alloy_provider::Provider;
+use alloy_pubsub::SubscriptionStream;
+use alloy_rpc_types::{BlockNumberOrTag, Header, TransactionReceipt, TransactionTrait};
+use alloy_transport::{Transport, TransportError};
+use futures::{Stream, StreamExt};
+use thiserror::Error;
+
+/// A state transition of a tracked transaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Confirmation {
+    /// The transaction was included in a canonical block.
+    Included(Box<TransactionReceipt>),
+    /// The including block is now `n` blocks deep, 1 being the latest block.
+    Confirmed(u64),
+    /// The block that included the transaction is no longer canonical; the transaction is
+    /// pending again until it is included in another block (or dropped).
+    Reorged {
+        /// Hash of the block that is no longer canonical.
+        block_hash: BlockHash,
+        /// Number of that block.
+        block_number: u64,
+    },
+    /// The transaction is neither included in a canonical block nor known to the node.
+    Dropped,
+}
+
+/// Errors raised while tracking confirmations.
+#[derive(Debug, Error)]
+pub enum ConfirmationError {
+    /// Querying the node failed.
+    #[error(transparent)]
+    Transport(#[from] TransportError),
+    /// The transaction was dropped before reaching the required confirmations.
+    #[error("transaction {0:#x} was dropped")]
+    Dropped(TxHash),
+    /// The new heads subscription ended.
+    #[error("new heads subscription closed")]
+    SubscriptionClosed,
+}
+
+/// Number of confirmations required for a transaction, by the value it transfers.
+///
+/// Low value transactions can be trusted after 1-3 confirmations, high value ones should wait
+/// for 6-12:
+///
+/// ```ignore
+/// let policy = ConfirmationPolicy::fixed(2)
+///     .with_tier(parse_ether("1")?, 6)
+///     .with_tier(parse_ether("100")?, 12);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfirmationPolicy {
+    default: u64,
+    /// Minimum value and required confirmations, sorted by value.
+    tiers: Vec<(U256, u64)>,
+}
+
+impl Default for ConfirmationPolicy {
+    fn default() -> Self {
+        Self::fixed(1)
+    }
+}
+
+impl ConfirmationPolicy {
+    /// Requires `confirmations` for every transaction (at least 1).
+    pub fn fixed(confirmations: u64) -> Self {
+        Self { default: confirmations.max(1), tiers: Vec::new() }
+    }
+
+    /// Requires `confirmations` for transactions transferring at least `min_value` wei, unless a
+    /// tier with a higher minimum value applies.
+    pub fn with_tier(mut self, min_value: U256, confirmations: u64) -> Self {
+        self.tiers.push((min_value, confirmations.max(1)));
+        self.tiers.sort_by_key(|(min_value, _)| *min_value);
+        self
+    }
+
+    /// Returns the confirmations required for a transaction transferring `value` wei.
+    pub fn required_confirmations(&self, value: U256) -> u64 {
+        self.tiers
+            .iter()
+            .rev()
+            .find(|(min_value, _)| value >= *min_value)
+            .map_or(self.default, |(_, confirmations)| *confirmations)
+    }
+}
+
+/// Follows new heads and reports the confirmation state of transactions.
+#[derive(Clone, Debug, Default)]
+pub struct ConfirmationTracker {
+    policy: ConfirmationPolicy,
+}
+
+impl ConfirmationTracker {
+    /// Creates a tracker requiring confirmations according to `policy`.
+    pub fn new(policy: ConfirmationPolicy) -> Self {
+        Self { policy }
+    }
+
+    /// Returns the confirmation policy.
+    pub fn policy(&self) -> &ConfirmationPolicy {
+        &self.policy
+    }
+
+    /// Streams the state transitions of `tx_hash` until it reaches the confirmations required by
+    /// the policy (ending with that [`Confirmation::Confirmed`]) or is [`Confirmation::Dropped`].
+    ///
+    /// The provider must support subscriptions (WebSocket or IPC).
+    pub fn track<'a, P, T>(
+        &'a self,
+        provider: &'a P,
+        tx_hash: TxHash,
+    ) -> impl Stream<Item = Result<Confirmation, ConfirmationError>> + 'a
+    where
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+    {
+        let state = TrackState {
+            provider,
+            policy: &self.policy,
+            tx_hash,
+            heads: None,
+            required: 0,
+            included: None,
+            confirmations: 0,
+            transitions: VecDeque::new(),
+            done: false,
+        };
+
+        futures::stream::unfold(state, |mut state| async move {
+            loop {
+                if let Some(transition) = state.transitions.pop_front() {
+                    return Some((Ok(transition), state));
+                }
+                if state.done {
+                    return None;
+                }
+                if let Err(error) = state.advance().await {
+                    state.done = true;
+                    return Some((Err(error), state));
+                }
+            }
+        })
+    }
+
+    /// Waits until `tx_hash` has the confirmations required by the policy and returns its
+    /// receipt, following it through reorgs.
+    pub async fn wait<P, T>(&self, provider: &P, tx_hash: TxHash) -> Result<TransactionReceipt, ConfirmationError>
+    where
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+    {
+        let transitions = self.track(provider, tx_hash);
+        futures::pin_mut!(transitions);
+
+        let mut receipt = None;
+        while let Some(transition) = transitions.next().await {
+            match transition? {
+                Confirmation::Included(included) => receipt = Some(*included),
+                Confirmation::Reorged { .. } => receipt = None,
+                Confirmation::Dropped => return Err(ConfirmationError::Dropped(tx_hash)),
+                Confirmation::Confirmed(_) => {}
+            }
+        }
+        receipt.ok_or(ConfirmationError::SubscriptionClosed)
+    }
+}
+
+/// State of a [`ConfirmationTracker::track`] stream.
+struct TrackState<'a, P> {
+    provider: &'a P,
+    policy: &'a ConfirmationPolicy,
+    tx_hash: TxHash,
+    heads: Option<SubscriptionStream<Header>>,
+    required: u64,
+    /// Hash and number of the canonical block including the transaction.
+    included: Option<(BlockHash, u64)>,
+    confirmations: u64,
+    transitions: VecDeque<Confirmation>,
+    done: bool,
+}
+
+impl<P> TrackState<'_, P> {
+    /// Waits for the next head (subscribing on first use) and queues the resulting transitions.
+    async fn advance<T>(&mut self) -> Result<(), ConfirmationError>
+    where
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+    {
+        let head = match &mut self.heads {
+            Some(heads) => heads.next().await.ok_or(ConfirmationError::SubscriptionClosed)?.number,
+            None => {
+                let Some(tx) = self.provider.get_transaction_by_hash(self.tx_hash).await? else {
+                    self.transitions.push_back(Confirmation::Dropped);
+                    self.done = true;
+                    return Ok(());
+                };
+                self.required = self.policy.required_confirmations(tx.value());
+
+                // Subscribe before reading the current head, so no head is missed
+                self.heads = Some(self.provider.subscribe_blocks().await?.into_stream());
+                self.provider.get_block_number().await?
+            }
+        };
+        self.check(head).await
+    }
+
+    /// Re-checks the receipt against the canonical chain at `head`.
+    async fn check<T>(&mut self, head: u64) -> Result<(), ConfirmationError>
+    where
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+    {
+        let receipt = self.provider.get_transaction_receipt(self.tx_hash).await?;
+        let canonical = match &receipt {
+            Some(TransactionReceipt { block_hash: Some(block_hash), block_number: Some(block_number), .. }) => {
+                let block = self
+                    .provider
+                    .get_block_by_number(BlockNumberOrTag::Number(*block_number), BlockTransactionsKind::Hashes)
+                    .await?;
+                block.is_some_and(|block| block.header.hash == *block_hash).then_some((*block_hash, *block_number))
+            }
+            _ => None,
+        };
+
+        // Leaving the previously including block, for another block or for the mempool
+        if let Some((block_hash, block_number)) = self.included {
+            if canonical.map(|(hash, _)| hash) != Some(block_hash) {
+                self.transitions.push_back(Confirmation::Reorged { block_hash, block_number });
+                self.included = None;
+                self.confirmations = 0;
+            }
+        }
+
+        match (canonical, receipt) {
+            (Some((block_hash, block_number)), Some(receipt)) => {
+                if self.included.is_none() {
+                    self.included = Some((block_hash, block_number));
+                    self.transitions.push_back(Confirmation::Included(Box::new(receipt)));
+                }
+                let depth = head.max(block_number) - block_number + 1;
+                if depth > self.confirmations {
+                    self.confirmations = depth;
+                    self.transitions.push_back(Confirmation::Confirmed(depth));
+                    self.done = depth >= self.required;
+                }
+            }
+            _ => {
+                if self.provider.get_transaction_by_hash(self.tx_hash).await?.is_none() {
+                    self.transitions.push_back(Confirmation::Dropped);
+                    self.done = true;
+                }
+            }
+        }
+        Ok(())
+    }
+}
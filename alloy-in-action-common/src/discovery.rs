@@ -0,0 +1,177 @@
+//! Discovering the deployments a [`ContractWatcher`](crate::ContractWatcher) follows.
+//!
+//! Instead of listing every address up front, new contracts are added to the [`Deployments`]
+//! as they appear on chain, and the watcher picks them up without restarting:
+//!
+//! - [`from_factory_events`] adds the contract announced by each event of a factory, and
+//!   removes it again if the event's block is reorganized away,
+//! - [`DeployDiscovery`] adds the contracts created by transactions of a set of deployers,
+//!   e.g. our signers, from the receipts of new blocks.
+//!
+//! Both are streams of [`Discovery`]s, reporting what they changed.
+
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::time::Duration;
+use alloy_network::Ethereum;
+use alloy_primitives::Address;
+use alloy_provider::Provider;
+use alloy_rpc_types::TransactionReceipt;
+use alloy_transport::Transport;
+use futures::{Stream, StreamExt};
+use crate::event_stream::{EventLog, EventStreamError, StreamEvent, DEFAULT_POLL_INTERVAL};
+use crate::watcher::{Deployment, Deployments};
+
+/// A change made to a set of [`Deployments`] by a discovery.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Discovery {
+    /// The deployment was added.
+    Added(Deployment),
+    /// The deployment was removed, as the event announcing it was reorganized away.
+    Removed(Deployment),
+}
+
+/// Adds the deployments announced by factory events to `deployments`.
+///
+/// A deployment is added when its event is observed, followed from the block of the event, and
+/// removed if the event is retracted, unless it was in the set already.
+///
+/// # Arguments
+///
+/// * `deployments` - The set to add the deployments to.
+/// * `events` - The factory's events, e.g. from [`EventStream::subscribe`](crate::EventStream::subscribe).
+/// * `discover` - Returns the deployment announced by an event, if any.
+///
+/// # Returns
+///
+/// The changes made to the set, and the errors of `events`.
+pub fn from_factory_events<E, S, F>(
+    deployments: Deployments,
+    events: S,
+    mut discover: F,
+) -> impl Stream<Item = Result<Discovery, EventStreamError>>
+where
+    S: Stream<Item = Result<StreamEvent<E>, EventStreamError>>,
+    F: FnMut(&EventLog<E>) -> Option<Deployment>,
+{
+    // Deployments added by events that are not final yet, by idempotency key of the event
+    let mut added: HashMap<String, Deployment> = HashMap::new();
+    events.filter_map(move |result| {
+        let discovery = match result {
+            Ok(StreamEvent::Observed(log)) => discover(&log)
+                .filter(|deployment| {
+                    deployments.add_new(deployment.address, deployment.name.clone(), Some(log.block_number))
+                })
+                .map(|deployment| {
+                    added.insert(log.idempotency_key(), deployment.clone());
+                    Ok(Discovery::Added(deployment))
+                }),
+            Ok(StreamEvent::Retracted(log)) => added
+                .remove(&log.idempotency_key())
+                .filter(|deployment| deployments.remove(&deployment.address))
+                .map(|deployment| Ok(Discovery::Removed(deployment))),
+            Ok(StreamEvent::Finalized(log)) => {
+                added.remove(&log.idempotency_key());
+                None
+            }
+            Err(error) => Some(Err(error)),
+        };
+        futures::future::ready(discovery)
+    })
+}
+
+/// Discovers the contracts created by transactions of a set of deployers.
+#[derive(Clone, Debug)]
+pub struct DeployDiscovery {
+    deployers: BTreeSet<Address>,
+    from_block: Option<u64>,
+    poll_interval: Duration,
+}
+
+impl DeployDiscovery {
+    /// Creates a discovery of the contracts deployed by `deployers`, from the current block on.
+    pub fn new(deployers: impl IntoIterator<Item = Address>) -> Self {
+        Self { deployers: deployers.into_iter().collect(), from_block: None, poll_interval: DEFAULT_POLL_INTERVAL }
+    }
+
+    /// Starts with the deployments of `block` and the blocks after it.
+    pub fn from_block(mut self, block: u64) -> Self {
+        self.from_block = Some(block);
+        self
+    }
+
+    /// Sets how often new blocks are polled for.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Returns the deployment created by `receipt`, if it is a successful deployment of one of
+    /// the deployers.
+    ///
+    /// It is named after the deployer and the block, as in `<deployer> in block 12`.
+    pub fn deployment(&self, receipt: &TransactionReceipt) -> Option<Deployment> {
+        if receipt.to.is_some() || !receipt.status() || !self.deployers.contains(&receipt.from) {
+            return None;
+        }
+        Some(Deployment {
+            address: receipt.contract_address?,
+            name: format!("{:#x} in block {}", receipt.from, receipt.block_number.unwrap_or_default()),
+        })
+    }
+
+    /// Polls for new blocks and adds the contracts deployed in them to `deployments`, followed
+    /// from the block they were deployed in, using `eth_getBlockReceipts`.
+    ///
+    /// # Returns
+    ///
+    /// The added deployments. Failed queries are reported as errors and retried at the next
+    /// poll.
+    pub fn discover<P, T>(self, provider: P, deployments: Deployments) -> impl Stream<Item = Result<Discovery, EventStreamError>>
+    where
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+    {
+        let state = (self, provider, deployments, VecDeque::new(), true);
+        futures::stream::unfold(state, |(mut discovery, provider, deployments, mut pending, mut first)| async move {
+            loop {
+                if let Some(item) = pending.pop_front() {
+                    return Some((item, (discovery, provider, deployments, pending, first)));
+                }
+                if !first {
+                    tokio::time::sleep(discovery.poll_interval).await;
+                }
+                first = false;
+                if let Err(error) = discovery.poll(&provider, &deployments, &mut pending).await {
+                    pending.push_back(Err(error));
+                }
+            }
+        })
+    }
+
+    /// Adds the deployments of the blocks up to the current one.
+    async fn poll<P, T>(
+        &mut self,
+        provider: &P,
+        deployments: &Deployments,
+        pending: &mut VecDeque<Result<Discovery, EventStreamError>>,
+    ) -> Result<(), EventStreamError>
+    where
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+    {
+        let head = provider.get_block_number().await?;
+        let from_block = *self.from_block.get_or_insert(head);
+        for block_number in from_block..=head {
+            // The node may not have the receipts of the block yet, retry at the next poll
+            let Some(receipts) = provider.get_block_receipts(block_number.into()).await? else { return Ok(()) };
+            for deployment in receipts.iter().filter_map(|receipt| self.deployment(receipt)) {
+                if deployments.add_new(deployment.address, deployment.name.clone(), Some(block_number)) {
+                    pending.push_back(Ok(Discovery::Added(deployment)));
+                }
+            }
+            // Only move on once the block is done, so that a failed query is retried
+            self.from_block = Some(block_number + 1);
+        }
+        Ok(())
+    }
+}
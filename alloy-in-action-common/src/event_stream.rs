@@ -0,0 +1,858 @@
+//! Reorg-safe streams of contract events.
+//!
+//! A raw `subscribe_logs` stream delivers logs of blocks that may later be reorganized away,
+//! flagging some of them as `removed` and silently replacing others. [`EventStream`] decodes
+//! the logs into contract events and runs them through a [`ReorgBuffer`], which tracks the
+//! block hash of every event that is not final yet:
+//!
+//! - [`StreamEvent::Observed`] is emitted when an event is first seen in a block,
+//! - [`StreamEvent::Retracted`] when that block is no longer canonical (a `removed` log, a log
+//!   at the same height with another block hash, or a head that no longer builds on it),
+//! - [`StreamEvent::Finalized`] once the block is buried the configured depth deep.
+//!
+//! Consumers can apply `Observed` events tentatively and undo them on `Retracted`, or only act
+//! on `Finalized` events; either way no event is counted twice.
+//!
+//! Logs of blocks the stream did not see (a head arriving more than one block after the last
+//! one, e.g. after the transport silently reconnected) are backfilled with `eth_getLogs`.
+//! [`EventStream::subscribe_with_reconnect`] additionally connects again when the subscriptions
+//! end, and backfills from the last block it processed.
+//!
+//! With [`EventStream::from_block`], the stream starts with the historical events from that
+//! block on, paged through with a [`LogPager`], and then switches to the live subscriptions.
+//! [`EventStream::resume_after`] starts right after a saved [`Checkpoint`] instead.
+//!
+//! Endpoints without subscriptions (plain HTTP) are followed with [`EventStream::poll`], which
+//! polls for new blocks and fetches their logs with `eth_getLogs`.
+
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error as StdError;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use alloy_network::primitives::BlockTransactionsKind;
+use alloy_network::Ethereum;
+use alloy_primitives::{Address, BlockHash, TxHash};
+use alloy_provider::Provider;
+use alloy_rpc_types::{BlockNumberOrTag, Filter, Header, Log};
+use alloy_sol_types::SolEventInterface;
+use alloy_transport::{Transport, TransportError};
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt};
+use thiserror::Error;
+use crate::backfill::{LogPager, DEFAULT_PAGE_SIZE};
+use crate::checkpoint::Checkpoint;
+use crate::reconnect::ReconnectPolicy;
+
+/// Number of blocks an event must be buried under before it is final by default.
+pub const DEFAULT_FINALITY_DEPTH: u64 = 12;
+
+/// How often [`EventStream::poll`] polls for new blocks by default.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A decoded event together with the position of its log in the chain.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EventLog<E> {
+    /// Number of the block containing the log.
+    pub block_number: u64,
+    /// Hash of the block containing the log.
+    pub block_hash: BlockHash,
+    /// Hash of the transaction that emitted the log.
+    pub transaction_hash: TxHash,
+    /// Index of the log in its block.
+    pub log_index: u64,
+    /// Address of the contract that emitted the log.
+    pub address: Address,
+    /// The decoded event.
+    pub data: E,
+}
+
+impl<E: SolEventInterface> EventLog<E> {
+    /// Decodes a mined log, returning `None` for pending logs (without block information) and
+    /// logs that are not events of `E`.
+    pub fn decode(log: &Log) -> Option<Self> {
+        Some(Self {
+            block_number: log.block_number?,
+            block_hash: log.block_hash?,
+            transaction_hash: log.transaction_hash?,
+            log_index: log.log_index?,
+            address: log.address(),
+            data: E::decode_log(&log.inner, true).ok()?.data,
+        })
+    }
+}
+
+impl<E> EventLog<E> {
+    /// Key identifying the log across redeliveries, for handlers to process it at most once.
+    ///
+    /// It includes the block hash, so the same event re-emitted in another block after a reorg
+    /// gets a new key.
+    pub fn idempotency_key(&self) -> String {
+        format!("{:#x}:{}", self.block_hash, self.log_index)
+    }
+}
+
+/// A state transition of a contract event.
+///
+/// The transitions of one event share its [`EventLog`], as generated event types need not be
+/// `Clone`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum StreamEvent<E> {
+    /// The event was emitted in a block that may still be reorganized away.
+    Observed(Arc<EventLog<E>>),
+    /// The block of a previously observed event is no longer canonical.
+    Retracted(Arc<EventLog<E>>),
+    /// The block of a previously observed event is buried deep enough to be final.
+    Finalized(Arc<EventLog<E>>),
+}
+
+impl<E> Clone for StreamEvent<E> {
+    fn clone(&self) -> Self {
+        match self {
+            StreamEvent::Observed(log) => StreamEvent::Observed(log.clone()),
+            StreamEvent::Retracted(log) => StreamEvent::Retracted(log.clone()),
+            StreamEvent::Finalized(log) => StreamEvent::Finalized(log.clone()),
+        }
+    }
+}
+
+impl<E> StreamEvent<E> {
+    /// The event the transition applies to.
+    pub fn log(&self) -> &EventLog<E> {
+        match self {
+            StreamEvent::Observed(log) | StreamEvent::Retracted(log) | StreamEvent::Finalized(log) => log,
+        }
+    }
+}
+
+/// Tracks observed events until they are final, detecting reorgs by block hash.
+///
+/// Logs must be fed in chain order, as log subscriptions and `eth_getLogs` deliver them. The
+/// buffer does no I/O; [`EventStream`] drives it from a node.
+#[derive(Debug)]
+pub struct ReorgBuffer<E> {
+    depth: u64,
+    /// Events that are not final yet, by block number and log index.
+    pending: BTreeMap<(u64, u64), Arc<EventLog<E>>>,
+    /// Highest block up to which events were finalized.
+    finalized_block: Option<u64>,
+    /// Events finalized in the last `depth` blocks up to `finalized_block`, by block number and
+    /// log index, to ignore late duplicates.
+    finalized: BTreeSet<(u64, u64)>,
+}
+
+impl<E> ReorgBuffer<E> {
+    /// Creates a buffer finalizing events once their block is `depth` blocks deep (at least 1,
+    /// the latest block).
+    pub fn new(depth: u64) -> Self {
+        Self { depth: depth.max(1), pending: BTreeMap::new(), finalized_block: None, finalized: BTreeSet::new() }
+    }
+
+    /// Number of blocks an event must be buried under before it is final.
+    pub fn depth(&self) -> u64 {
+        self.depth
+    }
+
+    /// Highest block up to which events were finalized.
+    pub fn finalized_block(&self) -> Option<u64> {
+        self.finalized_block
+    }
+
+    /// Events that are not final yet, in chain order.
+    pub fn pending(&self) -> impl Iterator<Item = &EventLog<E>> {
+        self.pending.values().map(|log| &**log)
+    }
+
+    /// Distinct blocks of the pending events with their hashes, in ascending order.
+    pub fn pending_blocks(&self) -> Vec<(u64, BlockHash)> {
+        let mut blocks: Vec<_> = self.pending.values().map(|log| (log.block_number, log.block_hash)).collect();
+        blocks.dedup();
+        blocks
+    }
+
+    /// Records a newly delivered event.
+    ///
+    /// Events already pending or finalized are ignored, so overlapping deliveries (e.g. after
+    /// resubscribing) are not counted twice; so are events more than `depth` blocks below the
+    /// finalized block. An event at a height where pending events have another block hash
+    /// retracts those events and all later ones first.
+    pub fn observe(&mut self, log: EventLog<E>) -> Vec<StreamEvent<E>> {
+        let key = (log.block_number, log.log_index);
+        let below_window = self.finalized_block.is_some_and(|finalized| log.block_number + self.depth < finalized);
+        if below_window || self.finalized.contains(&key) {
+            return Vec::new();
+        }
+
+        let mut transitions = Vec::new();
+        let conflicting = self
+            .pending
+            .range((log.block_number, 0)..=(log.block_number, u64::MAX))
+            .any(|(_, pending)| pending.block_hash != log.block_hash);
+        if conflicting {
+            transitions.extend(self.reorg(log.block_number));
+        }
+
+        if let Entry::Vacant(entry) = self.pending.entry(key) {
+            let log = Arc::new(log);
+            entry.insert(log.clone());
+            transitions.push(StreamEvent::Observed(log));
+        }
+        transitions
+    }
+
+    /// Retracts a pending event that the node reported as removed.
+    pub fn remove(&mut self, log: &EventLog<E>) -> Option<StreamEvent<E>> {
+        let key = (log.block_number, log.log_index);
+        match self.pending.get(&key) {
+            Some(pending) if pending.block_hash == log.block_hash => {
+                self.pending.remove(&key).map(StreamEvent::Retracted)
+            }
+            _ => None,
+        }
+    }
+
+    /// Retracts all pending events in blocks from `block_number` on, which are no longer canonical.
+    pub fn reorg(&mut self, block_number: u64) -> Vec<StreamEvent<E>> {
+        let retracted = self.pending.split_off(&(block_number, 0));
+        retracted.into_values().rev().map(StreamEvent::Retracted).collect()
+    }
+
+    /// Blocks of pending events that become final at `head`, in ascending order.
+    pub fn finalizable_blocks(&self, head: u64) -> Vec<(u64, BlockHash)> {
+        self.pending_blocks()
+            .into_iter()
+            .take_while(|(block_number, _)| block_number + self.depth - 1 <= head)
+            .collect()
+    }
+
+    /// Finalizes the pending events whose block is at least `depth` deep at `head`.
+    ///
+    /// The caller must have checked that their blocks are canonical, see
+    /// [`Self::finalizable_blocks`].
+    pub fn finalize(&mut self, head: u64) -> Vec<StreamEvent<E>> {
+        let Some(last_final) = (head + 1).checked_sub(self.depth) else { return Vec::new() };
+        let still_pending = self.pending.split_off(&(last_final + 1, 0));
+        let finalized = std::mem::replace(&mut self.pending, still_pending);
+
+        let finalized_block = self.finalized_block.map_or(last_final, |block| block.max(last_final));
+        self.finalized_block = Some(finalized_block);
+        self.finalized.extend(finalized.keys());
+        self.finalized = self.finalized.split_off(&(finalized_block.saturating_sub(self.depth), 0));
+
+        finalized.into_values().map(StreamEvent::Finalized).collect()
+    }
+}
+
+/// Errors raised by an [`EventStream`].
+#[derive(Debug, Error)]
+pub enum EventStreamError {
+    /// Subscribing or querying the node failed.
+    #[error(transparent)]
+    Transport(#[from] TransportError),
+    /// The log or new heads subscription ended.
+    #[error("subscription closed")]
+    SubscriptionClosed,
+    /// Connecting to the node failed, and the reconnect policy gave up.
+    #[error("failed to connect: {0}")]
+    Connect(#[source] Box<dyn StdError + Send + Sync>),
+}
+
+/// Subscribes to the events matching a filter, reporting them as reorg-safe [`StreamEvent`]s.
+#[derive(Clone, Debug)]
+pub struct EventStream {
+    filter: Filter,
+    depth: u64,
+    reconnect: ReconnectPolicy,
+    from_block: Option<u64>,
+    resume_after: Option<Checkpoint>,
+    page_size: u64,
+    poll_interval: Duration,
+}
+
+impl EventStream {
+    /// Creates a stream of the events matching `filter`, final at [`DEFAULT_FINALITY_DEPTH`].
+    pub fn new(filter: Filter) -> Self {
+        Self {
+            filter,
+            depth: DEFAULT_FINALITY_DEPTH,
+            reconnect: ReconnectPolicy::default(),
+            from_block: None,
+            resume_after: None,
+            page_size: DEFAULT_PAGE_SIZE,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+
+    /// The filter selecting the logs of the stream.
+    pub fn filter(&self) -> &Filter {
+        &self.filter
+    }
+
+    /// The block the historical events start from, `None` if only live events are streamed.
+    pub fn start_block(&self) -> Option<u64> {
+        self.from_block
+    }
+
+    /// Starts with the historical events from `block` on, before the live ones. Without it only
+    /// events emitted after subscribing are streamed.
+    pub fn from_block(mut self, block: u64) -> Self {
+        self.from_block = Some(block);
+        self.resume_after = None;
+        self
+    }
+
+    /// Starts with the historical events after `checkpoint`, the position of the last event a
+    /// previous run processed, before the live ones.
+    pub fn resume_after(mut self, checkpoint: Checkpoint) -> Self {
+        self.from_block = Some(checkpoint.block_number);
+        self.resume_after = Some(checkpoint);
+        self
+    }
+
+    /// Sets how often [`Self::poll`] polls for new blocks.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Sets the number of blocks per `eth_getLogs` page to start with when fetching historical
+    /// or missed events; pages shrink when the node rejects them as too large.
+    pub fn with_page_size(mut self, page_size: u64) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    /// Sets how many blocks deep an event must be to be final (1 finalizes on inclusion).
+    pub fn with_finality_depth(mut self, depth: u64) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Sets the backoff between the reconnection attempts of [`Self::subscribe_with_reconnect`]
+    /// and between the retries of [`Self::poll`].
+    pub fn with_reconnect_policy(mut self, reconnect: ReconnectPolicy) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    /// The same stream restricted to the logs of `addresses`, starting with the live events if
+    /// `live` is set.
+    pub(crate) fn for_addresses(&self, addresses: &[Address], live: bool) -> Self {
+        let mut stream = self.clone();
+        stream.filter = stream.filter.address(addresses.to_vec());
+        if live {
+            stream.from_block = None;
+            stream.resume_after = None;
+        }
+        stream
+    }
+
+    /// Subscribes to the logs and new heads and streams the state transitions of the events
+    /// of `E`, starting with the historical ones if a start block is set. Logs that are not
+    /// events of `E` are skipped.
+    ///
+    /// The provider must support subscriptions (WebSocket or IPC). The returned stream owns it,
+    /// so it can be moved into a task.
+    pub async fn subscribe<E, P, T>(
+        &self,
+        provider: P,
+    ) -> Result<impl Stream<Item = Result<StreamEvent<E>, EventStreamError>> + use<E, P, T>, EventStreamError>
+    where
+        E: SolEventInterface,
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+    {
+        let inputs = subscribe_inputs(&provider, &self.filter).await?;
+        let mut state = self.state(provider);
+        if let Some(from_block) = self.from_block {
+            state.start_history(from_block).await?;
+        }
+        Ok(state.drive(inputs))
+    }
+
+    /// Like [`Self::subscribe`], but survives dropped connections: when the subscriptions end or
+    /// the node cannot be queried, it connects again with `connect`, backing off according to the
+    /// reconnect policy, resubscribes to the same filter and backfills the logs missed since the
+    /// last processed block with `eth_getLogs`.
+    ///
+    /// # Arguments
+    ///
+    /// * `connect` - Creates a new provider, e.g. `move || ws_provider(&config)` with an owned
+    ///   `config`.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<impl Stream<Item = Result<StreamEvent<E>, EventStreamError>>, EventStreamError>` -
+    ///   The stream once the first connection is subscribed. It ends with
+    ///   [`EventStreamError::Connect`] when the reconnect policy gives up.
+    pub async fn subscribe_with_reconnect<E, P, T, C, Fut, CE>(
+        &self,
+        mut connect: C,
+    ) -> Result<
+        impl Stream<Item = Result<StreamEvent<E>, EventStreamError>> + use<E, P, T, C, Fut, CE>,
+        EventStreamError,
+    >
+    where
+        E: SolEventInterface,
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+        C: FnMut() -> Fut,
+        Fut: Future<Output = Result<P, CE>>,
+        CE: Into<Box<dyn StdError + Send + Sync>>,
+    {
+        let provider = connect().await.map_err(|error| EventStreamError::Connect(error.into()))?;
+        let inputs = subscribe_inputs(&provider, &self.filter).await?;
+
+        // Backfill after a reconnect from the current block on, even if no log or head arrives
+        let mut state = self.state(provider);
+        match self.from_block {
+            Some(from_block) => state.start_history(from_block).await?,
+            None => state.last_block = Some(state.provider.get_block_number().await?),
+        }
+
+        let reconnect = Reconnect { connect, policy: self.reconnect.clone() };
+        let stream = futures::stream::unfold(
+            (state, inputs, reconnect, false),
+            |(mut state, mut inputs, mut reconnect, done)| async move {
+                if done {
+                    return None;
+                }
+                let transitions = state.next(&mut inputs).await;
+                let (items, done) = match transitions {
+                    Ok(transitions) => (transitions.into_iter().map(Ok).collect(), false),
+                    // Whatever went wrong, the connection is no longer trusted
+                    Err(_) => match reconnect.resume(&mut state).await {
+                        Ok((new_inputs, transitions)) => {
+                            inputs = new_inputs;
+                            (transitions.into_iter().map(Ok).collect(), false)
+                        }
+                        Err(error) => (vec![Err(error)], true),
+                    },
+                };
+                Some((futures::stream::iter(items), (state, inputs, reconnect, done)))
+            },
+        );
+        Ok(stream.flatten())
+    }
+
+    /// Like [`Self::subscribe`], but for providers without subscriptions (e.g. over HTTP): polls
+    /// for the latest block at the poll interval and fetches the logs of the new blocks with
+    /// `eth_getLogs`. Failed polls are retried with the backoff of the reconnect policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - The provider to poll, owned by the returned stream.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<impl Stream<Item = Result<StreamEvent<E>, EventStreamError>>, EventStreamError>` -
+    ///   The stream, starting after the current block (or at the start block). It ends with the
+    ///   last error when the reconnect policy gives up.
+    pub async fn poll<E, P, T>(
+        &self,
+        provider: P,
+    ) -> Result<impl Stream<Item = Result<StreamEvent<E>, EventStreamError>> + use<E, P, T>, EventStreamError>
+    where
+        E: SolEventInterface,
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+    {
+        let mut state = self.state(provider);
+        state.polling = true;
+        match self.from_block {
+            Some(from_block) => state.start_history(from_block).await?,
+            None => state.last_block = Some(state.provider.get_block_number().await?),
+        }
+
+        let ticks = poll_ticks(self.poll_interval);
+        let policy = self.reconnect.clone();
+        let stream = futures::stream::unfold(
+            (state, ticks, policy, 0, false),
+            |(mut state, mut ticks, policy, attempt, done)| async move {
+                if done {
+                    return None;
+                }
+                let (items, attempt, done) = match state.next(&mut ticks).await {
+                    Ok(transitions) => (transitions.into_iter().map(Ok).collect(), 0, false),
+                    // Retry failed polls, the state is intact
+                    Err(error) => match policy.backoff(attempt) {
+                        Some(backoff) => {
+                            tokio::time::sleep(backoff).await;
+                            (Vec::new(), attempt + 1, false)
+                        }
+                        None => (vec![Err(error)], attempt, true),
+                    },
+                };
+                Some((futures::stream::iter(items), (state, ticks, policy, attempt, done)))
+            },
+        );
+        Ok(stream.flatten())
+    }
+
+    fn state<P, E>(&self, provider: P) -> StreamState<P, E> {
+        StreamState {
+            provider,
+            filter: self.filter.clone(),
+            buffer: ReorgBuffer::new(self.depth),
+            last_head: None,
+            last_block: None,
+            pager: LogPager::new(self.page_size),
+            history: None,
+            resume_after: self.resume_after,
+            polling: false,
+        }
+    }
+}
+
+/// Subscribes to the logs matching `filter` and to new heads, merged into one stream.
+async fn subscribe_inputs<P, T>(provider: &P, filter: &Filter) -> Result<BoxStream<'static, Input>, TransportError>
+where
+    P: Provider<T, Ethereum>,
+    T: Transport + Clone,
+{
+    // Subscribe to both before streaming, so no log or head is missed
+    let logs = provider.subscribe_logs(filter).await?.into_stream();
+    let heads = provider.subscribe_blocks().await?.into_stream();
+    let inputs = futures::stream::select(
+        logs.map(|log| Input::Log(Box::new(log))),
+        heads.map(|header| Input::Head(header.into())),
+    );
+    Ok(inputs.boxed())
+}
+
+/// Yields [`Input::Poll`] right away and then every `interval`.
+fn poll_ticks(interval: Duration) -> BoxStream<'static, Input> {
+    let ticks = futures::stream::unfold(true, move |first| async move {
+        if !first {
+            tokio::time::sleep(interval).await;
+        }
+        Some((Input::Poll, false))
+    });
+    ticks.boxed()
+}
+
+/// An item of the merged log and new heads subscriptions, or a polling tick.
+enum Input {
+    Log(Box<Log>),
+    Head(Head),
+    Poll,
+}
+
+/// The parts of a new head needed to detect reorgs.
+struct Head {
+    number: u64,
+    hash: BlockHash,
+    parent_hash: BlockHash,
+}
+
+impl From<Header> for Head {
+    fn from(header: Header) -> Self {
+        Self { number: header.number, hash: header.hash, parent_hash: header.parent_hash }
+    }
+}
+
+/// State of an [`EventStream::subscribe`] stream.
+struct StreamState<P, E> {
+    provider: P,
+    filter: Filter,
+    buffer: ReorgBuffer<E>,
+    /// Number and hash of the last head seen.
+    last_head: Option<(u64, BlockHash)>,
+    /// Highest block seen in a head or log, from which missed logs are backfilled.
+    last_block: Option<u64>,
+    pager: LogPager,
+    /// Remaining historical blocks to page through before following the subscriptions.
+    history: Option<(u64, u64)>,
+    /// Position of the last event processed by a previous run; it and earlier ones are skipped.
+    resume_after: Option<Checkpoint>,
+    /// Whether logs are fetched for every new head, instead of delivered by a subscription.
+    polling: bool,
+}
+
+impl<P, E> StreamState<P, E>
+where
+    E: SolEventInterface,
+{
+    /// Queues the historical blocks from `from_block` up to the current block.
+    async fn start_history<T>(&mut self, from_block: u64) -> Result<(), EventStreamError>
+    where
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+    {
+        let head = self.provider.get_block_number().await?;
+        self.history = (from_block <= head).then_some((from_block, head));
+        self.last_block = Some(head);
+        Ok(())
+    }
+
+    /// Feeds the merged subscriptions through the buffer.
+    fn drive<T>(
+        self,
+        inputs: impl Stream<Item = Input> + Unpin,
+    ) -> impl Stream<Item = Result<StreamEvent<E>, EventStreamError>>
+    where
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+    {
+        futures::stream::unfold((self, inputs, false), |(mut state, mut inputs, done)| async move {
+            if done {
+                return None;
+            }
+            let transitions = state.next(&mut inputs).await;
+            let done = transitions.is_err();
+            let items: Vec<_> = match transitions {
+                Ok(transitions) => transitions.into_iter().map(Ok).collect(),
+                Err(error) => vec![Err(error)],
+            };
+            Some((futures::stream::iter(items), (state, inputs, done)))
+        })
+        .flatten()
+    }
+
+    /// Processes the next page of history or, once done, the next subscription item.
+    async fn next<T>(&mut self, inputs: &mut (impl Stream<Item = Input> + Unpin)) -> Result<Vec<StreamEvent<E>>, EventStreamError>
+    where
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+    {
+        if let Some((from, to)) = self.history {
+            return self.next_history_page(from, to).await;
+        }
+        match inputs.next().await {
+            Some(input) => self.on_input(input).await,
+            None => Err(EventStreamError::SubscriptionClosed),
+        }
+    }
+
+    async fn next_history_page<T>(&mut self, from: u64, to: u64) -> Result<Vec<StreamEvent<E>>, EventStreamError>
+    where
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+    {
+        let (end, logs) = self.pager.next_page(&self.provider, &self.filter, from, to).await?;
+        let mut transitions: Vec<_> = logs.iter().flat_map(|log| self.on_log(log)).collect();
+        // Historical blocks at least `depth` below the end of the page are final
+        transitions.extend(self.buffer.finalize(end));
+
+        if end < to {
+            self.history = Some((end + 1, to));
+        } else {
+            // The subscriptions drop items they cannot buffer, so catch up with what arrived
+            // while paging before following them
+            self.history = None;
+            transitions.extend(self.catch_up().await?);
+        }
+        Ok(transitions)
+    }
+
+    async fn on_input<T>(&mut self, input: Input) -> Result<Vec<StreamEvent<E>>, EventStreamError>
+    where
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+    {
+        match input {
+            Input::Log(log) => Ok(self.on_log(&log)),
+            Input::Head(head) => self.on_head(&head).await,
+            Input::Poll => self.on_poll().await,
+        }
+    }
+
+    /// Processes the latest block as a new head if it changed since the last poll.
+    async fn on_poll<T>(&mut self) -> Result<Vec<StreamEvent<E>>, EventStreamError>
+    where
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+    {
+        let head = self.latest_head().await?;
+        if self.last_head.is_some_and(|(_, hash)| hash == head.hash) {
+            return Ok(Vec::new());
+        }
+        self.on_head(&head).await
+    }
+
+    fn on_log(&mut self, log: &Log) -> Vec<StreamEvent<E>> {
+        if !log.removed {
+            self.last_block = self.last_block.max(log.block_number);
+        }
+        let Some(event) = EventLog::decode(log) else { return Vec::new() };
+        if self.resume_after.is_some_and(|checkpoint| checkpoint.covers(&event)) {
+            return Vec::new();
+        }
+        if log.removed {
+            self.buffer.remove(&event).into_iter().collect()
+        } else {
+            self.buffer.observe(event)
+        }
+    }
+
+    async fn on_head<T>(&mut self, head: &Head) -> Result<Vec<StreamEvent<E>>, EventStreamError>
+    where
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+    {
+        let mut transitions = match self.last_block {
+            // Without a log subscription, fetch the logs of the new blocks
+            _ if self.polling => {
+                let from = self.poll_from(head).await?;
+                self.backfill(from, head.number).await?
+            }
+            // Heads were skipped, and so may have been the logs of their blocks
+            Some(last_block) if head.number > last_block + 1 => self.backfill(last_block, head.number).await?,
+            _ => Vec::new(),
+        };
+        self.last_block = self.last_block.max(Some(head.number));
+
+        // A head that does not build on the previous one may have replaced pending blocks
+        let reorged = self
+            .last_head
+            .is_some_and(|(number, hash)| head.number != number + 1 || head.parent_hash != hash);
+        self.last_head = Some((head.number, head.hash));
+        let suspects = if reorged {
+            self.buffer.pending_blocks()
+        } else {
+            // Blocks about to be final are re-checked in any case
+            self.buffer.finalizable_blocks(head.number)
+        };
+
+        for (block_number, block_hash) in suspects {
+            if self.canonical_hash(block_number).await? != Some(block_hash) {
+                transitions.extend(self.buffer.reorg(block_number));
+                break;
+            }
+        }
+        transitions.extend(self.buffer.finalize(head.number));
+        Ok(transitions)
+    }
+
+    /// Fetches and observes the logs of blocks `from..=to`; already observed ones are ignored.
+    async fn backfill<T>(&mut self, from: u64, to: u64) -> Result<Vec<StreamEvent<E>>, EventStreamError>
+    where
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+    {
+        let logs = self.pager.get_logs(&self.provider, &self.filter, from, to).await?;
+        Ok(logs.iter().flat_map(|log| self.on_log(log)).collect())
+    }
+
+    /// First block whose logs a poll at `head` must fetch: the one after the last fetched block
+    /// if `head` builds on the last head, otherwise the whole window in which blocks may have
+    /// been replaced.
+    async fn poll_from<T>(&self, head: &Head) -> Result<u64, EventStreamError>
+    where
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+    {
+        let Some(last_block) = self.last_block else { return Ok(head.number) };
+        let Some((number, hash)) = self.last_head else { return Ok(last_block + 1) };
+
+        let extends = if head.number == number + 1 {
+            head.parent_hash == hash
+        } else {
+            head.number > number && self.canonical_hash(number).await? == Some(hash)
+        };
+        if extends {
+            Ok(last_block + 1)
+        } else {
+            Ok((head.number + 1).saturating_sub(self.buffer.depth()).min(last_block + 1))
+        }
+    }
+
+    /// Catches up with the chain at the current head of a new connection.
+    async fn catch_up<T>(&mut self) -> Result<Vec<StreamEvent<E>>, EventStreamError>
+    where
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+    {
+        let head = self.latest_head().await?;
+
+        // Still paging through history: extend it to the new head instead
+        if let Some((from, _)) = self.history {
+            self.history = Some((from, head.number));
+            self.last_block = Some(head.number);
+            return Ok(Vec::new());
+        }
+
+        let mut transitions = self.backfill(self.last_block.unwrap_or(head.number), head.number).await?;
+        transitions.extend(self.on_head(&head).await?);
+        Ok(transitions)
+    }
+
+    async fn latest_head<T>(&self) -> Result<Head, EventStreamError>
+    where
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+    {
+        let block = self
+            .provider
+            .get_block_by_number(BlockNumberOrTag::Latest, BlockTransactionsKind::Hashes)
+            .await?
+            .ok_or(EventStreamError::SubscriptionClosed)?;
+        Ok(block.header.into())
+    }
+
+    async fn canonical_hash<T>(&self, block_number: u64) -> Result<Option<BlockHash>, EventStreamError>
+    where
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+    {
+        let block = self
+            .provider
+            .get_block_by_number(BlockNumberOrTag::Number(block_number), BlockTransactionsKind::Hashes)
+            .await?;
+        Ok(block.map(|block| block.header.hash))
+    }
+}
+
+/// Reconnection state of an [`EventStream::subscribe_with_reconnect`] stream.
+struct Reconnect<C> {
+    connect: C,
+    policy: ReconnectPolicy,
+}
+
+impl<C> Reconnect<C> {
+    /// Connects and subscribes again, backing off between attempts, and catches up with the
+    /// blocks missed while disconnected.
+    async fn resume<E, P, T, Fut, CE>(
+        &mut self,
+        state: &mut StreamState<P, E>,
+    ) -> Result<(BoxStream<'static, Input>, Vec<StreamEvent<E>>), EventStreamError>
+    where
+        E: SolEventInterface,
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+        C: FnMut() -> Fut,
+        Fut: Future<Output = Result<P, CE>>,
+        CE: Into<Box<dyn StdError + Send + Sync>>,
+    {
+        let mut attempt = 0;
+        let mut last_error: Box<dyn StdError + Send + Sync> = "no reconnection attempt allowed".into();
+        loop {
+            let Some(backoff) = self.policy.backoff(attempt) else {
+                return Err(EventStreamError::Connect(last_error));
+            };
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
+
+            let provider = match (self.connect)().await {
+                Ok(provider) => provider,
+                Err(error) => {
+                    last_error = error.into();
+                    continue;
+                }
+            };
+            let inputs = match subscribe_inputs(&provider, &state.filter).await {
+                Ok(inputs) => inputs,
+                Err(error) => {
+                    last_error = error.into();
+                    continue;
+                }
+            };
+            state.provider = provider;
+            match state.catch_up().await {
+                Ok(transitions) => return Ok((inputs, transitions)),
+                Err(error) => last_error = error.into(),
+            }
+        }
+    }
+}
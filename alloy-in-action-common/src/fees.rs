@@ -0,0 +1,354 @@
+//! EIP-1559 fee estimation.
+//!
+//! [`FeeEstimator`] combines the priority fees paid in recent blocks (`eth_feeHistory`) with a
+//! base fee projected a few blocks ahead using [`calculate_base_fee_per_gas`]. [`FeeFiller`]
+//! plugs the estimator into a [`ProviderBuilder`](alloy_provider::ProviderBuilder) in place of
+//! the default gas filler, so hand-built `TransactionRequest`s get gas limit and fees filled in:
+//!
+//! ```ignore
+//! let provider = ProviderBuilder::new()
+//!     .filler(FeeFiller::new(FeeEstimator::new(FeeStrategy::Fast)))
+//!     .with_simple_nonce_management()
+//!     .fetch_chain_id()
+//!     .wallet(wallet)
+//!     .on_http(rpc_url);
+//! ```
+
+use alloy_json_rpc::RpcError;
+use alloy_network::{Ethereum, TransactionBuilder};
+use alloy_network::primitives::BlockTransactionsKind;
+use alloy_provider::fillers::{FillerControlFlow, TxFiller};
+use alloy_provider::utils::Eip1559Estimation;
+use alloy_provider::{Provider, SendableTx};
+use alloy_rpc_types::{BlockNumberOrTag, TransactionRequest};
+use alloy_transport::{Transport, TransportResult};
+
+/// Number of past blocks sampled for priority fees by default.
+pub const DEFAULT_HISTORY_BLOCKS: u64 = 10;
+
+/// Number of blocks the base fee is projected ahead by default.
+pub const DEFAULT_BLOCKS_AHEAD: u64 = 3;
+
+/// Ratio of a block's gas limit to its gas target (EIP-1559 `ELASTICITY_MULTIPLIER`).
+pub const ELASTICITY_MULTIPLIER: u64 = 2;
+
+/// Bounds the base fee change per block to 1/8th, i.e. 12.5%
+/// (EIP-1559 `BASE_FEE_MAX_CHANGE_DENOMINATOR`).
+pub const BASE_FEE_MAX_CHANGE_DENOMINATOR: u64 = 8;
+
+/// Priority fee used when recent blocks paid no tips, e.g. on an idle devnet (1 gwei).
+pub const DEFAULT_MIN_PRIORITY_FEE: u128 = 1_000_000_000;
+
+/// How aggressively to bid for inclusion.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FeeStrategy {
+    /// Tip at the 10th percentile of recent blocks.
+    Slow,
+    /// Tip at the 50th percentile of recent blocks.
+    #[default]
+    Normal,
+    /// Tip at the 90th percentile of recent blocks.
+    Fast,
+}
+
+impl FeeStrategy {
+    /// Percentile of the priority fees paid in recent blocks that this strategy bids.
+    pub const fn reward_percentile(self) -> f64 {
+        match self {
+            FeeStrategy::Slow => 10.0,
+            FeeStrategy::Normal => 50.0,
+            FeeStrategy::Fast => 90.0,
+        }
+    }
+}
+
+/// Estimates `maxFeePerGas` and `maxPriorityFeePerGas` for EIP-1559 transactions.
+#[derive(Clone, Debug)]
+pub struct FeeEstimator {
+    strategy: FeeStrategy,
+    history_blocks: u64,
+    blocks_ahead: u64,
+    min_priority_fee: u128,
+    max_fee_cap: Option<u128>,
+}
+
+impl Default for FeeEstimator {
+    fn default() -> Self {
+        Self::new(FeeStrategy::default())
+    }
+}
+
+impl FeeEstimator {
+    /// Creates an estimator using `strategy` and the default settings.
+    pub const fn new(strategy: FeeStrategy) -> Self {
+        Self {
+            strategy,
+            history_blocks: DEFAULT_HISTORY_BLOCKS,
+            blocks_ahead: DEFAULT_BLOCKS_AHEAD,
+            min_priority_fee: DEFAULT_MIN_PRIORITY_FEE,
+            max_fee_cap: None,
+        }
+    }
+
+    /// Sets the number of past blocks sampled for priority fees.
+    pub const fn with_history_blocks(mut self, history_blocks: u64) -> Self {
+        self.history_blocks = history_blocks;
+        self
+    }
+
+    /// Sets how many blocks ahead the base fee is projected, i.e. for how many consecutive full
+    /// blocks the transaction stays includable.
+    pub const fn with_blocks_ahead(mut self, blocks_ahead: u64) -> Self {
+        self.blocks_ahead = blocks_ahead;
+        self
+    }
+
+    /// Sets the priority fee used when recent blocks paid lower (or no) tips.
+    pub const fn with_min_priority_fee(mut self, min_priority_fee: u128) -> Self {
+        self.min_priority_fee = min_priority_fee;
+        self
+    }
+
+    /// Caps `maxFeePerGas` (and therefore `maxPriorityFeePerGas`) at `max_fee_cap` wei.
+    pub const fn with_max_fee_cap(mut self, max_fee_cap: u128) -> Self {
+        self.max_fee_cap = Some(max_fee_cap);
+        self
+    }
+
+    /// Returns the configured strategy.
+    pub const fn strategy(&self) -> FeeStrategy {
+        self.strategy
+    }
+
+    /// Queries the latest block and the fee history and returns the fee estimate.
+    pub async fn estimate<P, T>(&self, provider: &P) -> TransportResult<Eip1559Estimation>
+    where
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+    {
+        let percentiles = [self.strategy.reward_percentile()];
+        let (history, latest_block) = futures::try_join!(
+            provider.get_fee_history(self.history_blocks, BlockNumberOrTag::Latest, &percentiles),
+            provider.get_block_by_number(BlockNumberOrTag::Latest, BlockTransactionsKind::Hashes),
+        )?;
+
+        let header = latest_block.ok_or(RpcError::NullResp)?.header;
+        let base_fee = header
+            .base_fee_per_gas
+            .ok_or(RpcError::UnsupportedFeature("eip1559"))?;
+
+        let rewards: Vec<u128> = history
+            .reward
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|block| block.first().copied())
+            .collect();
+
+        Ok(self.estimate_from(base_fee, header.gas_used, header.gas_limit, &rewards))
+    }
+
+    /// Computes the fee estimate from the latest block's base fee and gas usage and the
+    /// priority fees paid at the strategy's percentile in recent blocks.
+    pub fn estimate_from(
+        &self,
+        base_fee: u64,
+        gas_used: u64,
+        gas_limit: u64,
+        rewards: &[u128],
+    ) -> Eip1559Estimation {
+        let base_fee = project_base_fee(base_fee, gas_used, gas_limit, self.blocks_ahead);
+        let priority_fee = median(rewards).unwrap_or(0).max(self.min_priority_fee);
+        let max_fee = base_fee as u128 + priority_fee;
+
+        match self.max_fee_cap {
+            Some(cap) if max_fee > cap => Eip1559Estimation {
+                max_fee_per_gas: cap,
+                max_priority_fee_per_gas: priority_fee.min(cap),
+            },
+            _ => Eip1559Estimation {
+                max_fee_per_gas: max_fee,
+                max_priority_fee_per_gas: priority_fee,
+            },
+        }
+    }
+}
+
+/// Transaction filler that fills the gas limit and EIP-1559 fees using a [`FeeEstimator`].
+///
+/// Replaces the default gas filler; values already set on the request are kept.
+#[derive(Clone, Debug, Default)]
+pub struct FeeFiller {
+    estimator: FeeEstimator,
+}
+
+impl FeeFiller {
+    /// Creates a filler using `estimator`.
+    pub const fn new(estimator: FeeEstimator) -> Self {
+        Self { estimator }
+    }
+}
+
+/// Values prepared by [`FeeFiller`].
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FeeFillable {
+    gas_limit: u64,
+    fees: Option<Eip1559Estimation>,
+}
+
+impl TxFiller<Ethereum> for FeeFiller {
+    type Fillable = FeeFillable;
+
+    fn status(&self, tx: &TransactionRequest) -> FillerControlFlow {
+        let fees_set = tx.gas_price().is_some()
+            || (tx.max_fee_per_gas().is_some() && tx.max_priority_fee_per_gas().is_some());
+
+        if fees_set && tx.gas_limit().is_some() {
+            FillerControlFlow::Finished
+        } else {
+            FillerControlFlow::Ready
+        }
+    }
+
+    fn fill_sync(&self, _tx: &mut SendableTx<Ethereum>) {}
+
+    async fn prepare<P, T>(&self, provider: &P, tx: &TransactionRequest) -> TransportResult<Self::Fillable>
+    where
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+    {
+        let gas_limit = match tx.gas_limit() {
+            Some(gas_limit) => gas_limit,
+            None => provider.estimate_gas(tx).await?,
+        };
+
+        // Legacy transactions and requests with both fees set keep their pricing, a fee set on
+        // its own is completed with the estimate
+        let fees = match (tx.gas_price(), tx.max_fee_per_gas(), tx.max_priority_fee_per_gas()) {
+            (Some(_), _, _) => None,
+            (None, Some(max_fee_per_gas), Some(max_priority_fee_per_gas)) => {
+                Some(Eip1559Estimation { max_fee_per_gas, max_priority_fee_per_gas })
+            }
+            (None, max_fee_per_gas, max_priority_fee_per_gas) => {
+                let estimate = self.estimator.estimate(provider).await?;
+                Some(complete_fees(max_fee_per_gas, max_priority_fee_per_gas, estimate))
+            }
+        };
+
+        Ok(FeeFillable { gas_limit, fees })
+    }
+
+    async fn fill(
+        &self,
+        fillable: Self::Fillable,
+        mut tx: SendableTx<Ethereum>,
+    ) -> TransportResult<SendableTx<Ethereum>> {
+        if let Some(builder) = tx.as_mut_builder() {
+            builder.set_gas_limit(fillable.gas_limit);
+            if let Some(fees) = fillable.fees {
+                builder.set_max_fee_per_gas(fees.max_fee_per_gas);
+                builder.set_max_priority_fee_per_gas(fees.max_priority_fee_per_gas);
+            }
+        }
+        Ok(tx)
+    }
+}
+
+/// Completes the fees set on a transaction with an estimate, keeping the fees that are set.
+///
+/// A missing priority fee is the estimated one, at most the set max fee. A missing max fee is
+/// the estimated base fee plus the set priority fee.
+///
+/// # Arguments
+///
+/// * `max_fee_per_gas` - The max fee set on the transaction, if any.
+/// * `max_priority_fee_per_gas` - The priority fee set on the transaction, if any.
+/// * `estimate` - The estimated fees, e.g. from [`FeeEstimator::estimate`].
+pub fn complete_fees(
+    max_fee_per_gas: Option<u128>,
+    max_priority_fee_per_gas: Option<u128>,
+    estimate: Eip1559Estimation,
+) -> Eip1559Estimation {
+    let estimated_base_fee = estimate.max_fee_per_gas.saturating_sub(estimate.max_priority_fee_per_gas);
+    let max_priority_fee_per_gas = max_priority_fee_per_gas.unwrap_or(estimate.max_priority_fee_per_gas);
+    let max_fee_per_gas = max_fee_per_gas.unwrap_or(estimated_base_fee + max_priority_fee_per_gas);
+    Eip1559Estimation { max_fee_per_gas, max_priority_fee_per_gas: max_priority_fee_per_gas.min(max_fee_per_gas) }
+}
+
+/// Projects the base fee `blocks` blocks ahead of the current block.
+///
+/// The first block follows from the current block's gas usage; every further block is assumed
+/// to be full, i.e. the worst case of a 12.5% increase per block.
+pub fn project_base_fee(current_base_fee: u64, current_gas_used: u64, current_gas_limit: u64, blocks: u64) -> u64 {
+    if blocks == 0 {
+        return current_base_fee;
+    }
+
+    let mut base_fee = calculate_base_fee_per_gas(current_base_fee, current_gas_used, current_gas_limit);
+    for _ in 1..blocks {
+        base_fee = calculate_base_fee_per_gas(base_fee, current_gas_limit, current_gas_limit);
+    }
+    base_fee
+}
+
+/// Calculates the base fee per gas for the next block based on EIP-1559 specifications.
+///
+/// This function adjusts the base fee according to the gas usage of the current block.
+/// If the gas used is higher than the target (the gas limit divided by
+/// [`ELASTICITY_MULTIPLIER`]), the base fee increases by at least 1 wei. If it's lower, the
+/// base fee decreases. The change is capped at a maximum of ±12.5%
+/// (1 / [`BASE_FEE_MAX_CHANGE_DENOMINATOR`]) per block.
+///
+/// A block with a gas target of zero (gas limit below the elasticity multiplier) leaves the
+/// base fee unchanged, where the specification would divide by zero.
+///
+/// # Arguments
+///
+/// * `current_base_fee` - The base fee per gas of the current block (in wei).
+/// * `current_gas_used` - The total gas used in the current block.
+/// * `current_gas_limit` - The gas limit of the current block.
+///
+/// # Returns
+///
+/// * `u64` - The calculated base fee per gas for the next block.
+pub fn calculate_base_fee_per_gas(
+    current_base_fee: u64,
+    current_gas_used: u64,
+    current_gas_limit: u64,
+) -> u64 {
+    // Calculate the target gas usage (50% of the gas limit)
+    let gas_target = current_gas_limit / ELASTICITY_MULTIPLIER;
+
+    // If gas usage is exactly at the target (or there is no target), base fee remains the same
+    if gas_target == 0 || current_gas_used == gas_target {
+        return current_base_fee;
+    }
+
+    // Compute the base fee change proportional to the distance from the target
+    // Using u128 to prevent overflow in intermediate calculations
+    let base_fee_change = |gas_delta: u64| {
+        current_base_fee as u128 * gas_delta as u128
+            / gas_target as u128
+            / BASE_FEE_MAX_CHANGE_DENOMINATOR as u128
+    };
+
+    if current_gas_used > gas_target {
+        // Increase base fee by the calculated change, at least 1 wei
+        let change = base_fee_change(current_gas_used - gas_target).max(1);
+        (current_base_fee as u128 + change).try_into().unwrap_or(u64::MAX)
+    } else {
+        // Decrease base fee by the calculated change (at most 12.5%, so it stays non-negative)
+        current_base_fee - base_fee_change(gas_target - current_gas_used) as u64
+    }
+}
+
+/// Returns the median of `values`, or `None` if empty.
+fn median(values: &[u128]) -> Option<u128> {
+    let mut values = values.to_vec();
+    values.sort_unstable();
+    match values.len() {
+        0 => None,
+        n if n % 2 == 1 => Some(values[n / 2]),
+        n => Some((values[n / 2 - 1] + values[n / 2]) / 2),
+    }
+}
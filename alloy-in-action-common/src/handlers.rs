@@ -0,0 +1,193 @@
+//! Dispatching logs to typed event handlers.
+//!
+//! Instead of matching every variant of a `sol!` generated events enum inline, a
+//! [`HandlerRegistry`] holds async callbacks per event type, registered with
+//! [`HandlerRegistry::on`], and a fallback for logs no handler can decode, registered with
+//! [`HandlerRegistry::on_unknown`]:
+//!
+//! ```ignore
+//! let handlers = HandlerRegistry::new()
+//!     .on(|event: ValueChanged, _log| async move {
+//!         println!("value changed to {}", event.newValue);
+//!         Ok(())
+//!     })
+//!     .on_unknown(|log| async move {
+//!         println!("unknown event in block {}", log.block_number);
+//!         Ok(())
+//!     });
+//! ```
+//!
+//! The logs are streamed undecoded as [`RawEvent`]s, e.g. with
+//! `EventStream::subscribe::<RawEvent, _, _>`, and handed to [`HandlerRegistry::dispatch`].
+//! Every handler's failure, whether an error or a panic, is isolated: the other handlers still
+//! run and the failures are returned, so the task driving the stream keeps going.
+
+use std::any::Any;
+use std::fmt;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use alloy_primitives::{LogData, B256};
+use alloy_sol_types::{SolEvent, SolEventInterface, Word};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use thiserror::Error;
+use crate::event_stream::EventLog;
+
+/// A log whose event is not decoded yet, so that streams deliver every log matching their
+/// filter, whatever its event.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RawEvent(pub LogData);
+
+impl SolEventInterface for RawEvent {
+    const NAME: &'static str = "RawEvent";
+    const COUNT: usize = 0;
+
+    fn decode_raw_log(topics: &[Word], data: &[u8], _validate: bool) -> alloy_sol_types::Result<Self> {
+        Ok(Self(LogData::new_unchecked(topics.to_vec(), data.to_vec().into())))
+    }
+}
+
+/// A log handed to the handlers, with its position in the chain.
+pub type RawLog = Arc<EventLog<RawEvent>>;
+
+/// A handler that failed on a log.
+#[derive(Debug, Error)]
+pub enum HandlerError {
+    /// The handler returned an error.
+    #[error("{event} handler failed: {error:#}")]
+    Failed { event: &'static str, error: eyre::Report },
+    /// The handler panicked.
+    #[error("{event} handler panicked: {message}")]
+    Panicked { event: &'static str, message: String },
+}
+
+/// Name under which failures of the fallback handler are reported.
+const UNKNOWN_EVENT: &str = "unknown event";
+
+type HandlerFn = Box<dyn Fn(RawLog) -> Option<BoxFuture<'static, eyre::Result<()>>> + Send + Sync>;
+
+struct Handler {
+    selector: B256,
+    event: &'static str,
+    call: HandlerFn,
+}
+
+/// Async handlers per event type, with a fallback for logs none of them can decode.
+#[derive(Default)]
+pub struct HandlerRegistry {
+    handlers: Vec<Handler>,
+    fallback: Option<HandlerFn>,
+}
+
+impl fmt::Debug for HandlerRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HandlerRegistry")
+            .field("handlers", &self.handlers.iter().map(|handler| handler.event).collect::<Vec<_>>())
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+impl HandlerRegistry {
+    /// Creates a registry without handlers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `handler` for the events of type `E`, decoded from the logs with `E`'s
+    /// signature. Several handlers of one type run in the order they were registered.
+    pub fn on<E, Fut>(mut self, handler: impl Fn(E, RawLog) -> Fut + Send + Sync + 'static) -> Self
+    where
+        E: SolEvent + 'static,
+        Fut: Future<Output = eyre::Result<()>> + Send + 'static,
+    {
+        self.handlers.push(Handler {
+            selector: E::SIGNATURE_HASH,
+            event: event_name::<E>(),
+            call: Box::new(move |log| {
+                let RawEvent(data) = &log.data;
+                let event = E::decode_raw_log(data.topics().iter().copied(), &data.data, true).ok()?;
+                Some(handler(event, log).boxed())
+            }),
+        });
+        self
+    }
+
+    /// Registers `handler` for logs that no handler is registered for or can decode.
+    pub fn on_unknown<Fut>(mut self, handler: impl Fn(RawLog) -> Fut + Send + Sync + 'static) -> Self
+    where
+        Fut: Future<Output = eyre::Result<()>> + Send + 'static,
+    {
+        self.fallback = Some(Box::new(move |log| Some(handler(log).boxed())));
+        self
+    }
+
+    /// Signatures of the registered event types, to filter the logs by.
+    pub fn event_signatures(&self) -> Vec<B256> {
+        let mut signatures = Vec::new();
+        for handler in &self.handlers {
+            if !signatures.contains(&handler.selector) {
+                signatures.push(handler.selector);
+            }
+        }
+        signatures
+    }
+
+    /// Runs the handlers of the log's event, or the fallback if there are none or none of them
+    /// can decode the log.
+    ///
+    /// # Arguments
+    ///
+    /// * `log` - The log to handle.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<HandlerError>` - The errors and panics of the handlers that failed; the other
+    ///   handlers ran regardless.
+    pub async fn dispatch(&self, log: RawLog) -> Vec<HandlerError> {
+        let selector = log.data.0.topics().first().copied();
+        let mut errors = Vec::new();
+        let mut handled = false;
+        for handler in self.handlers.iter().filter(|handler| Some(handler.selector) == selector) {
+            if let Some(error) = run(handler.event, &handler.call, &log, &mut handled).await {
+                errors.push(error);
+            }
+        }
+        if let (false, Some(fallback)) = (handled, &self.fallback) {
+            errors.extend(run(UNKNOWN_EVENT, fallback, &log, &mut handled).await);
+        }
+        errors
+    }
+}
+
+/// Name of the event `E`, its signature without the parameters.
+pub(crate) fn event_name<E: SolEvent>() -> &'static str {
+    E::SIGNATURE.split('(').next().unwrap_or(E::SIGNATURE)
+}
+
+/// Calls a handler, catching its panics; sets `handled` if it accepted the log.
+async fn run(event: &'static str, call: &HandlerFn, log: &RawLog, handled: &mut bool) -> Option<HandlerError> {
+    let panicked = |panic| HandlerError::Panicked { event, message: panic_message(panic) };
+    let future = match std::panic::catch_unwind(AssertUnwindSafe(|| call(log.clone()))) {
+        Ok(Some(future)) => future,
+        Ok(None) => return None,
+        Err(panic) => {
+            *handled = true;
+            return Some(panicked(panic));
+        }
+    };
+    *handled = true;
+    match AssertUnwindSafe(future).catch_unwind().await {
+        Ok(Ok(())) => None,
+        Ok(Err(error)) => Some(HandlerError::Failed { event, error }),
+        Err(panic) => Some(panicked(panic)),
+    }
+}
+
+pub(crate) fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    match panic.downcast::<String>() {
+        Ok(message) => *message,
+        Err(panic) => panic.downcast_ref::<&str>().map_or_else(|| "unknown panic".to_string(), |message| message.to_string()),
+    }
+}
@@ -0,0 +1,61 @@
+//! Shared building blocks for the Alloy in Action examples.
+//!
+//! Provides the [`SampleContract`] bindings, the profile based [`Config`] and
+//! provider/wallet constructors so that each example does not have to carry its own copy, and
+//! an [`Anvil`] harness to run them against a local node in tests. On top of these:
+//!
+//! - transactions: EIP-1559 [`fees`], [`nonce`] management, stuck transaction [`replacement`],
+//!   [`confirmation`] tracking, [`receipt`] events and [`revert`] decoding,
+//! - events: reorg-safe [`event_stream`]s with [`reconnect`] and [`backfill`], typed
+//!   [`topics`] filters, [`handlers`] per event type, bounded [`queue`]s, [`checkpoint`]s, a
+//!   [`supervisor`] for listener tasks, and a [`watcher`] of many deployments fed by
+//!   [`discovery`].
+//!
+//! # Features
+//!
+//! - `cli`: parses [`ConfigOverrides`] from the command line with clap (`Config::from_cli`).
+//! - `sqlite`: adds `SqliteCheckpointStore`, compiling a bundled SQLite.
+
+pub mod anvil;
+pub mod backfill;
+pub mod bindings;
+pub mod checkpoint;
+pub mod config;
+pub mod confirmation;
+pub mod discovery;
+pub mod event_stream;
+pub mod fees;
+pub mod handlers;
+pub mod nonce;
+pub mod provider;
+pub mod queue;
+pub mod reconnect;
+pub mod receipt;
+pub mod replacement;
+pub mod revert;
+pub mod supervisor;
+pub mod topics;
+pub mod watcher;
+
+pub use anvil::{Anvil, AnvilInstance, TestNode};
+pub use backfill::{LogPager, RangeErrorExt};
+pub use bindings::SampleContract;
+pub use checkpoint::{Checkpoint, CheckpointError, CheckpointStore, JsonCheckpointStore};
+#[cfg(feature = "sqlite")]
+pub use checkpoint::SqliteCheckpointStore;
+pub use config::{Config, ConfigError, ConfigOverrides};
+pub use confirmation::{Confirmation, ConfirmationError, ConfirmationPolicy, ConfirmationTracker};
+pub use discovery::{from_factory_events, DeployDiscovery, Discovery};
+pub use event_stream::{EventLog, EventStream, EventStreamError, ReorgBuffer, StreamEvent};
+pub use fees::{FeeEstimator, FeeFiller, FeeStrategy};
+pub use handlers::{HandlerError, HandlerRegistry, RawEvent, RawLog};
+pub use nonce::{NonceErrorExt, NonceManager};
+pub use provider::{http_provider, wallet, ws_provider, HttpProvider, WsProvider};
+pub use queue::{EventQueue, OverflowPolicy, QueueError, QueueMetrics, QueueReceiver};
+pub use reconnect::ReconnectPolicy;
+pub use receipt::{DecodedEvent, ReceiptEvents, ReceiptExt};
+pub use replacement::{ReplacementError, ReplacingSender, SentTransaction};
+pub use revert::{decode_revert, Revert, RevertExt};
+pub use supervisor::{Completion, Progress, Shutdown, StopReason, Summary, Supervisor, TaskOutcome};
+pub use topics::encode_topics;
+pub use watcher::{ContractWatcher, Deployment, Deployments, WatchedEvent};
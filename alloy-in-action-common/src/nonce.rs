@@ -0,0 +1,235 @@
+//! Local nonce management for transactions sent from the same signer.
+//!
+//! Fetching `eth_getTransactionCount(address, "pending")` before every transaction hands out
+//! the same nonce twice as soon as two transactions are in flight. [`NonceManager`] keeps the
+//! next nonce per signer address locally, hands out sequential nonces and resyncs with the node
+//! when a transaction is rejected as `nonce too low` or `nonce too high`. A resync never goes
+//! back below a nonce still reserved by a transaction being sent, so no nonce is handed out twice.
+//!
+//! It can be used directly with hand-built requests and contract call builders through
+//! [`NonceManager::send_with_nonce`], or as the nonce manager of a provider:
+//!
+//! ```ignore
+//! let nonce_manager = NonceManager::default();
+//! let provider = ProviderBuilder::new()
+//!     .filler(NonceFiller::new(nonce_manager.clone()))
+//!     .wallet(wallet)
+//!     .on_http(rpc_url);
+//! ```
+
+use std::collections::{BTreeSet, HashMap};
+use std::future::Future;
+use std::sync::Arc;
+use alloy_network::Network;
+use alloy_primitives::Address;
+use alloy_provider::Provider;
+use alloy_transport::{Transport, TransportError, TransportResult};
+use async_trait::async_trait;
+use futures::lock::Mutex;
+
+/// How many times [`NonceManager::send_with_nonce`] resyncs and resends after a nonce error.
+pub const MAX_NONCE_RETRIES: usize = 3;
+
+/// Error messages (lowercase) with which nodes reject a transaction whose nonce does not follow
+/// the account's pending transactions.
+const NONCE_MISMATCH_ERRORS: [&str; 2] = ["nonce too low", "nonce too high"];
+
+/// Error messages (lowercase) with which nodes reject a transaction because of its nonce.
+const NONCE_ERRORS: [&str; 4] = [
+    "nonce too low",
+    "nonce too high",
+    "replacement transaction underpriced",
+    "replacement underpriced",
+];
+
+/// Nonce state of one signer address.
+#[derive(Debug, Default)]
+struct Nonces {
+    /// Next nonce to hand out.
+    next: u64,
+    /// Nonces handed out by [`NonceManager::send_with_nonce`] whose transaction is being sent.
+    reserved: BTreeSet<u64>,
+}
+
+/// Hands out sequential nonces per signer address.
+///
+/// Clones share their state, so a single manager can be used by several tasks and providers.
+#[derive(Clone, Debug, Default)]
+pub struct NonceManager {
+    /// Nonce state per address.
+    nonces: Arc<Mutex<HashMap<Address, Nonces>>>,
+}
+
+impl NonceManager {
+    /// Returns the next nonce for `address`, fetching the pending transaction count on first use.
+    pub async fn next_nonce<P, T, N>(&self, provider: &P, address: Address) -> TransportResult<u64>
+    where
+        P: Provider<T, N>,
+        T: Transport + Clone,
+        N: Network,
+    {
+        self.take_nonce(provider, address, false).await
+    }
+
+    /// Hands out the next nonce for `address`, optionally reserving it until [`Self::release`].
+    async fn take_nonce<P, T, N>(&self, provider: &P, address: Address, reserve: bool) -> TransportResult<u64>
+    where
+        P: Provider<T, N>,
+        T: Transport + Clone,
+        N: Network,
+    {
+        // Hold the lock across the fetch so concurrent callers never get the same nonce
+        let mut nonces = self.nonces.lock().await;
+        let state = match nonces.get_mut(&address) {
+            Some(state) => state,
+            None => {
+                let next = provider.get_transaction_count(address).pending().await?;
+                nonces.entry(address).or_insert(Nonces { next, reserved: BTreeSet::new() })
+            }
+        };
+        let nonce = state.next;
+        state.next += 1;
+        if reserve {
+            state.reserved.insert(nonce);
+        }
+        Ok(nonce)
+    }
+
+    /// Ends the reservation of `nonce` once its transaction was sent or rejected.
+    async fn release(&self, address: Address, nonce: u64) {
+        if let Some(state) = self.nonces.lock().await.get_mut(&address) {
+            state.reserved.remove(&nonce);
+        }
+    }
+
+    /// Replaces the local nonce of `address` with the node's pending transaction count, but never
+    /// goes back to or below a nonce still reserved by a transaction being sent.
+    ///
+    /// # Returns
+    ///
+    /// * `TransportResult<u64>` - The next nonce that will be handed out.
+    pub async fn resync<P, T, N>(&self, provider: &P, address: Address) -> TransportResult<u64>
+    where
+        P: Provider<T, N>,
+        T: Transport + Clone,
+        N: Network,
+    {
+        let mut nonces = self.nonces.lock().await;
+        let pending = provider.get_transaction_count(address).pending().await?;
+        let state = nonces.entry(address).or_default();
+        state.next = match state.reserved.last() {
+            Some(highest) => pending.max(highest + 1),
+            None => pending,
+        };
+        Ok(state.next)
+    }
+
+    /// Sends a transaction from `address` with the next nonce, resyncing and resending with a
+    /// fresh nonce if the node rejects it as `nonce too low` or `nonce too high` (up to
+    /// [`MAX_NONCE_RETRIES`] times).
+    ///
+    /// The nonce is reserved while `send` runs, so a concurrent resync does not hand it out again.
+    /// Other errors leave the local nonce as it is: whether the transaction reached the node is
+    /// unknown, and if it did not, the next transaction is rejected as `nonce too high` and resyncs.
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - Provider used to resync with the node.
+    /// * `address` - The signer address the transaction is sent from.
+    /// * `send` - Sends the transaction with the given nonce, e.g.
+    ///   `|nonce| provider.send_transaction(tx.clone().with_nonce(nonce))`, or for a contract call
+    ///   `|nonce| { let call = contract.setValue(value).nonce(nonce); async move { call.send().await } }`.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<R, E>` - The result of the first accepted `send`, or the last error.
+    pub async fn send_with_nonce<P, T, N, F, Fut, R, E>(
+        &self,
+        provider: &P,
+        address: Address,
+        mut send: F,
+    ) -> Result<R, E>
+    where
+        P: Provider<T, N>,
+        T: Transport + Clone,
+        N: Network,
+        F: FnMut(u64) -> Fut,
+        Fut: Future<Output = Result<R, E>>,
+        E: NonceErrorExt + From<TransportError>,
+    {
+        let mut nonce = self.take_nonce(provider, address, true).await?;
+        let mut retries = 0;
+        loop {
+            let result = send(nonce).await;
+            self.release(address, nonce).await;
+            match result {
+                Ok(result) => return Ok(result),
+                Err(error) if error.is_nonce_mismatch() && retries < MAX_NONCE_RETRIES => {
+                    retries += 1;
+                    self.resync(provider, address).await?;
+                    nonce = self.take_nonce(provider, address, true).await?;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl alloy_provider::fillers::NonceManager for NonceManager {
+    async fn get_next_nonce<P, T, N>(&self, provider: &P, address: Address) -> TransportResult<u64>
+    where
+        P: Provider<T, N>,
+        N: Network,
+        T: Transport + Clone,
+    {
+        self.next_nonce(provider, address).await
+    }
+}
+
+/// Detects transactions rejected because of their nonce.
+pub trait NonceErrorExt {
+    /// Returns `true` if the node rejected the transaction because its nonce was already used
+    /// (or is ahead of the account), or because it would replace a pending transaction without
+    /// a sufficient fee bump.
+    fn is_nonce_error(&self) -> bool;
+
+    /// Returns `true` if the node rejected the transaction as `nonce too low` or `nonce too high`,
+    /// i.e. the local nonce no longer follows the account's pending transactions.
+    fn is_nonce_mismatch(&self) -> bool;
+}
+
+/// Returns `true` if `error` is an error response whose message contains one of `messages`.
+fn error_matches(error: &TransportError, messages: &[&str]) -> bool {
+    error.as_error_resp().is_some_and(|payload| {
+        let message = payload.message.to_ascii_lowercase();
+        messages.iter().any(|expected| message.contains(expected))
+    })
+}
+
+impl NonceErrorExt for TransportError {
+    fn is_nonce_error(&self) -> bool {
+        error_matches(self, &NONCE_ERRORS)
+    }
+
+    fn is_nonce_mismatch(&self) -> bool {
+        error_matches(self, &NONCE_MISMATCH_ERRORS)
+    }
+}
+
+impl NonceErrorExt for alloy_contract::Error {
+    fn is_nonce_error(&self) -> bool {
+        match self {
+            alloy_contract::Error::TransportError(error) => error.is_nonce_error(),
+            _ => false,
+        }
+    }
+
+    fn is_nonce_mismatch(&self) -> bool {
+        match self {
+            alloy_contract::Error::TransportError(error) => error.is_nonce_mismatch(),
+            _ => false,
+        }
+    }
+}
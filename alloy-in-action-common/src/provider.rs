@@ -0,0 +1,44 @@
+//! Wallet and provider constructors built from a [`Config`].
+
+use alloy_network::{Ethereum, EthereumWallet};
+use alloy_provider::fillers::{FillProvider, JoinFill, RecommendedFillers, WalletFiller};
+use alloy_provider::{Identity, ProviderBuilder, RootProvider, WsConnect};
+use alloy_pubsub::PubSubFrontend;
+use alloy_signer_local::PrivateKeySigner;
+use alloy_transport_http::{Client, Http};
+use eyre::Result;
+use crate::config::Config;
+
+/// Filler stack used by the providers below: gas, blob gas, nonce and chain ID fillers plus a wallet.
+pub type WalletFillers = JoinFill<
+    JoinFill<Identity, <Ethereum as RecommendedFillers>::RecommendedFillers>,
+    WalletFiller<EthereumWallet>,
+>;
+
+/// HTTP provider with the recommended fillers and a signing wallet.
+pub type HttpProvider = FillProvider<WalletFillers, RootProvider<Http<Client>>, Http<Client>, Ethereum>;
+
+/// WebSocket provider with the recommended fillers and a signing wallet.
+pub type WsProvider = FillProvider<WalletFillers, RootProvider<PubSubFrontend>, PubSubFrontend, Ethereum>;
+
+/// Creates a wallet whose default signer is `signer`.
+pub fn wallet(signer: &PrivateKeySigner) -> EthereumWallet {
+    EthereumWallet::from(signer.clone())
+}
+
+/// Connects to `config.rpc_url` over HTTP, signing with the primary signer.
+pub fn http_provider(config: &Config) -> HttpProvider {
+    ProviderBuilder::new()
+        .with_recommended_fillers() // Adds gas estimation, nonce management, and chain ID fetching
+        .wallet(wallet(&config.signer))
+        .on_http(config.rpc_url.clone())
+}
+
+/// Connects to the configured WebSocket endpoint, signing with the primary signer.
+pub async fn ws_provider(config: &Config) -> Result<WsProvider> {
+    let provider = ProviderBuilder::new()
+        .with_recommended_fillers()
+        .wallet(wallet(&config.signer))
+        .on_ws(WsConnect::new(config.ws_url()?.clone())).await?;
+    Ok(provider)
+}
@@ -0,0 +1,439 @@
+//! Bounded queues between event streams and their consumers.
+//!
+//! A listener that handles every event inline on its stream stops reading the stream while a
+//! handler is slow, so a WebSocket subscription backs up behind it. [`EventQueue::spawn`] drives
+//! the stream on a task of its own instead, pushing the events into a bounded queue that the
+//! consumer reads through a [`QueueReceiver`]. When the queue is full, its [`OverflowPolicy`]
+//! decides what happens to the next event:
+//!
+//! - [`OverflowPolicy::Block`] stops reading the stream until the consumer caught up,
+//! - [`OverflowPolicy::DropOldest`] discards the oldest queued event to make room.
+//!
+//! [`EventQueue::spawn_spilling`] never loses events instead: it appends them to a temporary
+//! file until the consumer caught up, which requires the events to be serializable.
+//!
+//! [`QueueReceiver::metrics`] reports the depth of the queue and how many blocks the consumer
+//! lags behind the stream, to size consumers and queues.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Seek, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use alloy_primitives::{Address, BlockHash, TxHash};
+use futures::{Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+use thiserror::Error;
+use tokio::sync::Notify;
+use tokio::task::AbortHandle;
+use crate::event_stream::{EventLog, EventStreamError, StreamEvent};
+
+/// Number of events an [`EventQueue`] holds in memory by default.
+pub const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+
+/// What an [`EventQueue`] does with an event when it is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Stop reading the stream until the consumer took an event.
+    Block,
+    /// Discard the oldest queued event.
+    DropOldest,
+}
+
+/// A snapshot of the state of an event queue.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QueueMetrics {
+    /// Number of events waiting for the consumer, in memory and on disk.
+    pub depth: usize,
+    /// Number of the waiting events that were spilled to disk.
+    pub spilled: usize,
+    /// Number of events discarded because the queue was full, or lost on disk as they could not
+    /// be written or read back.
+    pub dropped: u64,
+    /// Number of blocks between the oldest waiting event and the newest event read from the
+    /// stream, `0` if no event is waiting.
+    pub lag_blocks: u64,
+}
+
+/// Errors delivered by a [`QueueReceiver`].
+#[derive(Debug, Error)]
+pub enum QueueError {
+    /// The queued stream failed.
+    #[error(transparent)]
+    Stream(#[from] EventStreamError),
+    /// Spilling events to disk, or reading them back, failed.
+    #[error("failed to spill events to disk: {0}")]
+    Spill(#[from] io::Error),
+}
+
+/// Configures a bounded queue between an event stream and its consumer.
+#[derive(Clone, Debug)]
+pub struct EventQueue {
+    capacity: usize,
+    overflow: OverflowPolicy,
+}
+
+impl Default for EventQueue {
+    fn default() -> Self {
+        Self::new(DEFAULT_QUEUE_CAPACITY)
+    }
+}
+
+impl EventQueue {
+    /// Creates a queue holding up to `capacity` events in memory, blocking when it is full.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is `0`.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "queue capacity must be positive");
+        Self { capacity, overflow: OverflowPolicy::Block }
+    }
+
+    /// Sets what happens to an event when the queue is full.
+    pub fn with_overflow(mut self, overflow: OverflowPolicy) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Spawns a task reading `events` into the queue, returning the receiving end.
+    ///
+    /// An error ends the stream: it is delivered after the events queued before it. Dropping
+    /// the receiver stops the task.
+    ///
+    /// Must be called within a Tokio runtime.
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - The stream to read, e.g. from [`EventStream::subscribe`](crate::EventStream::subscribe).
+    pub fn spawn<E, S>(self, events: S) -> QueueReceiver<E>
+    where
+        E: Send + Sync + 'static,
+        S: Stream<Item = Result<StreamEvent<E>, EventStreamError>> + Send + 'static,
+    {
+        let drop_oldest = self.overflow == OverflowPolicy::DropOldest;
+        self.start(events, None, drop_oldest)
+    }
+
+    /// Spawns a task reading `events` into the queue like [`spawn`](Self::spawn), but appends
+    /// the events to a temporary file in `dir` while the queue is full, whatever its
+    /// [`OverflowPolicy`].
+    ///
+    /// Spilled events are serialized as JSON lines, and the file is removed once the queue is
+    /// gone. An event that cannot be written or read back is dropped and counted in
+    /// [`QueueMetrics::dropped`].
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory to create the spill file in.
+    /// * `events` - The stream to read.
+    ///
+    /// # Returns
+    ///
+    /// The receiver, or an error if the spill file cannot be created.
+    pub fn spawn_spilling<E, S>(self, dir: impl AsRef<Path>, events: S) -> io::Result<QueueReceiver<E>>
+    where
+        E: Serialize + DeserializeOwned + Send + Sync + 'static,
+        S: Stream<Item = Result<StreamEvent<E>, EventStreamError>> + Send + 'static,
+    {
+        let spill = SpillFile::create(dir.as_ref())?;
+        Ok(self.start(events, Some(spill), false))
+    }
+
+    fn start<E, S>(self, events: S, spill: Option<SpillFile<E>>, drop_oldest: bool) -> QueueReceiver<E>
+    where
+        E: Send + Sync + 'static,
+        S: Stream<Item = Result<StreamEvent<E>, EventStreamError>> + Send + 'static,
+    {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                memory: VecDeque::with_capacity(self.capacity),
+                spill,
+                dropped: 0,
+                newest_block: 0,
+                closed: false,
+                error: None,
+            }),
+            capacity: self.capacity,
+            drop_oldest,
+            pushed: Notify::new(),
+            taken: Notify::new(),
+        });
+
+        let producer = shared.clone();
+        let task = tokio::spawn(async move {
+            futures::pin_mut!(events);
+            let error = loop {
+                match events.next().await {
+                    Some(Ok(event)) => {
+                        if let Err(e) = producer.push(event).await {
+                            break Some(QueueError::Spill(e));
+                        }
+                    }
+                    Some(Err(e)) => break Some(QueueError::Stream(e)),
+                    None => break None,
+                }
+            };
+            let mut state = producer.lock();
+            state.closed = true;
+            state.error = error;
+            producer.pushed.notify_one();
+        });
+
+        QueueReceiver { shared, task: task.abort_handle() }
+    }
+}
+
+/// The consuming end of an [`EventQueue`].
+#[derive(Debug)]
+pub struct QueueReceiver<E> {
+    shared: Arc<Shared<E>>,
+    task: AbortHandle,
+}
+
+impl<E> QueueReceiver<E> {
+    /// Waits for the next event, returning `None` once the stream ended and every queued event
+    /// was taken.
+    pub async fn recv(&mut self) -> Option<Result<StreamEvent<E>, QueueError>> {
+        loop {
+            {
+                let mut state = self.shared.lock();
+                let state = &mut *state;
+                let next = match state.memory.pop_front() {
+                    Some(event) => Some(Ok(event)),
+                    None => match &mut state.spill {
+                        Some(spill) => spill.pop(&mut state.dropped).transpose(),
+                        None => None,
+                    },
+                };
+                if let Some(next) = next {
+                    self.shared.taken.notify_one();
+                    return Some(next.map_err(QueueError::Spill));
+                }
+                if state.closed {
+                    return state.error.take().map(Err);
+                }
+            }
+            self.shared.pushed.notified().await;
+        }
+    }
+
+    /// Converts the receiver into a stream of its events.
+    pub fn into_stream(self) -> impl Stream<Item = Result<StreamEvent<E>, QueueError>> {
+        futures::stream::unfold(self, |mut receiver| async move {
+            let next = receiver.recv().await?;
+            Some((next, receiver))
+        })
+    }
+
+    /// Returns the current depth, lag and drop count of the queue.
+    pub fn metrics(&self) -> QueueMetrics {
+        let state = self.shared.lock();
+        let spilled = state.spill.as_ref().map_or(0, |spill| spill.blocks.len());
+        let oldest_block = state
+            .memory
+            .front()
+            .map(|event| event.log().block_number)
+            .or_else(|| state.spill.as_ref().and_then(|spill| spill.blocks.front().copied()));
+        QueueMetrics {
+            depth: state.memory.len() + spilled,
+            spilled,
+            dropped: state.dropped,
+            lag_blocks: oldest_block.map_or(0, |block| state.newest_block.saturating_sub(block)),
+        }
+    }
+}
+
+impl<E> Drop for QueueReceiver<E> {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[derive(Debug)]
+struct Shared<E> {
+    state: Mutex<State<E>>,
+    capacity: usize,
+    drop_oldest: bool,
+    /// Signals the receiver that an event was queued or the stream ended.
+    pushed: Notify,
+    /// Signals a blocked producer that an event was taken.
+    taken: Notify,
+}
+
+#[derive(Debug)]
+struct State<E> {
+    memory: VecDeque<StreamEvent<E>>,
+    spill: Option<SpillFile<E>>,
+    dropped: u64,
+    newest_block: u64,
+    closed: bool,
+    error: Option<QueueError>,
+}
+
+impl<E> Shared<E> {
+    fn lock(&self) -> MutexGuard<'_, State<E>> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Queues an event, waiting for room first if the queue blocks when full.
+    async fn push(&self, event: StreamEvent<E>) -> io::Result<()> {
+        loop {
+            let has_room = {
+                let state = self.lock();
+                state.spill.is_some() || self.drop_oldest || state.memory.len() < self.capacity
+            };
+            if has_room {
+                break;
+            }
+            self.taken.notified().await;
+        }
+
+        let block_number = event.log().block_number;
+        let mut state = self.lock();
+        let state = &mut *state;
+        let full = state.memory.len() >= self.capacity;
+        match &mut state.spill {
+            // Once events are spilled, the following ones go to disk as well to keep their order
+            Some(spill) if full || !spill.is_empty() => {
+                if !spill.push(&event, block_number)? {
+                    state.dropped += 1;
+                }
+            }
+            _ => {
+                if full {
+                    state.memory.pop_front();
+                    state.dropped += 1;
+                }
+                state.memory.push_back(event);
+            }
+        }
+        state.newest_block = state.newest_block.max(block_number);
+        self.pushed.notify_one();
+        Ok(())
+    }
+}
+
+/// Events spilled to disk as JSON lines, read back in order.
+#[derive(Debug)]
+struct SpillFile<E> {
+    /// The file, removed when dropped.
+    writer: NamedTempFile,
+    reader: BufReader<File>,
+    /// Block numbers of the spilled events not read back yet.
+    blocks: VecDeque<u64>,
+    encode: fn(&StreamEvent<E>) -> serde_json::Result<Vec<u8>>,
+    decode: fn(&str) -> serde_json::Result<StreamEvent<E>>,
+}
+
+impl<E> SpillFile<E> {
+    fn create(dir: &Path) -> io::Result<Self>
+    where
+        E: Serialize + DeserializeOwned,
+    {
+        let writer = NamedTempFile::new_in(dir)?;
+        let reader = BufReader::new(writer.reopen()?);
+        Ok(Self { writer, reader, blocks: VecDeque::new(), encode: encode::<E>, decode: decode::<E> })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Appends an event, returning `false` if it cannot be serialized.
+    fn push(&mut self, event: &StreamEvent<E>, block_number: u64) -> io::Result<bool> {
+        let Ok(mut line) = (self.encode)(event) else { return Ok(false) };
+        line.push(b'\n');
+        self.writer.write_all(&line)?;
+        self.blocks.push_back(block_number);
+        Ok(true)
+    }
+
+    /// Reads back the oldest decodable event, counting the events lost on the way in `dropped`.
+    ///
+    /// A line that cannot be decoded is skipped. If the file cannot be read, the events left in
+    /// it are lost.
+    fn pop(&mut self, dropped: &mut u64) -> io::Result<Option<StreamEvent<E>>> {
+        let mut event = None;
+        while event.is_none() && self.blocks.pop_front().is_some() {
+            let mut line = String::new();
+            if let Err(error) = self.reader.read_line(&mut line) {
+                *dropped += 1 + self.blocks.len() as u64;
+                self.blocks.clear();
+                self.reset()?;
+                return Err(error);
+            }
+            match (self.decode)(&line) {
+                Ok(decoded) => event = Some(decoded),
+                Err(_) => *dropped += 1,
+            }
+        }
+        // Start over once everything was read back, so the file does not grow forever
+        if self.blocks.is_empty() {
+            self.reset()?;
+        }
+        Ok(event)
+    }
+
+    fn reset(&mut self) -> io::Result<()> {
+        self.writer.as_file().set_len(0)?;
+        self.writer.rewind()?;
+        self.reader.rewind()
+    }
+}
+
+/// The transition of a spilled event.
+#[derive(Serialize, Deserialize)]
+enum Transition {
+    Observed,
+    Retracted,
+    Finalized,
+}
+
+/// A [`StreamEvent`] as spilled to disk, with the event data `D`.
+#[derive(Serialize, Deserialize)]
+struct SpilledEvent<D> {
+    transition: Transition,
+    block_number: u64,
+    block_hash: BlockHash,
+    transaction_hash: TxHash,
+    log_index: u64,
+    address: Address,
+    data: D,
+}
+
+fn encode<E: Serialize>(event: &StreamEvent<E>) -> serde_json::Result<Vec<u8>> {
+    let (transition, log) = match event {
+        StreamEvent::Observed(log) => (Transition::Observed, log),
+        StreamEvent::Retracted(log) => (Transition::Retracted, log),
+        StreamEvent::Finalized(log) => (Transition::Finalized, log),
+    };
+    serde_json::to_vec(&SpilledEvent {
+        transition,
+        block_number: log.block_number,
+        block_hash: log.block_hash,
+        transaction_hash: log.transaction_hash,
+        log_index: log.log_index,
+        address: log.address,
+        data: &log.data,
+    })
+}
+
+fn decode<E: DeserializeOwned>(line: &str) -> serde_json::Result<StreamEvent<E>> {
+    let spilled: SpilledEvent<E> = serde_json::from_str(line)?;
+    let log = Arc::new(EventLog {
+        block_number: spilled.block_number,
+        block_hash: spilled.block_hash,
+        transaction_hash: spilled.transaction_hash,
+        log_index: spilled.log_index,
+        address: spilled.address,
+        data: spilled.data,
+    });
+    Ok(match spilled.transition {
+        Transition::Observed => StreamEvent::Observed(log),
+        Transition::Retracted => StreamEvent::Retracted(log),
+        Transition::Finalized => StreamEvent::Finalized(log),
+    })
+}
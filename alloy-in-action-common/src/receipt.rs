@@ -0,0 +1,133 @@
+//! Extraction of decoded contract events from transaction receipts.
+
+use alloy_primitives::{Address, LogData};
+use alloy_rpc_types::{Log, TransactionReceipt};
+use alloy_sol_types::{SolEvent, SolEventInterface};
+
+/// An event decoded from one log of a receipt.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodedEvent<E> {
+    /// Index of the log in its block.
+    pub log_index: Option<u64>,
+    /// Address of the contract that emitted the log.
+    pub address: Address,
+    /// The decoded event.
+    pub data: E,
+}
+
+/// A log that could not be decoded as an event of the requested interface.
+#[derive(Clone, Debug)]
+pub struct UndecodedLog {
+    /// Index of the log in its block.
+    pub log_index: Option<u64>,
+    /// Address of the contract that emitted the log.
+    pub address: Address,
+    /// Topics and data of the log.
+    pub log: LogData,
+    /// Why decoding failed, e.g. an unknown event signature.
+    pub error: alloy_sol_types::Error,
+}
+
+/// All logs of a receipt, split into decoded events of `E` and undecodable logs.
+#[derive(Clone, Debug)]
+pub struct ReceiptEvents<E> {
+    /// Decoded events, in log order.
+    pub decoded: Vec<DecodedEvent<E>>,
+    /// Logs that are not events of `E` (or are malformed), in log order.
+    pub undecoded: Vec<UndecodedLog>,
+}
+
+impl<E: SolEventInterface> ReceiptEvents<E> {
+    /// Decodes all logs of `receipt`.
+    pub fn from_receipt(receipt: &TransactionReceipt) -> Self {
+        Self::from_logs(receipt.inner.logs())
+    }
+
+    /// Decodes the given logs.
+    pub fn from_logs(logs: &[Log]) -> Self {
+        let mut decoded = Vec::new();
+        let mut undecoded = Vec::new();
+
+        for log in logs {
+            match E::decode_log(&log.inner, true) {
+                Ok(event) => decoded.push(DecodedEvent {
+                    log_index: log.log_index,
+                    address: log.address(),
+                    data: event.data,
+                }),
+                Err(error) => undecoded.push(UndecodedLog {
+                    log_index: log.log_index,
+                    address: log.address(),
+                    log: log.inner.data.clone(),
+                    error,
+                }),
+            }
+        }
+
+        Self { decoded, undecoded }
+    }
+
+    /// Iterates over the decoded events.
+    pub fn iter(&self) -> impl Iterator<Item = &E> {
+        self.decoded.iter().map(|event| &event.data)
+    }
+}
+
+impl<E> IntoIterator for ReceiptEvents<E> {
+    type Item = DecodedEvent<E>;
+    type IntoIter = std::vec::IntoIter<DecodedEvent<E>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.decoded.into_iter()
+    }
+}
+
+/// Event accessors for transaction receipts.
+pub trait ReceiptExt {
+    /// Decodes every log as an event of the interface `E`, reporting undecodable logs separately.
+    fn decode_events<E: SolEventInterface>(&self) -> ReceiptEvents<E>;
+
+    /// Returns all events of type `T`, in log order.
+    ///
+    /// # Returns
+    ///
+    /// The events, or the error decoding the first log with the signature of `T` that is not a
+    /// valid `T` event, e.g. one emitted by another contract with different indexed arguments.
+    fn events<T: SolEvent>(&self) -> Result<Vec<T>, alloy_sol_types::Error>;
+
+    /// Returns the single event of type `T`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the receipt does not contain exactly one event of type `T`, or a log with the
+    /// signature of `T` cannot be decoded. Intended for tests.
+    fn expect_event<T: SolEvent>(&self) -> T;
+}
+
+impl ReceiptExt for TransactionReceipt {
+    fn decode_events<E: SolEventInterface>(&self) -> ReceiptEvents<E> {
+        ReceiptEvents::from_receipt(self)
+    }
+
+    fn events<T: SolEvent>(&self) -> Result<Vec<T>, alloy_sol_types::Error> {
+        self.inner
+            .logs()
+            .iter()
+            .filter(|log| log.topics().first() == Some(&T::SIGNATURE_HASH))
+            .map(|log| T::decode_log_data(log.data(), true))
+            .collect()
+    }
+
+    fn expect_event<T: SolEvent>(&self) -> T {
+        let mut events = self.events::<T>().unwrap_or_else(|error| {
+            panic!("failed to decode {} event in transaction {:#x}: {}", T::SIGNATURE, self.transaction_hash, error)
+        });
+        match events.len() {
+            1 => events.remove(0),
+            n => panic!(
+                "expected exactly one {} event in transaction {:#x}, found {}",
+                T::SIGNATURE, self.transaction_hash, n
+            ),
+        }
+    }
+}
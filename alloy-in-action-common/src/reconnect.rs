@@ -0,0 +1,53 @@
+//! Backoff between reconnection attempts.
+//!
+//! Alloy's WebSocket transport tries to reconnect once when the connection drops; if that fails,
+//! every subscription stream on it just ends. Long running consumers such as
+//! [`EventStream::subscribe_with_reconnect`](crate::EventStream::subscribe_with_reconnect)
+//! instead keep connecting again, waiting longer after each failed attempt as described by a
+//! [`ReconnectPolicy`].
+
+use std::time::Duration;
+
+/// Delay before the first reconnection attempt by default.
+pub const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Longest delay between reconnection attempts by default.
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Exponential backoff between reconnection attempts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self::new(DEFAULT_INITIAL_BACKOFF, DEFAULT_MAX_BACKOFF)
+    }
+}
+
+impl ReconnectPolicy {
+    /// Doubles the delay after every failed attempt, starting at `initial_backoff` and capped at
+    /// `max_backoff`, retrying forever.
+    pub fn new(initial_backoff: Duration, max_backoff: Duration) -> Self {
+        Self { initial_backoff, max_backoff: max_backoff.max(initial_backoff), max_attempts: None }
+    }
+
+    /// Gives up after `max_attempts` consecutive failed attempts.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+
+    /// Returns the delay before attempt number `attempt` (starting at 0), or `None` once the
+    /// attempts are exhausted.
+    pub fn backoff(&self, attempt: u32) -> Option<Duration> {
+        if self.max_attempts.is_some_and(|max_attempts| attempt >= max_attempts) {
+            return None;
+        }
+        let factor = 2u32.checked_pow(attempt).unwrap_or(u32::MAX);
+        Some(self.initial_backoff.saturating_mul(factor).min(self.max_backoff))
+    }
+}
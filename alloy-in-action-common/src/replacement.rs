@@ -0,0 +1,257 @@
+//! Sending transactions that replace themselves while stuck.
+//!
+//! `provider.send_transaction(tx).await?.watch()` waits forever for an underpriced transaction.
+//! [`ReplacingSender`] instead re-sends the transaction with the same nonce and bumped fees
+//! whenever it has not been mined within a timeout, keeps the hashes of all versions it sent
+//! and resolves to whichever of them is mined.
+
+use std::time::Duration;
+use alloy_network::{Ethereum, TransactionBuilder};
+use alloy_primitives::TxHash;
+use alloy_provider::utils::Eip1559Estimation;
+use alloy_provider::{Provider, WalletProvider};
+use alloy_rpc_types::{TransactionReceipt, TransactionRequest};
+use alloy_transport::{Transport, TransportError};
+use thiserror::Error;
+use crate::fees::{complete_fees, FeeEstimator};
+use crate::nonce::NonceErrorExt;
+
+/// Minimum fee increase (in percent) nodes require to replace a pending transaction.
+pub const MIN_REPLACEMENT_BUMP_PERCENT: u64 = 10;
+
+/// How long to wait for a transaction to be mined before replacing it by default.
+pub const DEFAULT_REPLACEMENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often to poll for receipts and new blocks by default.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Maximum number of replacements sent by default.
+pub const DEFAULT_MAX_REPLACEMENTS: usize = 5;
+
+/// Errors raised while sending a transaction with [`ReplacingSender`].
+#[derive(Debug, Error)]
+pub enum ReplacementError {
+    /// Sending the transaction or querying the node failed.
+    #[error(transparent)]
+    Transport(#[from] TransportError),
+    /// None of the sent versions was mined before the last replacement timed out.
+    #[error("none of the {} sent versions of the transaction was mined", hashes.len())]
+    NotMined {
+        /// Hashes of all sent versions, in sending order.
+        hashes: Vec<TxHash>,
+    },
+}
+
+impl NonceErrorExt for ReplacementError {
+    fn is_nonce_error(&self) -> bool {
+        match self {
+            ReplacementError::Transport(error) => error.is_nonce_error(),
+            ReplacementError::NotMined { .. } => false,
+        }
+    }
+
+    fn is_nonce_mismatch(&self) -> bool {
+        match self {
+            ReplacementError::Transport(error) => error.is_nonce_mismatch(),
+            ReplacementError::NotMined { .. } => false,
+        }
+    }
+}
+
+/// A transaction mined by [`ReplacingSender::send`].
+#[derive(Clone, Debug)]
+pub struct SentTransaction {
+    /// Receipt of the mined version.
+    pub receipt: TransactionReceipt,
+    /// Hashes of all sent versions, in sending order; the mined one is `receipt.transaction_hash`.
+    pub hashes: Vec<TxHash>,
+}
+
+impl SentTransaction {
+    /// Hash of the mined version.
+    pub fn tx_hash(&self) -> TxHash {
+        self.receipt.transaction_hash
+    }
+
+    /// Whether the mined version is a replacement rather than the originally sent one.
+    pub fn was_replaced(&self) -> bool {
+        self.hashes.first() != Some(&self.receipt.transaction_hash)
+    }
+}
+
+/// Sends EIP-1559 transactions and replaces them with bumped fees while they are not mined.
+#[derive(Clone, Debug)]
+pub struct ReplacingSender {
+    estimator: FeeEstimator,
+    timeout: Duration,
+    poll_interval: Duration,
+    bump_percent: u64,
+    max_replacements: usize,
+    max_fee_cap: Option<u128>,
+    confirmations: u64,
+}
+
+impl Default for ReplacingSender {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReplacingSender {
+    /// Creates a sender that replaces a transaction every 30 seconds with 10% higher fees, up to
+    /// 5 times, and resolves once it has 1 confirmation.
+    pub fn new() -> Self {
+        Self {
+            estimator: FeeEstimator::default(),
+            timeout: DEFAULT_REPLACEMENT_TIMEOUT,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            bump_percent: MIN_REPLACEMENT_BUMP_PERCENT,
+            max_replacements: DEFAULT_MAX_REPLACEMENTS,
+            max_fee_cap: None,
+            confirmations: 1,
+        }
+    }
+
+    /// Sets the estimator for the fees of transactions sent without them.
+    pub fn with_estimator(mut self, estimator: FeeEstimator) -> Self {
+        self.estimator = estimator;
+        self
+    }
+
+    /// Sets how long to wait for a version to be mined and confirmed before replacing it.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets how often to poll for receipts and new blocks.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Sets the fee increase per replacement in percent, at least [`MIN_REPLACEMENT_BUMP_PERCENT`].
+    pub fn with_bump_percent(mut self, bump_percent: u64) -> Self {
+        self.bump_percent = bump_percent.max(MIN_REPLACEMENT_BUMP_PERCENT);
+        self
+    }
+
+    /// Sets the maximum number of replacements.
+    pub fn with_max_replacements(mut self, max_replacements: usize) -> Self {
+        self.max_replacements = max_replacements;
+        self
+    }
+
+    /// Stops replacing once `maxFeePerGas` would exceed `max_fee_cap` wei.
+    pub fn with_max_fee_cap(mut self, max_fee_cap: u128) -> Self {
+        self.max_fee_cap = Some(max_fee_cap);
+        self
+    }
+
+    /// Sets the number of confirmations to wait for once a version is mined.
+    pub fn with_confirmations(mut self, confirmations: u64) -> Self {
+        self.confirmations = confirmations.max(1);
+        self
+    }
+
+    /// Sends `tx`, replacing it while it is not mined, and waits for the configured confirmations.
+    ///
+    /// A missing sender, nonce, gas limit or fees are filled in first, so that every version
+    /// shares them apart from the fees.
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - Provider with a wallet signing for the sender of `tx`.
+    /// * `tx` - The transaction to send.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<SentTransaction, ReplacementError>` - The receipt of the mined version and the
+    ///   hashes of all sent versions.
+    pub async fn send<P, T>(&self, provider: &P, tx: TransactionRequest) -> Result<SentTransaction, ReplacementError>
+    where
+        P: Provider<T, Ethereum> + WalletProvider<Ethereum>,
+        T: Transport + Clone,
+    {
+        // Pin everything but the fees, so each version replaces the previous one
+        let mut tx = tx;
+        let from = *tx.from.get_or_insert_with(|| provider.default_signer_address());
+        if tx.nonce.is_none() {
+            tx.set_nonce(provider.get_transaction_count(from).pending().await?);
+        }
+        if tx.gas.is_none() {
+            tx.set_gas_limit(provider.estimate_gas(&tx).await?);
+        }
+        let mut fees = match (tx.max_fee_per_gas, tx.max_priority_fee_per_gas) {
+            (Some(max_fee_per_gas), Some(max_priority_fee_per_gas)) => {
+                Eip1559Estimation { max_fee_per_gas, max_priority_fee_per_gas }
+            }
+            (max_fee_per_gas, max_priority_fee_per_gas) => {
+                complete_fees(max_fee_per_gas, max_priority_fee_per_gas, self.estimator.estimate(provider).await?)
+            }
+        };
+
+        let mut hashes = Vec::new();
+        let mut replacements = 0;
+        loop {
+            let version = tx
+                .clone()
+                .with_max_fee_per_gas(fees.max_fee_per_gas)
+                .with_max_priority_fee_per_gas(fees.max_priority_fee_per_gas);
+            match provider.send_transaction(version).await {
+                Ok(pending) => hashes.push(*pending.tx_hash()),
+                // A previous version was mined meanwhile, or the node wants a higher bump
+                Err(error) if !hashes.is_empty() && error.is_nonce_error() => {}
+                Err(error) => return Err(error.into()),
+            }
+
+            if let Some(receipt) = self.wait_for_receipt(provider, &hashes).await? {
+                return Ok(SentTransaction { receipt, hashes });
+            }
+
+            // Not mined in time: replace with bumped fees, unless out of replacements or budget
+            let bumped = bump_fees(fees, self.bump_percent);
+            let within_cap = self.max_fee_cap.is_none_or(|cap| bumped.max_fee_per_gas <= cap);
+            if replacements == self.max_replacements || !within_cap {
+                return Err(ReplacementError::NotMined { hashes });
+            }
+            replacements += 1;
+            fees = bumped;
+        }
+    }
+
+    /// Polls for a receipt of any of `hashes` with the configured confirmations until the
+    /// timeout.
+    ///
+    /// The receipt is fetched again at every poll, so that a version whose block was
+    /// reorganized away before it was confirmed is not reported.
+    async fn wait_for_receipt<P, T>(&self, provider: &P, hashes: &[TxHash]) -> Result<Option<TransactionReceipt>, ReplacementError>
+    where
+        P: Provider<T, Ethereum>,
+        T: Transport + Clone,
+    {
+        let deadline = tokio::time::Instant::now() + self.timeout;
+        while tokio::time::Instant::now() < deadline {
+            for hash in hashes {
+                let Some(receipt) = provider.get_transaction_receipt(*hash).await? else { continue };
+                let Some(block_number) = receipt.block_number else { continue };
+                if provider.get_block_number().await? >= block_number + self.confirmations - 1 {
+                    return Ok(Some(receipt));
+                }
+            }
+            tokio::time::sleep(self.poll_interval).await;
+        }
+        Ok(None)
+    }
+}
+
+/// Raises both fees by `percent` percent (rounding up), as required to replace a pending
+/// transaction, keeping the priority fee at or below the max fee.
+pub fn bump_fees(fees: Eip1559Estimation, percent: u64) -> Eip1559Estimation {
+    let bump = |fee: u128| (fee * (100 + percent as u128)).div_ceil(100).max(fee + 1);
+    let max_fee_per_gas = bump(fees.max_fee_per_gas);
+    Eip1559Estimation {
+        max_fee_per_gas,
+        max_priority_fee_per_gas: bump(fees.max_priority_fee_per_gas).min(max_fee_per_gas),
+    }
+}
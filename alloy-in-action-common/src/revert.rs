@@ -0,0 +1,109 @@
+//! Typed decoding of contract reverts.
+//!
+//! Nodes report reverts as JSON-RPC error responses carrying the ABI-encoded revert data. The
+//! [`RevertExt`] trait extracts that data from [`alloy_contract::Error`] (or a bare
+//! [`TransportError`]) and decodes it into a [`Revert`], so every call site can handle custom
+//! errors, `Error(string)`, `Panic(uint256)` and out-of-gas failures the same way.
+
+use std::fmt;
+use alloy_json_rpc::ErrorPayload;
+use alloy_primitives::{Bytes, Selector, U256};
+use alloy_sol_types::{ContractError, PanicKind, SolInterface};
+use alloy_transport::TransportError;
+
+/// Reason a contract call or transaction reverted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Revert<E> {
+    /// A custom error declared by the contract interface `E`.
+    Custom(E),
+    /// `Error(string)`, raised by `require(cond, "...")` and `revert("...")`.
+    Error(String),
+    /// `Panic(uint256)`, raised by failing assertions, arithmetic errors and the like.
+    Panic {
+        /// The raw panic code.
+        code: U256,
+        /// The named panic code, if `code` is one defined by Solidity.
+        kind: Option<PanicKind>,
+    },
+    /// Execution ran out of gas.
+    OutOfGas,
+    /// Revert data that does not match any known error, or no revert data at all.
+    Unknown {
+        /// The leading four bytes of `data`, if present.
+        selector: Option<Selector>,
+        /// The raw revert data.
+        data: Bytes,
+    },
+}
+
+impl<E: SolInterface> Revert<E> {
+    /// Decodes raw revert data into a custom error of `E`, `Error(string)` or `Panic(uint256)`.
+    pub fn from_revert_data(data: &[u8]) -> Self {
+        match ContractError::<E>::abi_decode(data, true) {
+            Ok(ContractError::CustomError(error)) => Revert::Custom(error),
+            Ok(ContractError::Revert(revert)) => Revert::Error(revert.reason),
+            Ok(ContractError::Panic(panic)) => Revert::Panic { code: panic.code, kind: panic.kind() },
+            Err(_) => Revert::Unknown {
+                selector: data.get(..4).map(Selector::from_slice),
+                data: Bytes::copy_from_slice(data),
+            },
+        }
+    }
+
+    /// Decodes the revert carried by a JSON-RPC error response, if it reports one.
+    pub fn from_error_payload(payload: &ErrorPayload) -> Option<Self> {
+        let message = payload.message.to_ascii_lowercase();
+        if message.contains("out of gas") || message.contains("outofgas") {
+            return Some(Revert::OutOfGas);
+        }
+        match payload.as_revert_data() {
+            Some(data) => Some(Self::from_revert_data(&data)),
+            // A revert without data, e.g. a bare `revert()` or `require(cond)`
+            None if message.contains("revert") => Some(Revert::Unknown { selector: None, data: Bytes::new() }),
+            None => None,
+        }
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for Revert<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Revert::Custom(error) => write!(f, "custom error {error:?}"),
+            Revert::Error(reason) => write!(f, "Error({reason:?})"),
+            Revert::Panic { code, kind: Some(kind) } => write!(f, "Panic({code:#x}): {kind}"),
+            Revert::Panic { code, kind: None } => write!(f, "Panic({code:#x})"),
+            Revert::OutOfGas => f.write_str("out of gas"),
+            Revert::Unknown { selector: Some(selector), data } => {
+                write!(f, "unknown error with selector {selector} ({data})")
+            }
+            Revert::Unknown { selector: None, .. } => f.write_str("revert without data"),
+        }
+    }
+}
+
+/// Decodes the revert behind a failed contract call or transaction.
+pub trait RevertExt {
+    /// Returns the decoded revert, or `None` if the error is not a revert (e.g. a connection
+    /// failure or an ABI error on our side).
+    fn revert<E: SolInterface>(&self) -> Option<Revert<E>>;
+}
+
+impl RevertExt for TransportError {
+    fn revert<E: SolInterface>(&self) -> Option<Revert<E>> {
+        self.as_error_resp().and_then(Revert::from_error_payload)
+    }
+}
+
+impl RevertExt for alloy_contract::Error {
+    fn revert<E: SolInterface>(&self) -> Option<Revert<E>> {
+        match self {
+            alloy_contract::Error::TransportError(error) => error.revert(),
+            _ => None,
+        }
+    }
+}
+
+/// Decodes the revert behind `error`, see [`RevertExt::revert`].
+pub fn decode_revert<E: SolInterface>(error: &alloy_contract::Error) -> Option<Revert<E>> {
+    error.revert()
+}
@@ -0,0 +1,364 @@
+//! Supervising listener tasks until they are done.
+//!
+//! A [`Supervisor`] owns the tasks that consume event streams. It runs until one of:
+//!
+//! - all the [`Completion`] conditions added with [`Supervisor::until`] hold, or any of those
+//!   added with [`Supervisor::until_any`], e.g. "2 `ValueChanged` events" or "block 100", as
+//!   reported by the listeners through their [`Progress`] handle,
+//! - SIGINT (Ctrl+C) or SIGTERM is received,
+//! - every task exited on its own.
+//!
+//! It then asks the tasks to shut down and waits for them to drain: a listener that is
+//! [handling](Shutdown) an event finishes it before stopping, and a task that does not stop
+//! within the drain timeout is aborted. [`Supervisor::run`] returns a [`Summary`] of why it
+//! stopped, what was processed and how each task ended.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::time::Duration;
+use alloy_sol_types::SolEvent;
+use futures::FutureExt;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use crate::handlers::{event_name, panic_message};
+
+/// How long the tasks get to drain after the shutdown was requested by default.
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A condition under which the supervised work is complete.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Completion {
+    /// At least `count` events recorded under `name`.
+    Events { name: String, count: u64 },
+    /// An event or block at least this high recorded.
+    Block(u64),
+}
+
+impl Completion {
+    /// Complete after `count` events of type `E`.
+    pub fn events<E: SolEvent>(count: u64) -> Self {
+        Self::named(event_name::<E>(), count)
+    }
+
+    /// Complete after `count` events recorded under `name`.
+    pub fn named(name: impl Into<String>, count: u64) -> Self {
+        Self::Events { name: name.into(), count }
+    }
+
+    /// Complete once block `block_number` is reached.
+    pub fn block(block_number: u64) -> Self {
+        Self::Block(block_number)
+    }
+
+    fn holds(&self, state: &ProgressState) -> bool {
+        match self {
+            Completion::Events { name, count } => state.events.get(name).copied().unwrap_or_default() >= *count,
+            Completion::Block(block_number) => state.block.is_some_and(|block| block >= *block_number),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct ProgressState {
+    events: BTreeMap<String, u64>,
+    block: Option<u64>,
+}
+
+/// Handle through which listeners report the events they processed.
+#[derive(Clone, Debug)]
+pub struct Progress {
+    state: Arc<watch::Sender<ProgressState>>,
+}
+
+impl Progress {
+    /// Records a processed event of type `E` in block `block_number`.
+    pub fn record<E: SolEvent>(&self, block_number: u64) {
+        self.record_named(event_name::<E>(), block_number);
+    }
+
+    /// Records a processed event under `name` in block `block_number`.
+    pub fn record_named(&self, name: &str, block_number: u64) {
+        self.state.send_modify(|state| {
+            *state.events.entry(name.to_string()).or_default() += 1;
+            state.block = state.block.max(Some(block_number));
+        });
+    }
+
+    /// Takes back a recorded event of type `E`, e.g. when its block was reorganized away.
+    pub fn retract<E: SolEvent>(&self) {
+        self.retract_named(event_name::<E>());
+    }
+
+    /// Takes back an event recorded under `name`.
+    pub fn retract_named(&self, name: &str) {
+        self.state.send_if_modified(|state| match state.events.get_mut(name) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                true
+            }
+            _ => false,
+        });
+    }
+
+    /// Records that block `block_number` was reached, e.g. by a new head or a finalized event.
+    pub fn record_block(&self, block_number: u64) {
+        self.state.send_if_modified(|state| {
+            let reached = state.block.is_none_or(|block| block < block_number);
+            if reached {
+                state.block = Some(block_number);
+            }
+            reached
+        });
+    }
+}
+
+/// Tells a supervised task when to stop.
+#[derive(Clone, Debug)]
+pub struct Shutdown {
+    requested: watch::Receiver<bool>,
+}
+
+impl Shutdown {
+    /// Resolves once the shutdown was requested (or the supervisor is gone).
+    ///
+    /// Listeners select on it next to their stream, handling each received event in the body
+    /// of the `select!` so that the event in flight is finished before stopping:
+    ///
+    /// ```ignore
+    /// loop {
+    ///     tokio::select! {
+    ///         _ = shutdown.requested() => break,
+    ///         Some(event) = events.next() => handle(event).await,
+    ///     }
+    /// }
+    /// ```
+    pub async fn requested(&mut self) {
+        let _ = self.requested.wait_for(|requested| *requested).await;
+    }
+
+    /// Returns `true` if the shutdown was requested.
+    pub fn is_requested(&self) -> bool {
+        *self.requested.borrow()
+    }
+}
+
+/// Why a [`Supervisor`] stopped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// The completion conditions hold.
+    Completed,
+    /// The process received a signal, e.g. `SIGINT`.
+    Signal(&'static str),
+    /// Every task exited before the completion conditions held.
+    TasksExited,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Completed => write!(f, "completed"),
+            StopReason::Signal(signal) => write!(f, "received {signal}"),
+            StopReason::TasksExited => write!(f, "all tasks exited"),
+        }
+    }
+}
+
+/// How a supervised task ended.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TaskOutcome {
+    /// The task returned `Ok`.
+    Finished,
+    /// The task returned an error or panicked.
+    Failed(String),
+    /// The task did not stop within the drain timeout.
+    Aborted,
+}
+
+/// What a [`Supervisor`] did, returned by [`Supervisor::run`].
+#[derive(Clone, Debug)]
+pub struct Summary {
+    /// Why the supervisor stopped.
+    pub reason: StopReason,
+    /// Number of processed events per name.
+    pub events: BTreeMap<String, u64>,
+    /// Highest block recorded.
+    pub block: Option<u64>,
+    /// How each task ended, in the order they were spawned.
+    pub tasks: Vec<(String, TaskOutcome)>,
+}
+
+/// Owns listener tasks and runs them until the work is complete or a signal is received.
+#[derive(Debug)]
+pub struct Supervisor {
+    tasks: JoinSet<(usize, TaskOutcome)>,
+    names: Vec<String>,
+    completions: Vec<Completion>,
+    any_completions: Vec<Completion>,
+    progress: Progress,
+    shutdown: watch::Sender<bool>,
+    drain_timeout: Duration,
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Supervisor {
+    /// Creates a supervisor without tasks or completion conditions, which runs until a signal
+    /// is received or its tasks exit.
+    pub fn new() -> Self {
+        Self {
+            tasks: JoinSet::new(),
+            names: Vec::new(),
+            completions: Vec::new(),
+            any_completions: Vec::new(),
+            progress: Progress { state: Arc::new(watch::Sender::new(ProgressState::default())) },
+            shutdown: watch::Sender::new(false),
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+        }
+    }
+
+    /// Adds a completion condition; the supervisor stops once all of them hold.
+    pub fn until(mut self, completion: Completion) -> Self {
+        self.completions.push(completion);
+        self
+    }
+
+    /// Adds a completion condition that stops the supervisor on its own, e.g. "2 `ValueChanged`
+    /// events or block 100" with two of them, whatever the conditions added with
+    /// [`until`](Self::until).
+    pub fn until_any(mut self, completion: Completion) -> Self {
+        self.any_completions.push(completion);
+        self
+    }
+
+    /// Sets how long the tasks get to drain after the shutdown was requested.
+    pub fn with_drain_timeout(mut self, drain_timeout: Duration) -> Self {
+        self.drain_timeout = drain_timeout;
+        self
+    }
+
+    /// Handle through which the tasks report their progress.
+    pub fn progress(&self) -> Progress {
+        self.progress.clone()
+    }
+
+    /// Spawns a supervised task, handing it the [`Shutdown`] to stop on.
+    ///
+    /// Must be called within a Tokio runtime.
+    pub fn spawn<F, Fut>(&mut self, name: impl Into<String>, task: F)
+    where
+        F: FnOnce(Shutdown) -> Fut,
+        Fut: Future<Output = eyre::Result<()>> + Send + 'static,
+    {
+        let index = self.names.len();
+        self.names.push(name.into());
+        let task = task(Shutdown { requested: self.shutdown.subscribe() });
+        self.tasks.spawn(async move {
+            let outcome = match AssertUnwindSafe(task).catch_unwind().await {
+                Ok(Ok(())) => TaskOutcome::Finished,
+                Ok(Err(error)) => TaskOutcome::Failed(format!("{error:#}")),
+                Err(panic) => TaskOutcome::Failed(format!("panicked: {}", panic_message(panic))),
+            };
+            (index, outcome)
+        });
+    }
+
+    /// Runs until the completion conditions are met, a signal is received or all tasks exited,
+    /// then requests the shutdown and drains the tasks.
+    pub async fn run(mut self) -> Summary {
+        let mut outcomes = vec![None; self.names.len()];
+        let mut progress = self.progress.state.subscribe();
+        let completions = std::mem::take(&mut self.completions);
+        let any_completions = std::mem::take(&mut self.any_completions);
+        let completed = async {
+            if completions.is_empty() && any_completions.is_empty() {
+                return futures::future::pending().await;
+            }
+            let _ = progress
+                .wait_for(|state| {
+                    let all = !completions.is_empty() && completions.iter().all(|completion| completion.holds(state));
+                    all || any_completions.iter().any(|completion| completion.holds(state))
+                })
+                .await;
+        };
+        let signal = shutdown_signal();
+        futures::pin_mut!(completed, signal);
+
+        let reason = loop {
+            if self.tasks.is_empty() {
+                break StopReason::TasksExited;
+            }
+            tokio::select! {
+                _ = &mut completed => break StopReason::Completed,
+                signal = &mut signal => break StopReason::Signal(signal),
+                Some(joined) = self.tasks.join_next() => {
+                    if let Ok((index, outcome)) = joined {
+                        outcomes[index] = Some(outcome);
+                    }
+                }
+            }
+        };
+
+        // Ask the tasks to stop and wait for them to finish what they are handling
+        self.shutdown.send_replace(true);
+        let drained = tokio::time::timeout(self.drain_timeout, async {
+            while let Some(joined) = self.tasks.join_next().await {
+                if let Ok((index, outcome)) = joined {
+                    outcomes[index] = Some(outcome);
+                }
+            }
+        })
+        .await;
+        if drained.is_err() {
+            self.tasks.abort_all();
+        }
+
+        let state = self.progress.state.borrow().clone();
+        Summary {
+            reason,
+            events: state.events,
+            block: state.block,
+            tasks: self
+                .names
+                .into_iter()
+                .zip(outcomes)
+                .map(|(name, outcome)| (name, outcome.unwrap_or(TaskOutcome::Aborted)))
+                .collect(),
+        }
+    }
+}
+
+/// Resolves with the name of the first termination signal received.
+async fn shutdown_signal() -> &'static str {
+    let interrupt = async {
+        match tokio::signal::ctrl_c().await {
+            Ok(()) => "SIGINT",
+            Err(_) => futures::future::pending().await,
+        }
+    };
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let terminate = async {
+            match signal(SignalKind::terminate()) {
+                Ok(mut terminate) => {
+                    terminate.recv().await;
+                    "SIGTERM"
+                }
+                Err(_) => futures::future::pending().await,
+            }
+        };
+        tokio::select! {
+            signal = interrupt => signal,
+            signal = terminate => signal,
+        }
+    }
+    #[cfg(not(unix))]
+    interrupt.await
+}
@@ -0,0 +1,142 @@
+//! Typed filters on the indexed arguments of events.
+//!
+//! Matching an indexed argument means comparing its topic, which is the argument's ABI encoding
+//! padded to 32 bytes for value types (addresses, integers, `bool`, `bytesN`) and the keccak256
+//! hash of it for dynamic types (`string`, `bytes`, arrays). [`event_filters!`](crate::event_filters)
+//! generates a builder per event with a method per indexed argument that does the encoding:
+//!
+//! ```ignore
+//! let filter = ValueChanged::filter()
+//!     .address(contract_address)
+//!     .updater_in([signer_address, secondary_signer_address])
+//!     .old_value(U256::from(1));
+//! ```
+//!
+//! Only indexed arguments get methods: the generated code fails to compile unless the listed
+//! arguments are exactly the indexed arguments of the event, in order.
+
+use alloy_primitives::B256;
+use alloy_sol_types::EventTopic;
+
+#[doc(hidden)]
+pub mod __private {
+    pub use alloy_primitives;
+    pub use alloy_rpc_types;
+    pub use alloy_sol_types;
+    pub use paste::paste;
+}
+
+/// Encodes the values of an indexed argument of Solidity type `T` as topics, matching any of
+/// them.
+///
+/// # Panics
+///
+/// Panics if `values` is empty, as an empty set of topics matches any value.
+pub fn encode_topics<T: EventTopic>(values: impl IntoIterator<Item = T::RustType>) -> Vec<B256> {
+    let topics: Vec<B256> = values.into_iter().map(|value| T::encode_topic(&value).0).collect();
+    assert!(!topics.is_empty(), "at least one value to match is required");
+    topics
+}
+
+/// Generates a filter builder for each of the given events, e.g.
+///
+/// ```ignore
+/// event_filters! {
+///     ValueChanged { updater: sol_data::Address, oldValue: sol_data::Uint<256> }
+/// }
+/// ```
+///
+/// adds `ValueChanged::filter()`, returning a `ValueChangedFilter` with the methods
+/// `updater(address)`, `updater_in(addresses)`, `old_value(u256)` and `old_value_in(u256s)`,
+/// plus `address(..)` to select the emitting contracts and `into_filter()`.
+///
+/// The arguments are the indexed arguments of the event in order, with their `sol_data` types.
+/// The events must be defined in the invoking crate.
+#[macro_export]
+macro_rules! event_filters {
+    ($($event:ident { $($param:ident : $ty:ty),* $(,)? })*) => {
+        $(
+            $crate::__event_filter!($event { $($param: $ty),* });
+        )*
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __event_filter {
+    ($event:ident { $($param:ident : $ty:ty),* }) => {
+        $crate::topics::__private::paste! {
+            #[doc = concat!("A filter on the indexed arguments of `", stringify!($event), "` events, see [`", stringify!($event), "::filter`].")]
+            #[derive(Clone, Debug)]
+            pub struct [<$event Filter>] {
+                filter: $crate::topics::__private::alloy_rpc_types::Filter,
+            }
+
+            impl $event {
+                #[doc = concat!("Creates a filter matching all `", stringify!($event), "` events.")]
+                pub fn filter() -> [<$event Filter>] {
+                    [<$event Filter>] {
+                        filter: $crate::topics::__private::alloy_rpc_types::Filter::new()
+                            .event_signature(<$event as $crate::topics::__private::alloy_sol_types::SolEvent>::SIGNATURE_HASH),
+                    }
+                }
+            }
+
+            // The listed arguments must be the indexed arguments of the event, after its signature
+            const _: fn() = || {
+                let _: ::core::marker::PhantomData<<$event as $crate::topics::__private::alloy_sol_types::SolEvent>::TopicList> =
+                    ::core::marker::PhantomData::<($crate::topics::__private::alloy_sol_types::sol_data::FixedBytes<32>, $($ty,)*)>;
+            };
+
+            impl [<$event Filter>] {
+                /// Only matches the events emitted by the given contracts.
+                pub fn address(
+                    mut self,
+                    address: impl Into<
+                        $crate::topics::__private::alloy_rpc_types::ValueOrArray<$crate::topics::__private::alloy_primitives::Address>,
+                    >,
+                ) -> Self {
+                    self.filter = self.filter.address(address);
+                    self
+                }
+
+                /// Returns the filter, e.g. to set its block range.
+                pub fn into_filter(self) -> $crate::topics::__private::alloy_rpc_types::Filter {
+                    self.filter
+                }
+            }
+
+            impl From<[<$event Filter>]> for $crate::topics::__private::alloy_rpc_types::Filter {
+                fn from(filter: [<$event Filter>]) -> Self {
+                    filter.filter
+                }
+            }
+        }
+        $crate::__event_filter!(@params $event [topic1 topic2 topic3] $($param: $ty,)*);
+    };
+    (@params $event:ident [$topic:ident $($topics:ident)*] $param:ident : $ty:ty, $($rest:tt)*) => {
+        $crate::topics::__private::paste! {
+            impl [<$event Filter>] {
+                #[doc = concat!("Only matches the events whose `", stringify!($param), "` is `value`.")]
+                pub fn [<$param:snake>](self, value: <$ty as $crate::topics::__private::alloy_sol_types::SolType>::RustType) -> Self {
+                    self.[<$param:snake _in>]([value])
+                }
+
+                #[doc = concat!("Only matches the events whose `", stringify!($param), "` is one of `values`.")]
+                ///
+                /// # Panics
+                ///
+                /// Panics if `values` is empty.
+                pub fn [<$param:snake _in>](
+                    mut self,
+                    values: impl IntoIterator<Item = <$ty as $crate::topics::__private::alloy_sol_types::SolType>::RustType>,
+                ) -> Self {
+                    self.filter = self.filter.$topic($crate::topics::encode_topics::<$ty>(values));
+                    self
+                }
+            }
+        }
+        $crate::__event_filter!(@params $event [$($topics)*] $($rest)*);
+    };
+    (@params $event:ident [$($topics:ident)*]) => {};
+}
@@ -0,0 +1,359 @@
+//! Watching the events of many deployments of a contract.
+//!
+//! A [`ContractWatcher`] follows the events of a set of [`Deployments`] that can change while
+//! it runs. Its addresses are split into batches of up to [`DEFAULT_BATCH_SIZE`], each followed
+//! by one [`EventStream`] filtering on the batch's addresses, and every event is tagged with the
+//! [`Deployment`] it came from.
+//!
+//! Changing the set does not interrupt the batches already followed:
+//!
+//! - deployments added while the watcher runs are batched with the other additions seen at the
+//!   same time, and followed from their start block if they were added with one (e.g. the block
+//!   they were deployed in), or else from the moment their batch is subscribed,
+//! - the events of a removed deployment are dropped right away, and its batch is stopped once
+//!   none of its deployments is left.
+//!
+//! A batch whose stream ended is dropped, and its deployments are followed by a new batch the
+//! next time the set changes.
+
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::future::Future;
+use std::sync::Arc;
+use alloy_network::Ethereum;
+use alloy_primitives::Address;
+use alloy_provider::Provider;
+use alloy_sol_types::SolEventInterface;
+use alloy_transport::Transport;
+use futures::stream::{AbortHandle, BoxStream, SelectAll};
+use futures::{Stream, StreamExt};
+use tokio::sync::watch;
+use crate::event_stream::{EventStream, EventStreamError, StreamEvent};
+
+/// Number of addresses followed by one subscription by default.
+pub const DEFAULT_BATCH_SIZE: usize = 50;
+
+/// A named deployment of a contract.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Deployment {
+    /// Address of the contract.
+    pub address: Address,
+    /// Name to tell the deployment apart, e.g. its owner.
+    pub name: String,
+}
+
+/// An event together with the deployment that emitted it.
+#[derive(Debug)]
+pub struct WatchedEvent<E> {
+    /// The deployment that emitted the event.
+    pub deployment: Deployment,
+    /// The state transition of the event.
+    pub event: StreamEvent<E>,
+}
+
+/// The set of deployments a [`ContractWatcher`] follows, shared with whoever changes it.
+#[derive(Clone, Debug)]
+pub struct Deployments {
+    names: Arc<watch::Sender<BTreeMap<Address, Watched>>>,
+}
+
+/// What the set holds for a deployment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Watched {
+    name: String,
+    from_block: Option<u64>,
+}
+
+impl Default for Deployments {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deployments {
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        Self { names: Arc::new(watch::Sender::new(BTreeMap::new())) }
+    }
+
+    /// Adds a deployment, or renames it if it is already in the set.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the contract.
+    /// * `name` - Name to tell the deployment apart.
+    /// * `from_block` - The block to follow the deployment from, e.g. the block it was deployed
+    ///   in. Without it, a deployment added while the watcher runs is followed from the moment
+    ///   its batch is subscribed, and one present at the start like the watcher's stream.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the deployment was not in the set yet.
+    pub fn add(&self, address: Address, name: impl Into<String>, from_block: Option<u64>) -> bool {
+        let watched = Watched { name: name.into(), from_block };
+        let mut added = false;
+        self.names.send_if_modified(|names| {
+            let previous = names.insert(address, watched.clone());
+            added = previous.is_none();
+            previous.as_ref() != Some(&watched)
+        });
+        added
+    }
+
+    /// Adds a deployment like [`add`](Self::add), unless it is in the set already.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the deployment was added.
+    pub fn add_new(&self, address: Address, name: impl Into<String>, from_block: Option<u64>) -> bool {
+        self.names.send_if_modified(|names| match names.entry(address) {
+            Entry::Vacant(entry) => {
+                entry.insert(Watched { name: name.into(), from_block });
+                true
+            }
+            Entry::Occupied(_) => false,
+        })
+    }
+
+    /// Removes a deployment.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the deployment was in the set.
+    pub fn remove(&self, address: &Address) -> bool {
+        self.names.send_if_modified(|names| names.remove(address).is_some())
+    }
+
+    /// Returns the deployment at `address`, if it is in the set.
+    pub fn get(&self, address: &Address) -> Option<Deployment> {
+        deployment(&self.names.borrow(), *address)
+    }
+
+    /// Returns the addresses in the set, in ascending order.
+    pub fn addresses(&self) -> Vec<Address> {
+        self.names.borrow().keys().copied().collect()
+    }
+
+    /// Returns the number of deployments in the set.
+    pub fn len(&self) -> usize {
+        self.names.borrow().len()
+    }
+
+    /// Returns `true` if the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.names.borrow().is_empty()
+    }
+}
+
+fn deployment(names: &BTreeMap<Address, Watched>, address: Address) -> Option<Deployment> {
+    names.get(&address).map(|watched| Deployment { address, name: watched.name.clone() })
+}
+
+/// Follows the events of a changing set of deployments, batching their addresses into combined
+/// filters.
+#[derive(Clone, Debug)]
+pub struct ContractWatcher {
+    stream: EventStream,
+    deployments: Deployments,
+    batch_size: usize,
+}
+
+impl ContractWatcher {
+    /// Creates a watcher following the events of `deployments`.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The stream each batch is followed with. Its filter selects the events, e.g.
+    ///   by signature; the addresses of the batch are set on it. A start block applies to the
+    ///   deployments in the set when the watcher starts that were added without one.
+    /// * `deployments` - The deployments to follow, which can change while the watcher runs.
+    pub fn new(stream: EventStream, deployments: Deployments) -> Self {
+        Self { stream, deployments, batch_size: DEFAULT_BATCH_SIZE }
+    }
+
+    /// Sets the maximum number of addresses followed by one subscription.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `batch_size` is `0`.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "batch size must be positive");
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Follows every batch with [`EventStream::subscribe`].
+    pub fn subscribe<E, P, T>(self, provider: P) -> impl Stream<Item = Result<WatchedEvent<E>, EventStreamError>>
+    where
+        E: SolEventInterface + Send + Sync + 'static,
+        P: Provider<T, Ethereum> + Clone + 'static,
+        T: Transport + Clone,
+    {
+        self.watch(move |stream| {
+            let provider = provider.clone();
+            async move { stream.subscribe::<E, _, _>(provider).await }
+        })
+    }
+
+    /// Follows every batch with [`EventStream::poll`].
+    pub fn poll<E, P, T>(self, provider: P) -> impl Stream<Item = Result<WatchedEvent<E>, EventStreamError>>
+    where
+        E: SolEventInterface + Send + Sync + 'static,
+        P: Provider<T, Ethereum> + Clone + 'static,
+        T: Transport + Clone,
+    {
+        self.watch(move |stream| {
+            let provider = provider.clone();
+            async move { stream.poll::<E, _, _>(provider).await }
+        })
+    }
+
+    /// Follows every batch with the stream returned by `open`.
+    ///
+    /// # Arguments
+    ///
+    /// * `open` - Opens the stream of a batch, e.g. with
+    ///   [`EventStream::subscribe_with_reconnect`].
+    ///
+    /// # Returns
+    ///
+    /// The events of all batches, tagged with their deployment. A batch that cannot be opened is
+    /// reported as an error, and opened again the next time the set changes, as is a batch
+    /// whose stream ended. The stream ends once every batch ended and the [`Deployments`] were
+    /// dropped.
+    pub fn watch<E, S, F, Fut>(self, open: F) -> impl Stream<Item = Result<WatchedEvent<E>, EventStreamError>>
+    where
+        E: Send + Sync + 'static,
+        S: Stream<Item = Result<StreamEvent<E>, EventStreamError>> + Send + 'static,
+        F: FnMut(EventStream) -> Fut,
+        Fut: Future<Output = Result<S, EventStreamError>>,
+    {
+        let driver = Driver {
+            stream: self.stream,
+            batch_size: self.batch_size,
+            open,
+            deployments: self.deployments.names.subscribe(),
+            watching: true,
+            started: false,
+            next_batch: 0,
+            batches: Vec::new(),
+            events: SelectAll::new(),
+            errors: VecDeque::new(),
+        };
+        futures::stream::unfold(driver, |mut driver| async move {
+            let next = driver.next().await?;
+            Some((next, driver))
+        })
+    }
+}
+
+/// The addresses followed by one stream.
+struct Batch {
+    id: u64,
+    addresses: BTreeSet<Address>,
+    stop: AbortHandle,
+}
+
+/// An item of the batch with the given id, `None` once the batch ended.
+type BatchItem<E> = (u64, Option<Result<StreamEvent<E>, EventStreamError>>);
+
+/// Keeps the batches in line with the deployments and merges their events.
+struct Driver<E, F> {
+    stream: EventStream,
+    batch_size: usize,
+    open: F,
+    deployments: watch::Receiver<BTreeMap<Address, Watched>>,
+    /// Whether the deployments can still change.
+    watching: bool,
+    /// Whether the deployments present at the start were batched.
+    started: bool,
+    /// Id of the next batch opened.
+    next_batch: u64,
+    batches: Vec<Batch>,
+    events: SelectAll<BoxStream<'static, BatchItem<E>>>,
+    /// Errors opening batches, not reported yet.
+    errors: VecDeque<EventStreamError>,
+}
+
+impl<E, S, F, Fut> Driver<E, F>
+where
+    E: Send + Sync + 'static,
+    S: Stream<Item = Result<StreamEvent<E>, EventStreamError>> + Send + 'static,
+    F: FnMut(EventStream) -> Fut,
+    Fut: Future<Output = Result<S, EventStreamError>>,
+{
+    async fn next(&mut self) -> Option<Result<WatchedEvent<E>, EventStreamError>> {
+        if !self.started {
+            self.sync().await;
+            self.started = true;
+        }
+        loop {
+            if let Some(error) = self.errors.pop_front() {
+                return Some(Err(error));
+            }
+            tokio::select! {
+                changed = self.deployments.changed(), if self.watching => match changed {
+                    Ok(()) => self.sync().await,
+                    // The set can no longer change, keep following the current batches
+                    Err(_) => self.watching = false,
+                },
+                Some((id, result)) = self.events.next() => match result {
+                    // Drop the events of deployments removed in the meantime
+                    Some(Ok(event)) => match deployment(&self.deployments.borrow(), event.log().address) {
+                        Some(deployment) => return Some(Ok(WatchedEvent { deployment, event })),
+                        None => continue,
+                    },
+                    Some(Err(error)) => return Some(Err(error)),
+                    // Its deployments are followed by a new batch the next time the set changes
+                    None => self.batches.retain(|batch| batch.id != id),
+                },
+                else => return None,
+            }
+        }
+    }
+
+    /// Stops the batches without deployments left and opens batches for the new deployments.
+    async fn sync(&mut self) {
+        let deployments: BTreeMap<Address, Option<u64>> = self
+            .deployments
+            .borrow_and_update()
+            .iter()
+            .map(|(address, watched)| (*address, watched.from_block))
+            .collect();
+        self.batches.retain(|batch| {
+            let live = batch.addresses.iter().any(|address| deployments.contains_key(address));
+            if !live {
+                batch.stop.abort();
+            }
+            live
+        });
+
+        // Batch the new deployments by the block they are followed from
+        let followed: BTreeSet<Address> = self.batches.iter().flat_map(|batch| batch.addresses.iter().copied()).collect();
+        let mut added: BTreeMap<Option<u64>, Vec<Address>> = BTreeMap::new();
+        for (address, from_block) in deployments {
+            if !followed.contains(&address) {
+                added.entry(from_block).or_default().push(address);
+            }
+        }
+        for (from_block, addresses) in added {
+            for batch in addresses.chunks(self.batch_size) {
+                let stream = match from_block {
+                    Some(block) => self.stream.for_addresses(batch, true).from_block(block),
+                    None => self.stream.for_addresses(batch, self.started),
+                };
+                match (self.open)(stream).await {
+                    Ok(events) => {
+                        let id = self.next_batch;
+                        self.next_batch += 1;
+                        let (events, stop) = futures::stream::abortable(events);
+                        let end = futures::stream::once(async move { (id, None) });
+                        self.events.push(events.map(move |result| (id, Some(result))).chain(end).boxed());
+                        self.batches.push(Batch { id, addresses: batch.iter().copied().collect(), stop });
+                    }
+                    Err(error) => self.errors.push_back(error),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,67 @@
+//! Checks that the checked-in Foundry artifact matches `SampleContract.sol`.
+
+use std::collections::BTreeSet;
+use alloy_in_action_common::SampleContract;
+use alloy_json_abi::JsonAbi;
+use alloy_sol_macro::sol;
+
+mod source {
+    use super::sol;
+
+    // Parse the Solidity source directly to obtain its ABI
+    sol!(
+        #[sol(abi)]
+        "../solidity-smart-contracts/src/SampleContract.sol"
+    );
+}
+
+const ARTIFACT: &str = include_str!(
+    "../../solidity-smart-contracts/out/SampleContract.sol/SampleContract.json"
+);
+
+/// Reduces an ABI to the parts that determine the generated bindings and the on-chain interface.
+///
+/// Output names are left out since `solc` leaves public getter outputs unnamed.
+fn interface(abi: &JsonAbi) -> BTreeSet<String> {
+    let constructor = abi.constructor.iter().map(|c| {
+        let inputs: Vec<_> = c.inputs.iter().map(|p| format!("{} {}", p.ty, p.name)).collect();
+        format!("constructor({}) {:?}", inputs.join(","), c.state_mutability)
+    });
+    let functions = abi.functions().map(|f| {
+        let inputs: Vec<_> = f.inputs.iter().map(|p| format!("{} {}", p.ty, p.name)).collect();
+        let outputs: Vec<_> = f.outputs.iter().map(|p| p.ty.clone()).collect();
+        format!(
+            "function {}({}) returns ({}) {:?}",
+            f.name, inputs.join(","), outputs.join(","), f.state_mutability
+        )
+    });
+    let events = abi.events().map(|e| format!("{} anonymous={}", e.full_signature(), e.anonymous));
+    let errors = abi.errors().map(|e| {
+        let inputs: Vec<_> = e.inputs.iter().map(|p| format!("{} {}", p.ty, p.name)).collect();
+        format!("error {}({})", e.name, inputs.join(","))
+    });
+
+    constructor.chain(functions).chain(events).chain(errors).collect()
+}
+
+#[test]
+fn artifact_abi_matches_solidity_source() {
+    let artifact: serde_json::Value = serde_json::from_str(ARTIFACT).unwrap();
+    let artifact_abi: JsonAbi = serde_json::from_value(artifact["abi"].clone()).unwrap();
+    let source_abi = source::SampleContract::abi::contract();
+
+    assert_eq!(
+        interface(&artifact_abi),
+        interface(&source_abi),
+        "out/SampleContract.sol/SampleContract.json is out of date, run `forge build` in solidity-smart-contracts"
+    );
+}
+
+#[test]
+fn artifact_bytecode_is_embedded() {
+    // The runtime code is copied out of the creation code by the constructor
+    assert!(!SampleContract::DEPLOYED_BYTECODE.is_empty());
+    assert!(SampleContract::BYTECODE
+        .windows(SampleContract::DEPLOYED_BYTECODE.len())
+        .any(|window| window == &SampleContract::DEPLOYED_BYTECODE[..]));
+}
@@ -0,0 +1,122 @@
+//! Paging through historical logs and switching to the live subscriptions.
+
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use alloy_in_action_common::{Anvil, EventStream, LogPager, RangeErrorExt, SampleContract, StreamEvent, TestNode};
+use alloy_json_rpc::{ErrorPayload, RequestPacket, Response, ResponsePacket, ResponsePayload};
+use alloy_primitives::U256;
+use alloy_provider::ProviderBuilder;
+use alloy_rpc_client::RpcClient;
+use alloy_rpc_types::Filter;
+use alloy_transport::{TransportError, TransportFut};
+use futures::StreamExt;
+use serde_json::value::RawValue;
+use tower::Service;
+use SampleContract::SampleContractEvents;
+
+/// A node answering `eth_getLogs` with no logs, rejecting queries over more than `max_range`
+/// blocks like RPC providers do.
+#[derive(Clone)]
+struct RangeLimitedNode {
+    max_range: u64,
+    queries: Arc<Mutex<Vec<(u64, u64, bool)>>>,
+}
+
+impl Service<RequestPacket> for RangeLimitedNode {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), TransportError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let RequestPacket::Single(request) = request else { unimplemented!("batch request") };
+        let (filter,): (Filter,) = serde_json::from_str(request.params().expect("filter").get()).unwrap();
+        let (from, to) = (filter.get_from_block().unwrap(), filter.get_to_block().unwrap());
+        let accepted = to - from < self.max_range;
+        self.queries.lock().unwrap().push((from, to, accepted));
+
+        let payload = if accepted {
+            ResponsePayload::Success(RawValue::from_string("[]".to_string()).unwrap())
+        } else {
+            let message = "query returned more than 10000 results".into();
+            ResponsePayload::Failure(ErrorPayload { code: -32005, message, data: None })
+        };
+        let response = Response { id: request.id().clone(), payload };
+        Box::pin(async move { Ok(ResponsePacket::Single(response)) })
+    }
+}
+
+#[tokio::test]
+async fn shrinks_pages_rejected_as_too_large() -> eyre::Result<()> {
+    let node = RangeLimitedNode { max_range: 10, queries: Arc::default() };
+    let provider = ProviderBuilder::new().on_client(RpcClient::new(node.clone(), true));
+
+    let mut pager = LogPager::new(64);
+    pager.get_logs(&provider, &Filter::new(), 0, 99).await?;
+
+    // The accepted pages cover the range exactly once, each within the node's limit
+    let queries = node.queries.lock().unwrap().clone();
+    let accepted: Vec<_> = queries.iter().filter(|(_, _, accepted)| *accepted).collect();
+    assert_eq!(accepted.first().map(|(from, ..)| *from), Some(0));
+    assert_eq!(accepted.last().map(|(_, to, _)| *to), Some(99));
+    assert!(accepted.windows(2).all(|pages| pages[1].0 == pages[0].1 + 1));
+    assert!(accepted.iter().all(|(from, to, _)| to - from < 10));
+    assert_eq!(queries[..4].iter().map(|(from, to, _)| (*from, *to)).collect::<Vec<_>>(), [(0, 63), (0, 31), (0, 15), (0, 7)]);
+
+    // A single block that is still too large is an error
+    let node = RangeLimitedNode { max_range: 0, queries: Arc::default() };
+    let provider = ProviderBuilder::new().on_client(RpcClient::new(node, true));
+    let error = LogPager::new(8).get_logs(&provider, &Filter::new(), 0, 99).await.unwrap_err();
+    assert!(error.is_range_error());
+
+    Ok(())
+}
+
+#[test]
+fn tells_range_errors_from_rate_limits() {
+    let error = |code: i64, message: &str| {
+        TransportError::ErrorResp(ErrorPayload { code, message: message.to_string().into(), data: None })
+    };
+
+    assert!(error(-32005, "query returned more than 10000 results").is_range_error());
+    assert!(error(-32602, "Log response size exceeded. You can make eth_getLogs requests with up to a 2K block range").is_range_error());
+    assert!(error(-32000, "exceed maximum block range: 5000").is_range_error());
+    assert!(error(-32600, "eth_getLogs is limited to a 10000 range").is_range_error());
+
+    // Rate limits mention limits, or even block ranges, without the query being too large
+    assert!(!error(-32005, "Limit exceeded").is_range_error());
+    assert!(!error(429, "Too many results requested, block range queries are rate limited").is_range_error());
+    assert!(!error(-32029, "too many results in the last minute").is_range_error());
+    assert!(!error(-32000, "execution reverted").is_range_error());
+}
+
+#[tokio::test]
+async fn streams_history_then_live_events_once() -> eyre::Result<()> {
+    let Some(node) = TestNode::ws(Anvil::new()).await? else { return Ok(()) };
+    let provider = &node.provider;
+    let contract = node.deploy().await?;
+    for value in 2..=4 {
+        contract.setValue(U256::from(value)).send().await?.get_receipt().await?;
+    }
+
+    // Page through the history one block at a time, then follow the new events
+    let stream = EventStream::new(Filter::new().address(*contract.address())).from_block(0).with_page_size(1);
+    let events = stream.subscribe::<SampleContractEvents, _, _>(provider.clone()).await?;
+    futures::pin_mut!(events);
+    contract.setValue(U256::from(5)).send().await?.get_receipt().await?;
+
+    let mut values = Vec::new();
+    while values.len() < 4 {
+        let event = tokio::time::timeout(std::time::Duration::from_secs(10), events.next()).await?.unwrap()?;
+        if let StreamEvent::Observed(log) = event {
+            let SampleContractEvents::ValueChanged(event) = &log.data else { continue };
+            values.push(event.newValue.to::<u64>());
+        }
+    }
+    assert_eq!(values, [2, 3, 4, 5]);
+
+    Ok(())
+}
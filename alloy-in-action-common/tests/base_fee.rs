@@ -0,0 +1,113 @@
+use alloy_in_action_common::fees::calculate_base_fee_per_gas;
+use proptest::prelude::*;
+
+/// Literal transcription of the base fee rule from the EIP-1559 specification, in unbounded
+/// (u128) arithmetic. A zero gas target, where the specification divides by zero, keeps the
+/// base fee unchanged.
+fn reference_base_fee(parent_base_fee: u64, parent_gas_used: u64, parent_gas_limit: u64) -> u128 {
+    const ELASTICITY_MULTIPLIER: u128 = 2;
+    const BASE_FEE_MAX_CHANGE_DENOMINATOR: u128 = 8;
+
+    let parent_base_fee = parent_base_fee as u128;
+    let parent_gas_used = parent_gas_used as u128;
+    let parent_gas_target = parent_gas_limit as u128 / ELASTICITY_MULTIPLIER;
+
+    if parent_gas_target == 0 || parent_gas_used == parent_gas_target {
+        parent_base_fee
+    } else if parent_gas_used > parent_gas_target {
+        let gas_used_delta = parent_gas_used - parent_gas_target;
+        let base_fee_per_gas_delta = std::cmp::max(
+            parent_base_fee * gas_used_delta / parent_gas_target / BASE_FEE_MAX_CHANGE_DENOMINATOR,
+            1,
+        );
+        parent_base_fee + base_fee_per_gas_delta
+    } else {
+        let gas_used_delta = parent_gas_target - parent_gas_used;
+        let base_fee_per_gas_delta =
+            parent_base_fee * gas_used_delta / parent_gas_target / BASE_FEE_MAX_CHANGE_DENOMINATOR;
+        parent_base_fee - base_fee_per_gas_delta
+    }
+}
+
+#[test]
+fn matches_known_values() {
+    // (base fee, gas used, gas limit, expected next base fee)
+    let cases: &[(u64, u64, u64, u64)] = &[
+        // At target: unchanged
+        (1_000_000_000, 15_000_000, 30_000_000, 1_000_000_000),
+        // Full block: +12.5%
+        (1_000_000_000, 30_000_000, 30_000_000, 1_125_000_000),
+        // Empty block: -12.5%
+        (1_000_000_000, 0, 30_000_000, 875_000_000),
+        // Halfway between target and full: +6.25%
+        (1_000_000_000, 22_500_000, 30_000_000, 1_062_500_000),
+        // Halfway between empty and target: -6.25%
+        (1_000_000_000, 7_500_000, 30_000_000, 937_500_000),
+        // Tiny increase rounds down to zero but must be at least 1 wei
+        (7, 15_000_001, 30_000_000, 8),
+        (1, 30_000_000, 30_000_000, 2),
+        // Base fee of zero still rises by 1 wei above target, stays zero below
+        (0, 30_000_000, 30_000_000, 1),
+        (0, 0, 30_000_000, 0),
+        // Tiny decrease rounds down to zero
+        (7, 14_999_999, 30_000_000, 7),
+        // Zero gas target (gas limit 0 or 1): unchanged
+        (1_000, 0, 0, 1_000),
+        (1_000, 1, 1, 1_000),
+        // Odd gas limit: target rounds down
+        (1_000, 2, 3, 1_125),
+        // Gas used above u32/i32 ranges and a base fee near u64::MAX
+        (u64::MAX / 2, u64::MAX - 1, u64::MAX - 1, u64::MAX / 2 + u64::MAX / 2 / 8),
+        (u64::MAX, u64::MAX, u64::MAX, u64::MAX),
+    ];
+
+    for &(base_fee, gas_used, gas_limit, expected) in cases {
+        assert_eq!(
+            calculate_base_fee_per_gas(base_fee, gas_used, gas_limit),
+            expected,
+            "base fee {base_fee}, gas used {gas_used}, gas limit {gas_limit}"
+        );
+    }
+}
+
+proptest! {
+    #[test]
+    fn matches_reference(
+        base_fee in any::<u64>(),
+        gas_limit in any::<u64>(),
+        gas_used_ratio in 0.0..=1.0f64,
+    ) {
+        let gas_used = (gas_limit as f64 * gas_used_ratio) as u64;
+        let expected = reference_base_fee(base_fee, gas_used, gas_limit).min(u64::MAX as u128) as u64;
+        prop_assert_eq!(calculate_base_fee_per_gas(base_fee, gas_used, gas_limit), expected);
+    }
+
+    #[test]
+    fn matches_reference_on_realistic_blocks(
+        base_fee in 0..1_000_000_000_000u64,
+        gas_limit in 0..60_000_000u64,
+        gas_used in 0..60_000_000u64,
+    ) {
+        let gas_used = gas_used.min(gas_limit);
+        let expected = reference_base_fee(base_fee, gas_used, gas_limit) as u64;
+        prop_assert_eq!(calculate_base_fee_per_gas(base_fee, gas_used, gas_limit), expected);
+    }
+
+    #[test]
+    fn changes_at_most_one_eighth(
+        base_fee in any::<u64>(),
+        gas_limit in 2..u64::MAX,
+        gas_used_ratio in 0.0..=1.0f64,
+    ) {
+        let gas_used = (gas_limit as f64 * gas_used_ratio) as u64;
+        let next = calculate_base_fee_per_gas(base_fee, gas_used, gas_limit);
+        let target = gas_limit / 2;
+
+        prop_assert!(next.abs_diff(base_fee) <= (base_fee / 8).max(1));
+        if gas_used > target {
+            prop_assert!(next > base_fee || base_fee == u64::MAX);
+        } else {
+            prop_assert!(next <= base_fee);
+        }
+    }
+}
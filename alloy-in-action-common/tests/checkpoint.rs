@@ -0,0 +1,96 @@
+//! Checkpoint stores and resuming event streams after a checkpoint.
+
+#[cfg(feature = "sqlite")]
+use alloy_in_action_common::SqliteCheckpointStore;
+use alloy_in_action_common::{
+    Anvil, Checkpoint, CheckpointStore, EventStream, JsonCheckpointStore, SampleContract, StreamEvent, TestNode
+};
+use alloy_primitives::U256;
+use alloy_rpc_types::Filter;
+use futures::StreamExt;
+use SampleContract::SampleContractEvents;
+
+/// Saves checkpoints of two subscriptions, overwriting one, and checks what `store` returns.
+fn round_trip(store: &impl CheckpointStore) -> eyre::Result<()> {
+    assert_eq!(store.load("values")?, None);
+
+    store.save("values", Checkpoint::new(10, 0))?;
+    store.save("transfers", Checkpoint::new(7, 3))?;
+    store.save("values", Checkpoint::new(12, 1))?;
+
+    assert_eq!(store.load("values")?, Some(Checkpoint::new(12, 1)));
+    assert_eq!(store.load("transfers")?, Some(Checkpoint::new(7, 3)));
+    Ok(())
+}
+
+#[test]
+fn json_store_persists_checkpoints() -> eyre::Result<()> {
+    let directory = tempfile::tempdir()?;
+    let path = directory.path().join("checkpoints.json");
+    round_trip(&JsonCheckpointStore::new(&path))?;
+
+    // A new store on the same file, as after a restart, sees the saved checkpoints
+    let reopened = JsonCheckpointStore::new(&path);
+    assert_eq!(reopened.load("values")?, Some(Checkpoint::new(12, 1)));
+    assert!(!path.with_extension("json.tmp").exists());
+
+    // A corrupt file is an error rather than a silent restart from scratch
+    std::fs::write(&path, "{")?;
+    assert!(reopened.load("values").is_err());
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "sqlite")]
+fn sqlite_store_persists_checkpoints() -> eyre::Result<()> {
+    round_trip(&SqliteCheckpointStore::in_memory()?)?;
+
+    let directory = tempfile::tempdir()?;
+    let path = directory.path().join("checkpoints.db");
+    round_trip(&SqliteCheckpointStore::open(&path)?)?;
+    let reopened = SqliteCheckpointStore::open(&path)?;
+    assert_eq!(reopened.load("transfers")?, Some(Checkpoint::new(7, 3)));
+    Ok(())
+}
+
+#[test]
+fn checkpoints_order_by_block_then_log_index() {
+    let checkpoint = Checkpoint::new(5, 2);
+    assert!(Checkpoint::new(4, 9) < checkpoint);
+    assert!(Checkpoint::new(5, 1) < checkpoint);
+    assert!(Checkpoint::new(5, 3) > checkpoint);
+    assert!(Checkpoint::new(6, 0) > checkpoint);
+}
+
+#[tokio::test]
+async fn resumes_after_checkpoint() -> eyre::Result<()> {
+    let Some(node) = TestNode::ws(Anvil::new()).await? else { return Ok(()) };
+    let provider = &node.provider;
+    let contract = node.deploy().await?;
+    let mut checkpoint = None;
+    for value in 2..=4 {
+        let receipt = contract.setValue(U256::from(value)).send().await?.get_receipt().await?;
+        if value == 3 {
+            let log = &receipt.inner.logs()[0];
+            checkpoint = Some(Checkpoint::new(log.block_number.unwrap(), log.log_index.unwrap()));
+        }
+    }
+
+    // Events up to and including the checkpoint were processed by the previous run
+    let stream = EventStream::new(Filter::new().address(*contract.address())).resume_after(checkpoint.unwrap());
+    let events = stream.subscribe::<SampleContractEvents, _, _>(provider.clone()).await?;
+    futures::pin_mut!(events);
+    contract.setValue(U256::from(5)).send().await?.get_receipt().await?;
+
+    let mut values = Vec::new();
+    while values.len() < 2 {
+        let event = tokio::time::timeout(std::time::Duration::from_secs(10), events.next()).await?.unwrap()?;
+        if let StreamEvent::Observed(log) = event {
+            let SampleContractEvents::ValueChanged(event) = &log.data else { continue };
+            values.push(event.newValue.to::<u64>());
+        }
+    }
+    assert_eq!(values, [4, 5]);
+
+    Ok(())
+}
@@ -0,0 +1,84 @@
+//! Profile resolution and validation of the typed configuration.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+use alloy_in_action_common::config::{ConfigFile, Origin};
+use alloy_in_action_common::{Config, ConfigError, ConfigOverrides};
+
+const KEY: &str = "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+const FILE: &str = r#"
+default_profile = "anvil"
+
+[profiles.anvil]
+rpc_url = "http://127.0.0.1:8545"
+chain_id = 31337
+
+[profiles.devnet]
+rpc_url = "http://127.0.0.1:8546"
+ws_url = "ws://127.0.0.1:8546"
+chain_id = 1337
+"#;
+
+fn resolve(env: &[(&str, &str)], overrides: &ConfigOverrides) -> Result<Config, ConfigError> {
+    let file: ConfigFile = toml::from_str(FILE).unwrap();
+    let env: HashMap<String, String> = env.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+    Config::resolve(&file, Path::new("alloy-in-action.toml"), |key| env.get(key).cloned(), overrides)
+}
+
+#[test]
+fn environment_overrides_file_and_flags_override_environment() {
+    let env = [("ANVIL_PRIVATE_KEY", KEY), ("ANVIL_CHAIN_ID", "1")];
+    let config = resolve(&env, &ConfigOverrides::default()).unwrap();
+    assert_eq!(config.profile, "anvil");
+    assert_eq!(config.chain_id, 1);
+    assert_eq!(config.rpc_url.as_str(), "http://127.0.0.1:8545/");
+
+    let overrides = ConfigOverrides { chain_id: Some("5".into()), ..Default::default() };
+    assert_eq!(resolve(&env, &overrides).unwrap().chain_id, 5);
+}
+
+#[test]
+fn poll_interval_is_optional_milliseconds() {
+    let env = [("ANVIL_PRIVATE_KEY", KEY)];
+    assert_eq!(resolve(&env, &ConfigOverrides::default()).unwrap().poll_interval(), None);
+
+    let overrides = ConfigOverrides { poll_interval_ms: Some("250".into()), ..Default::default() };
+    assert_eq!(resolve(&env, &overrides).unwrap().poll_interval(), Some(Duration::from_millis(250)));
+
+    let env = [("ANVIL_PRIVATE_KEY", KEY), ("ANVIL_POLL_INTERVAL_MS", "0")];
+    let error = resolve(&env, &ConfigOverrides::default()).unwrap_err();
+    assert!(matches!(&error, ConfigError::Invalid { key: "poll_interval_ms", .. }), "{error}");
+}
+
+#[test]
+fn profile_selects_prefixed_variables_and_file_table() {
+    let env = [("ALLOY_PROFILE", "devnet"), ("ANVIL_PRIVATE_KEY", KEY), ("DEVNET_PRIVATE_KEY", KEY)];
+    let config = resolve(&env, &ConfigOverrides::default()).unwrap();
+    assert_eq!(config.profile, "devnet");
+    assert_eq!(config.chain_id, 1337);
+    assert_eq!(config.ws_url().unwrap().as_str(), "ws://127.0.0.1:8546/");
+}
+
+#[test]
+fn missing_values_name_the_key() {
+    let error = resolve(&[], &ConfigOverrides::default()).unwrap_err();
+    assert!(matches!(&error, ConfigError::Missing { key: "private_key", env_key, .. } if env_key == "ANVIL_PRIVATE_KEY"));
+
+    // Optional values are only reported once they are needed
+    let config = resolve(&[("ANVIL_PRIVATE_KEY", KEY)], &ConfigOverrides::default()).unwrap();
+    let error = config.ws_url().unwrap_err();
+    assert!(error.to_string().contains("ANVIL_WS_URL"), "{error}");
+}
+
+#[test]
+fn malformed_values_name_the_key_and_origin() {
+    let env = [("ANVIL_PRIVATE_KEY", KEY), ("ANVIL_RPC_URL", "not a url")];
+    let error = resolve(&env, &ConfigOverrides::default()).unwrap_err();
+    assert!(matches!(&error, ConfigError::Invalid { key: "rpc_url", origin: Origin::Env(var), .. } if var == "ANVIL_RPC_URL"));
+
+    let overrides = ConfigOverrides { private_key: Some("0x1234".into()), ..Default::default() };
+    let error = resolve(&env, &overrides).unwrap_err();
+    assert!(matches!(&error, ConfigError::Invalid { key: "private_key", origin: Origin::Flag(flag), .. } if flag == "private-key"));
+}
@@ -0,0 +1,65 @@
+//! Confirmation policies and reorg-aware confirmation tracking.
+
+use alloy_in_action_common::{Anvil, Confirmation, ConfirmationPolicy, ConfirmationTracker, TestNode};
+use alloy_network::TransactionBuilder;
+use alloy_primitives::utils::parse_ether;
+use alloy_primitives::U256;
+use alloy_provider::Provider;
+use alloy_rpc_types::TransactionRequest;
+use futures::StreamExt;
+
+#[test]
+fn scales_confirmations_with_value() -> eyre::Result<()> {
+    let policy = ConfirmationPolicy::fixed(2)
+        .with_tier(parse_ether("100")?, 12)
+        .with_tier(parse_ether("1")?, 6);
+
+    assert_eq!(policy.required_confirmations(U256::ZERO), 2);
+    assert_eq!(policy.required_confirmations(parse_ether("0.5")?), 2);
+    assert_eq!(policy.required_confirmations(parse_ether("1")?), 6);
+    assert_eq!(policy.required_confirmations(parse_ether("99")?), 6);
+    assert_eq!(policy.required_confirmations(parse_ether("1000")?), 12);
+    assert_eq!(ConfirmationPolicy::fixed(0).required_confirmations(U256::ZERO), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn reports_confirmations_reorgs_and_drops() -> eyre::Result<()> {
+    let Some(node) = TestNode::ws(Anvil::new()).await? else { return Ok(()) };
+    let config = &node.config;
+    let provider = &node.provider;
+    let mine = || async { provider.client().request_noparams::<String>("evm_mine").await };
+
+    // Confirmed once buried 3 blocks deep
+    let tx = TransactionRequest::default().with_to(config.signer.address()).with_value(U256::from(1));
+    let tx_hash = *provider.send_transaction(tx.clone()).await?.tx_hash();
+    let tracker = ConfirmationTracker::new(ConfirmationPolicy::fixed(3));
+    let (receipt, _) = tokio::join!(tracker.wait(provider, tx_hash), async {
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        mine().await?;
+        mine().await
+    });
+    assert_eq!(receipt?.transaction_hash, tx_hash);
+
+    // Reverting to a snapshot taken before the transaction reorganizes it away and drops it
+    let snapshot: U256 = provider.client().request_noparams("evm_snapshot").await?;
+    let tx_hash = *provider.send_transaction(tx).await?.tx_hash();
+    let tracker = ConfirmationTracker::new(ConfirmationPolicy::fixed(10));
+    let transitions = tracker.track(provider, tx_hash);
+    futures::pin_mut!(transitions);
+
+    let included = transitions.next().await.unwrap()?;
+    let Confirmation::Included(receipt) = included else { panic!("expected Included, got {included:?}") };
+    assert_eq!(transitions.next().await.unwrap()?, Confirmation::Confirmed(1));
+
+    let _: bool = provider.client().request("evm_revert", (snapshot,)).await?;
+    mine().await?;
+    assert_eq!(
+        transitions.next().await.unwrap()?,
+        Confirmation::Reorged { block_hash: receipt.block_hash.unwrap(), block_number: receipt.block_number.unwrap() }
+    );
+    assert_eq!(transitions.next().await.unwrap()?, Confirmation::Dropped);
+    assert!(transitions.next().await.is_none());
+
+    Ok(())
+}
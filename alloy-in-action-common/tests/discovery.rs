@@ -0,0 +1,123 @@
+//! Discovering deployments from factory events and deploy receipts.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use alloy_in_action_common::{
+    from_factory_events, Anvil, ContractWatcher, DeployDiscovery, Deployment, Deployments, Discovery, EventLog,
+    EventStream, EventStreamError, SampleContract, StreamEvent, TestNode,
+};
+use alloy_primitives::{Address, B256, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::Filter;
+use futures::StreamExt;
+use SampleContract::{SampleContractEvents, ValueChanged};
+
+/// A factory event in block `block_hash`, announcing the contract at `created`.
+fn created(block_hash: u8, log_index: u64, created: Address) -> Arc<EventLog<Address>> {
+    Arc::new(EventLog {
+        block_number: 1,
+        block_hash: B256::repeat_byte(block_hash),
+        transaction_hash: B256::ZERO,
+        log_index,
+        address: Address::repeat_byte(0xfa),
+        data: created,
+    })
+}
+
+fn deployment(byte: u8) -> Deployment {
+    Deployment { address: Address::repeat_byte(byte), name: format!("pool {byte}") }
+}
+
+#[tokio::test]
+async fn adds_and_retracts_factory_deployments() {
+    let deployments = Deployments::new();
+    deployments.add(Address::repeat_byte(3), "known", None);
+    let (first, second, known) =
+        (created(1, 0, Address::repeat_byte(1)), created(1, 1, Address::repeat_byte(2)), created(1, 2, Address::repeat_byte(3)));
+    let events = futures::stream::iter([
+        StreamEvent::Observed(first.clone()),
+        StreamEvent::Observed(second.clone()),
+        StreamEvent::Observed(known.clone()),
+        StreamEvent::Finalized(first),
+        StreamEvent::Retracted(second),
+        StreamEvent::Retracted(known),
+    ]);
+
+    let discoveries: Vec<_> = from_factory_events(deployments.clone(), events.map(Ok), |log| {
+        Some(Deployment { address: log.data, name: format!("pool {}", log.data.0[0]) })
+    })
+    .map(Result::unwrap)
+    .collect()
+    .await;
+
+    // A deployment in the set already is neither added nor removed
+    assert_eq!(
+        discoveries,
+        [Discovery::Added(deployment(1)), Discovery::Added(deployment(2)), Discovery::Removed(deployment(2))]
+    );
+    assert_eq!(deployments.addresses(), [Address::repeat_byte(1), Address::repeat_byte(3)]);
+    assert_eq!(deployments.get(&Address::repeat_byte(3)).unwrap().name, "known");
+}
+
+#[tokio::test]
+async fn watches_factory_deployments_from_the_block_of_their_event() {
+    let deployments = Deployments::new();
+    let starts = Arc::new(Mutex::new(Vec::new()));
+    let opened = starts.clone();
+    let events = ContractWatcher::new(EventStream::new(Filter::new()), deployments.clone()).watch(move |stream| {
+        opened.lock().unwrap().push(stream.start_block());
+        async move { Ok(futures::stream::pending::<Result<StreamEvent<()>, EventStreamError>>()) }
+    });
+    futures::pin_mut!(events);
+    let _ = tokio::time::timeout(Duration::from_millis(20), events.next()).await;
+
+    let announced = futures::stream::iter([Ok(StreamEvent::Observed(created(1, 0, Address::repeat_byte(1))))]);
+    let discoveries = from_factory_events(deployments.clone(), announced, |log| {
+        Some(Deployment { address: log.data, name: "pool".to_string() })
+    });
+    assert_eq!(discoveries.count().await, 1);
+
+    // The new batch backfills the events the deployment emitted since it was created
+    let _ = tokio::time::timeout(Duration::from_millis(20), events.next()).await;
+    assert_eq!(*starts.lock().unwrap(), [Some(1)]);
+}
+
+#[tokio::test]
+async fn watches_contracts_deployed_by_the_signers() -> eyre::Result<()> {
+    let Some(node) = TestNode::ws(Anvil::new()).await? else { return Ok(()) };
+    let config = &node.config;
+    let provider = &node.provider;
+    let signer = config.signer.address();
+
+    // Follow the contracts deployed by the signer from the current block on
+    let deployments = Deployments::new();
+    let discoveries = DeployDiscovery::new([signer])
+        .from_block(provider.get_block_number().await? + 1)
+        .with_poll_interval(Duration::from_millis(50))
+        .discover(provider.clone(), deployments.clone());
+    futures::pin_mut!(discoveries);
+    let stream = EventStream::new(Filter::new()).with_finality_depth(1);
+    let events = ContractWatcher::new(stream, deployments.clone()).subscribe::<SampleContractEvents, _, _>(provider.clone());
+    futures::pin_mut!(events);
+    let _ = tokio::time::timeout(Duration::from_millis(200), events.next()).await;
+
+    // The deployment is discovered from its receipt, and its events are watched
+    let contract = node.deploy().await?;
+    let discovery = tokio::time::timeout(Duration::from_secs(10), discoveries.next()).await?.unwrap()?;
+    let Discovery::Added(deployment) = discovery else { panic!("{discovery:?}") };
+    assert_eq!(deployment.address, *contract.address());
+    assert!(deployment.name.starts_with(&format!("{signer:#x} in block ")));
+
+    let _ = tokio::time::timeout(Duration::from_millis(200), events.next()).await;
+    contract.setValue(U256::from(2)).send().await?.get_receipt().await?;
+    let watched = loop {
+        let watched = tokio::time::timeout(Duration::from_secs(10), events.next()).await?.unwrap()?;
+        if let SampleContractEvents::ValueChanged(ValueChanged { newValue, .. }) = &watched.event.log().data {
+            assert_eq!(*newValue, U256::from(2));
+            break watched;
+        }
+    };
+    assert_eq!(watched.deployment, deployment);
+
+    Ok(())
+}
@@ -0,0 +1,124 @@
+//! Reorg handling of contract event streams.
+
+use std::sync::Arc;
+use std::time::Duration;
+use alloy_in_action_common::{Anvil, EventLog, EventStream, ReorgBuffer, SampleContract, StreamEvent, TestNode};
+use alloy_primitives::{Address, B256, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::Filter;
+use futures::StreamExt;
+use SampleContract::SampleContractEvents;
+
+fn observed(log: EventLog<u64>) -> StreamEvent<u64> {
+    StreamEvent::Observed(Arc::new(log))
+}
+
+fn retracted(log: EventLog<u64>) -> StreamEvent<u64> {
+    StreamEvent::Retracted(Arc::new(log))
+}
+
+fn finalized(log: EventLog<u64>) -> StreamEvent<u64> {
+    StreamEvent::Finalized(Arc::new(log))
+}
+
+fn event(block_number: u64, block_hash: u8, log_index: u64) -> EventLog<u64> {
+    EventLog {
+        block_number,
+        block_hash: B256::repeat_byte(block_hash),
+        transaction_hash: B256::ZERO,
+        log_index,
+        address: Address::ZERO,
+        data: block_number * 100 + log_index,
+    }
+}
+
+#[test]
+fn observes_each_event_once() {
+    let mut buffer = ReorgBuffer::new(3);
+    assert_eq!(buffer.observe(event(10, 1, 0)), [observed(event(10, 1, 0))]);
+    assert_eq!(buffer.observe(event(10, 1, 0)), []);
+    assert_eq!(buffer.observe(event(11, 2, 1)), [observed(event(11, 2, 1))]);
+    assert_eq!(buffer.pending_blocks(), [(10, B256::repeat_byte(1)), (11, B256::repeat_byte(2))]);
+}
+
+#[test]
+fn retracts_events_of_replaced_blocks() {
+    let mut buffer = ReorgBuffer::new(3);
+    buffer.observe(event(10, 1, 0));
+    buffer.observe(event(11, 2, 0));
+    buffer.observe(event(11, 2, 1));
+
+    // Another block at height 11 replaces the old one and everything after it
+    assert_eq!(
+        buffer.observe(event(11, 3, 0)),
+        [
+            retracted(event(11, 2, 1)),
+            retracted(event(11, 2, 0)),
+            observed(event(11, 3, 0)),
+        ]
+    );
+
+    // Removed logs retract only the matching event
+    assert_eq!(buffer.remove(&event(10, 9, 0)), None);
+    assert_eq!(buffer.remove(&event(10, 1, 0)), Some(retracted(event(10, 1, 0))));
+    assert_eq!(buffer.reorg(11), [retracted(event(11, 3, 0))]);
+    assert_eq!(buffer.pending().count(), 0);
+}
+
+#[test]
+fn finalizes_at_depth_and_ignores_late_duplicates() {
+    let mut buffer = ReorgBuffer::new(3);
+    buffer.observe(event(10, 1, 0));
+    buffer.observe(event(11, 2, 0));
+
+    assert_eq!(buffer.finalizable_blocks(11), []);
+    assert_eq!(buffer.finalize(11), []);
+    assert_eq!(buffer.finalizable_blocks(12), [(10, B256::repeat_byte(1))]);
+    assert_eq!(buffer.finalize(12), [finalized(event(10, 1, 0))]);
+    assert_eq!(buffer.finalized_block(), Some(10));
+
+    // A redelivered finalized event is ignored, a late new one in a finalized block is not
+    assert_eq!(buffer.observe(event(10, 1, 0)), []);
+    assert_eq!(buffer.observe(event(10, 1, 1)), [observed(event(10, 1, 1))]);
+    assert_eq!(
+        buffer.finalize(13),
+        [finalized(event(10, 1, 1)), finalized(event(11, 2, 0))]
+    );
+}
+
+#[tokio::test]
+async fn retracts_events_reorganized_away() -> eyre::Result<()> {
+    let Some(node) = TestNode::ws(Anvil::new()).await? else { return Ok(()) };
+    let provider = &node.provider;
+    let contract = node.deploy().await?;
+
+    let stream = EventStream::new(Filter::new().address(*contract.address())).with_finality_depth(3);
+    let events = stream.subscribe::<SampleContractEvents, _, _>(provider.clone()).await?;
+    futures::pin_mut!(events);
+
+    // Observe a ValueChanged event, then revert its block away
+    let snapshot: U256 = provider.client().request_noparams("evm_snapshot").await?;
+    contract.setValue(U256::from(2)).send().await?.get_receipt().await?;
+    let observed = tokio::time::timeout(Duration::from_secs(10), events.next()).await?.unwrap()?;
+    assert!(matches!(observed.log().data, SampleContractEvents::ValueChanged(_)), "{observed:?}");
+    assert!(matches!(observed, StreamEvent::Observed(_)));
+
+    let _: bool = provider.client().request("evm_revert", (snapshot,)).await?;
+    let _: String = provider.client().request_noparams("evm_mine").await?;
+    let retracted = tokio::time::timeout(Duration::from_secs(10), events.next()).await?.unwrap()?;
+    assert!(matches!(retracted, StreamEvent::Retracted(_)), "{retracted:?}");
+    assert_eq!(retracted.log(), observed.log());
+
+    // A new event on the new chain is observed and finalized 3 blocks deep
+    contract.setValue(U256::from(3)).send().await?.get_receipt().await?;
+    let observed = tokio::time::timeout(Duration::from_secs(10), events.next()).await?.unwrap()?;
+    assert!(matches!(observed, StreamEvent::Observed(_)));
+    for _ in 0..2 {
+        let _: String = provider.client().request_noparams("evm_mine").await?;
+    }
+    let finalized = tokio::time::timeout(Duration::from_secs(10), events.next()).await?.unwrap()?;
+    assert!(matches!(finalized, StreamEvent::Finalized(_)), "{finalized:?}");
+    assert_eq!(finalized.log(), observed.log());
+
+    Ok(())
+}
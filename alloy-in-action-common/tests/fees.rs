@@ -0,0 +1,52 @@
+use alloy_in_action_common::{FeeEstimator, FeeStrategy};
+use alloy_in_action_common::fees::{complete_fees, project_base_fee, DEFAULT_MIN_PRIORITY_FEE};
+use alloy_provider::utils::Eip1559Estimation;
+
+const GWEI: u128 = 1_000_000_000;
+
+#[test]
+fn projects_base_fee_ahead() {
+    // No projection keeps the current base fee
+    assert_eq!(project_base_fee(1_000, 15, 30, 0), 1_000);
+    // Half-full block: unchanged next block, then full blocks (+12.5% each)
+    assert_eq!(project_base_fee(1_000, 15, 30, 1), 1_000);
+    assert_eq!(project_base_fee(1_000, 15, 30, 3), 1_265);
+}
+
+#[test]
+fn estimates_tip_from_fee_history() {
+    let estimator = FeeEstimator::new(FeeStrategy::Fast).with_blocks_ahead(1);
+    let fees = estimator.estimate_from(10 * GWEI as u64, 15, 30, &[2 * GWEI, 4 * GWEI, 3 * GWEI]);
+    assert_eq!(fees.max_priority_fee_per_gas, 3 * GWEI);
+    assert_eq!(fees.max_fee_per_gas, 13 * GWEI);
+
+    // Idle chains paid no tips: fall back to the minimum priority fee
+    let fees = estimator.estimate_from(10 * GWEI as u64, 15, 30, &[]);
+    assert_eq!(fees.max_priority_fee_per_gas, DEFAULT_MIN_PRIORITY_FEE);
+}
+
+#[test]
+fn caps_max_fee() {
+    let estimator = FeeEstimator::new(FeeStrategy::Normal)
+        .with_blocks_ahead(1)
+        .with_max_fee_cap(5 * GWEI);
+    let fees = estimator.estimate_from(10 * GWEI as u64, 15, 30, &[8 * GWEI]);
+    assert_eq!(fees.max_fee_per_gas, 5 * GWEI);
+    assert_eq!(fees.max_priority_fee_per_gas, 5 * GWEI);
+}
+
+#[test]
+fn completes_partially_set_fees() {
+    let estimate = Eip1559Estimation { max_fee_per_gas: 13 * GWEI, max_priority_fee_per_gas: 3 * GWEI };
+    let fees = |max_fee_per_gas, max_priority_fee_per_gas| {
+        let fees = complete_fees(max_fee_per_gas, max_priority_fee_per_gas, estimate);
+        (fees.max_fee_per_gas, fees.max_priority_fee_per_gas)
+    };
+
+    assert_eq!(fees(None, None), (13 * GWEI, 3 * GWEI));
+    // The set fee is kept, the estimated priority fee stays within the set max fee
+    assert_eq!(fees(Some(20 * GWEI), None), (20 * GWEI, 3 * GWEI));
+    assert_eq!(fees(Some(2 * GWEI), None), (2 * GWEI, 2 * GWEI));
+    // The max fee covers the estimated base fee and the set priority fee
+    assert_eq!(fees(None, Some(5 * GWEI)), (15 * GWEI, 5 * GWEI));
+}
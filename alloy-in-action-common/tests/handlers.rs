@@ -0,0 +1,116 @@
+//! Dispatching logs to typed event handlers.
+
+use std::sync::{Arc, Mutex};
+use alloy_in_action_common::{EventLog, HandlerError, HandlerRegistry, RawEvent, RawLog, SampleContract};
+use alloy_primitives::{Address, LogData, B256, U256};
+use alloy_sol_types::SolEvent;
+use eyre::bail;
+use SampleContract::{EtherReceived, EtherWithdrawn, ValueChanged};
+
+fn raw_log(log_index: u64, data: LogData) -> RawLog {
+    Arc::new(EventLog {
+        block_number: 1,
+        block_hash: B256::repeat_byte(1),
+        transaction_hash: B256::repeat_byte(2),
+        log_index,
+        address: Address::repeat_byte(3),
+        data: RawEvent(data),
+    })
+}
+
+fn value_changed(new_value: u64) -> LogData {
+    ValueChanged { updater: Address::repeat_byte(0xa1), oldValue: U256::from(1), newValue: U256::from(new_value) }
+        .encode_log_data()
+}
+
+/// A registry recording what each handler saw, whose `EtherWithdrawn` handler fails.
+fn registry(seen: &Arc<Mutex<Vec<String>>>) -> HandlerRegistry {
+    let (first, second, received, unknown) = (seen.clone(), seen.clone(), seen.clone(), seen.clone());
+    HandlerRegistry::new()
+        .on(move |event: ValueChanged, _log| {
+            let seen = first.clone();
+            async move {
+                seen.lock().unwrap().push(format!("first {}", event.newValue));
+                Ok(())
+            }
+        })
+        .on(move |event: ValueChanged, log| {
+            let seen = second.clone();
+            async move {
+                seen.lock().unwrap().push(format!("second {} at {}", event.newValue, log.log_index));
+                Ok(())
+            }
+        })
+        .on(move |event: EtherReceived, _log| {
+            let seen = received.clone();
+            async move {
+                seen.lock().unwrap().push(format!("received {}", event.amount));
+                Ok(())
+            }
+        })
+        .on(|_: EtherWithdrawn, _log| async move { bail!("insufficient funds") })
+        .on_unknown(move |log| {
+            let seen = unknown.clone();
+            async move {
+                seen.lock().unwrap().push(format!("unknown {:?}", log.data.0.topics().first()));
+                Ok(())
+            }
+        })
+}
+
+#[tokio::test]
+async fn dispatches_to_handlers_of_the_event_type() {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let handlers = registry(&seen);
+    assert_eq!(
+        handlers.event_signatures(),
+        [ValueChanged::SIGNATURE_HASH, EtherReceived::SIGNATURE_HASH, EtherWithdrawn::SIGNATURE_HASH]
+    );
+
+    assert!(handlers.dispatch(raw_log(0, value_changed(2))).await.is_empty());
+    let received = EtherReceived { sender: Address::ZERO, amount: U256::from(5), newBalance: U256::from(5) };
+    assert!(handlers.dispatch(raw_log(1, received.encode_log_data())).await.is_empty());
+    assert_eq!(*seen.lock().unwrap(), ["first 2", "second 2 at 0", "received 5"]);
+}
+
+#[tokio::test]
+async fn falls_back_to_the_raw_log_for_unknown_topics() {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let handlers = registry(&seen);
+
+    // No handler for the topic, and a known topic whose data does not decode
+    let topic = B256::repeat_byte(0xee);
+    handlers.dispatch(raw_log(0, LogData::new_unchecked(vec![topic], Default::default()))).await;
+    let truncated = LogData::new_unchecked(value_changed(2).topics().to_vec(), Default::default());
+    handlers.dispatch(raw_log(1, truncated)).await;
+
+    assert_eq!(
+        *seen.lock().unwrap(),
+        [format!("unknown {:?}", Some(topic)), format!("unknown {:?}", Some(ValueChanged::SIGNATURE_HASH))]
+    );
+}
+
+#[tokio::test]
+async fn isolates_failing_and_panicking_handlers() {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let recorded = seen.clone();
+    let handlers = HandlerRegistry::new()
+        .on(|_: ValueChanged, _log| async move { panic!("handler bug") })
+        .on(|_: ValueChanged, _log| async move { bail!("database unavailable") })
+        .on(move |event: ValueChanged, _log| {
+            let seen = recorded.clone();
+            async move {
+                seen.lock().unwrap().push(event.newValue);
+                Ok(())
+            }
+        });
+
+    let errors = handlers.dispatch(raw_log(0, value_changed(3))).await;
+    assert!(matches!(&errors[0], HandlerError::Panicked { event: "ValueChanged", message } if message == "handler bug"));
+    assert!(matches!(&errors[1], HandlerError::Failed { event: "ValueChanged", .. }));
+    assert_eq!(errors[1].to_string(), "ValueChanged handler failed: database unavailable");
+
+    // The remaining handler ran, and the registry keeps dispatching
+    handlers.dispatch(raw_log(1, value_changed(4))).await;
+    assert_eq!(*seen.lock().unwrap(), [U256::from(3), U256::from(4)]);
+}
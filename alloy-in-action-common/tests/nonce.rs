@@ -0,0 +1,146 @@
+//! Nonce allocation and recovery from nonce errors.
+
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use alloy_in_action_common::{Anvil, NonceErrorExt, NonceManager, TestNode};
+use alloy_json_rpc::{ErrorPayload, RequestPacket, Response, ResponsePacket, ResponsePayload};
+use alloy_network::TransactionBuilder;
+use alloy_primitives::{Address, U256};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_client::RpcClient;
+use alloy_rpc_types::{TransactionRequest, TransactionTrait};
+use alloy_transport::{TransportError, TransportFut};
+use futures::channel::oneshot;
+use serde_json::value::RawValue;
+use tower::Service;
+
+fn transport_error(message: &str) -> TransportError {
+    TransportError::ErrorResp(ErrorPayload { code: -32000, message: message.to_string().into(), data: None })
+}
+
+/// A node answering `eth_getTransactionCount` with a fixed pending transaction count.
+#[derive(Clone)]
+struct PendingCountNode {
+    pending: u64,
+}
+
+impl Service<RequestPacket> for PendingCountNode {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), TransportError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let RequestPacket::Single(request) = request else { unimplemented!("batch request") };
+        assert_eq!(request.method(), "eth_getTransactionCount");
+        let count = RawValue::from_string(format!("\"{:#x}\"", self.pending)).unwrap();
+        let response = Response { id: request.id().clone(), payload: ResponsePayload::Success(count) };
+        Box::pin(async move { Ok(ResponsePacket::Single(response)) })
+    }
+}
+
+#[test]
+fn detects_nonce_errors() {
+    assert!(transport_error("nonce too low").is_nonce_error());
+    assert!(transport_error("Nonce too low: next nonce 5, tx nonce 3").is_nonce_error());
+    assert!(transport_error("replacement transaction underpriced").is_nonce_error());
+    assert!(!transport_error("insufficient funds for gas * price + value").is_nonce_error());
+    assert!(!alloy_contract::Error::from(transport_error("execution reverted")).is_nonce_error());
+
+    // Only a nonce that does not follow the account's transactions calls for a resync
+    assert!(transport_error("nonce too high").is_nonce_mismatch());
+    assert!(alloy_contract::Error::from(transport_error("nonce too low")).is_nonce_mismatch());
+    assert!(!transport_error("replacement transaction underpriced").is_nonce_mismatch());
+    assert!(!transport_error("insufficient funds for gas * price + value").is_nonce_mismatch());
+}
+
+#[tokio::test]
+async fn never_hands_out_a_reserved_nonce_twice() -> eyre::Result<()> {
+    let provider = ProviderBuilder::new().on_client(RpcClient::new(PendingCountNode { pending: 0 }, true));
+    let signer = Address::repeat_byte(1);
+    let nonce_manager = NonceManager::default();
+    let (release, released) = oneshot::channel::<()>();
+    let released = Arc::new(Mutex::new(Some(released)));
+
+    // The first transaction holds nonce 0 until the others are done
+    let in_flight = nonce_manager.send_with_nonce(&provider, signer, |nonce| {
+        let released = released.lock().unwrap().take().expect("sent once");
+        async move {
+            released.await.unwrap();
+            Ok::<_, TransportError>(nonce)
+        }
+    });
+    let others = async {
+        tokio::task::yield_now().await;
+
+        // A failure other than a nonce mismatch keeps the local nonce
+        let error = nonce_manager
+            .send_with_nonce(&provider, signer, |_| async { Err::<u64, _>(transport_error("insufficient funds")) })
+            .await
+            .unwrap_err();
+        assert!(!error.is_nonce_mismatch());
+
+        // The gap it may leave resyncs, but not back to the nonce still in flight
+        let attempts = Mutex::new(Vec::new());
+        let nonce = nonce_manager
+            .send_with_nonce(&provider, signer, |nonce| {
+                attempts.lock().unwrap().push(nonce);
+                let result = if nonce == 2 { Err(transport_error("nonce too high")) } else { Ok(nonce) };
+                async move { result }
+            })
+            .await?;
+        assert_eq!(*attempts.lock().unwrap(), [2, 1]);
+        release.send(()).unwrap();
+        Ok::<_, TransportError>(nonce)
+    };
+    let (first, last) = tokio::try_join!(in_flight, others)?;
+    assert_eq!((first, last), (0, 1));
+    assert_eq!(nonce_manager.next_nonce(&provider, signer).await?, 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn hands_out_sequential_nonces_and_resyncs() -> eyre::Result<()> {
+    let Some(node) = TestNode::http(Anvil::new())? else { return Ok(()) };
+    let config = &node.config;
+    let provider = &node.provider;
+    let signer = config.signer.address();
+    let contract = node.deploy().await?;
+
+    // Two contract calls in flight at once get consecutive nonces
+    let nonce_manager = NonceManager::default();
+    let (first, second) = tokio::try_join!(
+        nonce_manager.send_with_nonce(provider, signer, |nonce| {
+            let call = contract.setValue(U256::from(2)).nonce(nonce);
+            async move { call.send().await }
+        }),
+        nonce_manager.send_with_nonce(provider, signer, |nonce| {
+            let call = contract.setValue(U256::from(3)).nonce(nonce);
+            async move { call.send().await }
+        }),
+    )?;
+    let (first, second) = tokio::try_join!(first.get_receipt(), second.get_receipt())?;
+    let nonces = [
+        provider.get_transaction_by_hash(first.transaction_hash).await?.expect("mined").nonce(),
+        provider.get_transaction_by_hash(second.transaction_hash).await?.expect("mined").nonce(),
+    ];
+    assert!(nonces == [1, 2] || nonces == [2, 1], "{nonces:?}");
+
+    // A transaction sent behind the manager's back makes its next nonce stale
+    contract.setValue(U256::from(4)).send().await?.get_receipt().await?;
+    let tx = TransactionRequest::default().with_to(signer).with_value(U256::from(1));
+    let receipt = nonce_manager
+        .send_with_nonce(provider, signer, |nonce| provider.send_transaction(tx.clone().with_nonce(nonce)))
+        .await?
+        .get_receipt()
+        .await?;
+    let tx = provider.get_transaction_by_hash(receipt.transaction_hash).await?.expect("mined");
+    assert_eq!(tx.nonce(), 4);
+    assert_eq!(nonce_manager.next_nonce(provider, signer).await?, 5);
+
+    Ok(())
+}
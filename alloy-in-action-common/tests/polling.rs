@@ -0,0 +1,48 @@
+//! Following contract events by polling over HTTP.
+
+use std::time::Duration;
+use alloy_in_action_common::{Anvil, EventStream, SampleContract, StreamEvent, TestNode};
+use alloy_primitives::U256;
+use alloy_provider::Provider;
+use alloy_rpc_types::Filter;
+use futures::StreamExt;
+use SampleContract::SampleContractEvents;
+
+#[tokio::test]
+async fn polls_new_events_and_retracts_replaced_blocks() -> eyre::Result<()> {
+    let Some(node) = TestNode::http(Anvil::new())? else { return Ok(()) };
+    let provider = &node.provider;
+    let contract = node.deploy().await?;
+
+    let stream = EventStream::new(Filter::new().address(*contract.address()))
+        .with_finality_depth(3)
+        .with_poll_interval(Duration::from_millis(50));
+    let events = stream.poll::<SampleContractEvents, _, _>(provider.clone()).await?;
+    futures::pin_mut!(events);
+
+    // Observe a ValueChanged event, then replace its block with an empty one
+    let snapshot: U256 = provider.client().request_noparams("evm_snapshot").await?;
+    contract.setValue(U256::from(2)).send().await?.get_receipt().await?;
+    let observed = tokio::time::timeout(Duration::from_secs(10), events.next()).await?.unwrap()?;
+    assert!(matches!(observed, StreamEvent::Observed(_)), "{observed:?}");
+
+    let _: bool = provider.client().request("evm_revert", (snapshot,)).await?;
+    let _: String = provider.client().request_noparams("evm_mine").await?;
+    let retracted = tokio::time::timeout(Duration::from_secs(10), events.next()).await?.unwrap()?;
+    assert!(matches!(retracted, StreamEvent::Retracted(_)), "{retracted:?}");
+    assert_eq!(retracted.log(), observed.log());
+
+    // Events of several blocks mined between two polls arrive in order
+    contract.setValue(U256::from(3)).send().await?.get_receipt().await?;
+    contract.setValue(U256::from(4)).send().await?.get_receipt().await?;
+    let mut values = Vec::new();
+    while values.len() < 2 {
+        let event = tokio::time::timeout(Duration::from_secs(10), events.next()).await?.unwrap()?;
+        if let (StreamEvent::Observed(_), SampleContractEvents::ValueChanged(event)) = (&event, &event.log().data) {
+            values.push(event.newValue.to::<u64>());
+        }
+    }
+    assert_eq!(values, [3, 4]);
+
+    Ok(())
+}
@@ -0,0 +1,158 @@
+//! Queueing event streams for slow consumers.
+
+use std::sync::Arc;
+use std::time::Duration;
+use alloy_in_action_common::{
+    EventLog, EventQueue, EventStreamError, OverflowPolicy, QueueError, QueueMetrics, QueueReceiver, RawEvent,
+    StreamEvent,
+};
+use alloy_primitives::{Address, LogData, B256};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+
+/// Event data that can be spilled, but not read back for the value `5`.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "u64")]
+struct Picky(u64);
+
+impl TryFrom<u64> for Picky {
+    type Error = String;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        if value == 5 {
+            return Err("unlucky".to_string());
+        }
+        Ok(Self(value))
+    }
+}
+
+/// An observed event in block `block_number`, one per block.
+fn observed<E>(block_number: u64, data: E) -> StreamEvent<E> {
+    StreamEvent::Observed(Arc::new(EventLog {
+        block_number,
+        block_hash: B256::with_last_byte(block_number as u8),
+        transaction_hash: B256::repeat_byte(2),
+        log_index: 0,
+        address: Address::repeat_byte(3),
+        data,
+    }))
+}
+
+/// An undecoded event in block `block_number`.
+fn raw(block_number: u64) -> StreamEvent<RawEvent> {
+    observed(block_number, RawEvent(LogData::new_unchecked(vec![B256::repeat_byte(4)], vec![block_number as u8].into())))
+}
+
+/// A stream of events in blocks `1..=count`.
+fn events<E>(
+    count: u64,
+    data: impl Fn(u64) -> E,
+) -> impl futures::Stream<Item = Result<StreamEvent<E>, EventStreamError>> {
+    futures::stream::iter((1..=count).map(move |block_number| Ok(observed(block_number, data(block_number)))))
+}
+
+/// A stream of undecoded events in blocks `1..=count`.
+fn raw_events(count: u64) -> impl futures::Stream<Item = Result<StreamEvent<RawEvent>, EventStreamError>> {
+    futures::stream::iter((1..=count).map(|block_number| Ok(raw(block_number))))
+}
+
+/// Waits until the queue reached `depth`.
+async fn wait_for_depth<E>(receiver: &QueueReceiver<E>, depth: usize) -> QueueMetrics {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let metrics = receiver.metrics();
+            if metrics.depth == depth {
+                return metrics;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("queue depth not reached")
+}
+
+async fn block_numbers<E>(receiver: QueueReceiver<E>) -> Vec<u64> {
+    receiver.into_stream().map(|event| event.unwrap().log().block_number).collect().await
+}
+
+#[tokio::test]
+async fn blocks_the_stream_while_full() {
+    let mut receiver = EventQueue::new(3).spawn(raw_events(10));
+
+    // The stream is not read beyond the capacity until the consumer takes an event
+    let metrics = wait_for_depth(&receiver, 3).await;
+    assert_eq!(metrics, QueueMetrics { depth: 3, spilled: 0, dropped: 0, lag_blocks: 2 });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(receiver.metrics().depth, 3);
+
+    assert_eq!(receiver.recv().await.unwrap().unwrap(), raw(1));
+    wait_for_depth(&receiver, 3).await;
+    assert_eq!(receiver.metrics().lag_blocks, 2);
+    assert_eq!(block_numbers(receiver).await, (2..=10).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn drops_the_oldest_events_when_full() {
+    let receiver = EventQueue::new(3).with_overflow(OverflowPolicy::DropOldest).spawn(raw_events(10));
+
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(receiver.metrics(), QueueMetrics { depth: 3, spilled: 0, dropped: 7, lag_blocks: 2 });
+    assert_eq!(block_numbers(receiver).await, [8, 9, 10]);
+}
+
+#[tokio::test]
+async fn spills_to_disk_in_order_when_full() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut receiver = EventQueue::new(3).spawn_spilling(dir.path(), events(10, |n| n)).unwrap();
+
+    let metrics = wait_for_depth(&receiver, 10).await;
+    assert_eq!(metrics, QueueMetrics { depth: 10, spilled: 7, dropped: 0, lag_blocks: 9 });
+    let files: Vec<_> = std::fs::read_dir(dir.path()).unwrap().map(|entry| entry.unwrap().path()).collect();
+    assert_eq!(files.len(), 1);
+    assert_eq!(std::fs::read_to_string(&files[0]).unwrap().lines().count(), 7);
+
+    // Events are read back from disk once the ones in memory were taken
+    for block_number in 1..=4 {
+        assert_eq!(receiver.recv().await.unwrap().unwrap(), observed(block_number, block_number));
+    }
+    assert_eq!(receiver.metrics(), QueueMetrics { depth: 6, spilled: 6, dropped: 0, lag_blocks: 5 });
+    assert_eq!(block_numbers(receiver).await, (5..=10).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn skips_spilled_events_that_cannot_be_read_back() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("events.jsonl"), "kept").unwrap();
+    let mut receiver = EventQueue::new(2).spawn_spilling(dir.path(), events(8, Picky)).unwrap();
+    wait_for_depth(&receiver, 8).await;
+
+    // Only the event that cannot be decoded is lost, and counted as dropped
+    let mut block_numbers = Vec::new();
+    while let Some(event) = receiver.recv().await {
+        block_numbers.push(event.unwrap().log().block_number);
+    }
+    assert_eq!(block_numbers, [1, 2, 3, 4, 6, 7, 8]);
+    assert_eq!(receiver.metrics(), QueueMetrics { depth: 0, spilled: 0, dropped: 1, lag_blocks: 0 });
+    drop(receiver);
+
+    // The spill file is removed with the queue, and the other files are left alone
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let files: Vec<_> = std::fs::read_dir(dir.path()).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    assert_eq!(files, ["events.jsonl"]);
+    assert_eq!(std::fs::read_to_string(dir.path().join("events.jsonl")).unwrap(), "kept");
+}
+
+#[tokio::test]
+async fn delivers_the_stream_error_after_the_queued_events() {
+    let failing = raw_events(2).chain(futures::stream::iter([Err(EventStreamError::SubscriptionClosed)]));
+    let mut receiver = EventQueue::new(3).spawn(failing);
+
+    assert_eq!(receiver.recv().await.unwrap().unwrap(), raw(1));
+    assert_eq!(receiver.recv().await.unwrap().unwrap(), raw(2));
+    assert!(matches!(
+        receiver.recv().await,
+        Some(Err(QueueError::Stream(EventStreamError::SubscriptionClosed)))
+    ));
+    assert!(receiver.recv().await.is_none());
+    assert_eq!(receiver.metrics(), QueueMetrics::default());
+}
@@ -0,0 +1,90 @@
+//! Extraction of SampleContract events from receipts.
+
+use alloy_in_action_common::{ReceiptEvents, ReceiptExt, SampleContract};
+use alloy_primitives::{address, Address, LogData, B256, U256};
+use alloy_rpc_types::{Log, TransactionReceipt};
+use alloy_sol_types::SolEvent;
+use SampleContract::{EtherReceived, SampleContractEvents, ValueChanged};
+
+const CONTRACT: Address = address!("5FbDB2315678afecb367f032d93F642f64180aa3");
+const SIGNER: Address = address!("f39Fd6e51aad88F6F4ce6aB8827279cffFb92266");
+
+fn log(log_index: u64, data: LogData) -> Log {
+    Log {
+        inner: alloy_primitives::Log { address: CONTRACT, data },
+        log_index: Some(log_index),
+        ..Default::default()
+    }
+}
+
+fn value_changed(old_value: u64, new_value: u64) -> ValueChanged {
+    ValueChanged { updater: SIGNER, oldValue: U256::from(old_value), newValue: U256::from(new_value) }
+}
+
+fn receipt(logs: &[Log]) -> TransactionReceipt {
+    serde_json::from_value(serde_json::json!({
+        "type": "0x2",
+        "status": "0x1",
+        "cumulativeGasUsed": "0x5208",
+        "logs": logs,
+        "logsBloom": format!("0x{}", "00".repeat(256)),
+        "transactionHash": B256::repeat_byte(0x11),
+        "transactionIndex": "0x0",
+        "blockHash": B256::repeat_byte(0x22),
+        "blockNumber": "0x1",
+        "gasUsed": "0x5208",
+        "effectiveGasPrice": "0x1",
+        "from": SIGNER,
+        "to": CONTRACT,
+        "contractAddress": null
+    }))
+    .unwrap()
+}
+
+#[test]
+fn separates_decoded_and_undecoded_logs() {
+    let unknown = LogData::new_unchecked(vec![B256::repeat_byte(0xab)], Default::default());
+    let logs = [log(0, value_changed(1, 2).encode_log_data()), log(1, unknown)];
+
+    let events = ReceiptEvents::<SampleContractEvents>::from_logs(&logs);
+    assert_eq!(events.decoded.len(), 1);
+    assert_eq!(events.decoded[0].log_index, Some(0));
+    assert_eq!(events.decoded[0].address, CONTRACT);
+    assert_eq!(events.decoded[0].data, SampleContractEvents::ValueChanged(value_changed(1, 2)));
+    assert_eq!(events.undecoded.len(), 1);
+    assert_eq!(events.undecoded[0].log_index, Some(1));
+}
+
+#[test]
+fn selects_events_by_type() {
+    let deposit = EtherReceived { sender: SIGNER, amount: U256::from(10), newBalance: U256::from(10) };
+    let receipt = receipt(&[
+        log(0, value_changed(1, 2).encode_log_data()),
+        log(1, deposit.encode_log_data()),
+        log(2, value_changed(2, 3).encode_log_data()),
+    ]);
+
+    assert_eq!(receipt.events::<ValueChanged>().unwrap(), vec![value_changed(1, 2), value_changed(2, 3)]);
+    assert_eq!(receipt.expect_event::<EtherReceived>(), deposit);
+    assert_eq!(receipt.decode_events::<SampleContractEvents>().decoded.len(), 3);
+}
+
+#[test]
+#[should_panic(expected = "expected exactly one ValueChanged")]
+fn expect_event_rejects_multiple_matches() {
+    let receipt = receipt(&[
+        log(0, value_changed(1, 2).encode_log_data()),
+        log(1, value_changed(2, 3).encode_log_data()),
+    ]);
+    receipt.expect_event::<ValueChanged>();
+}
+
+#[test]
+#[should_panic(expected = "failed to decode ValueChanged")]
+fn expect_event_reports_undecodable_matches() {
+    // The signature of ValueChanged, without its indexed updater
+    let truncated = LogData::new_unchecked(vec![ValueChanged::SIGNATURE_HASH], Default::default());
+    let receipt = receipt(&[log(0, truncated)]);
+    assert!(receipt.events::<ValueChanged>().is_err());
+    receipt.expect_event::<ValueChanged>();
+}
@@ -0,0 +1,116 @@
+//! Reconnecting event streams.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use alloy_in_action_common::{Anvil, EventLog, EventStream, ReconnectPolicy, SampleContract, StreamEvent, TestNode};
+use alloy_primitives::U256;
+use alloy_provider::{ProviderBuilder, WsConnect};
+use alloy_rpc_types::Filter;
+use futures::StreamExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use SampleContract::SampleContractEvents;
+
+#[test]
+fn backs_off_exponentially_up_to_the_cap() {
+    let policy = ReconnectPolicy::new(Duration::from_millis(100), Duration::from_secs(1));
+    let backoffs: Vec<_> = (0..6).map(|attempt| policy.backoff(attempt)).collect();
+    assert_eq!(
+        backoffs,
+        [100, 200, 400, 800, 1000, 1000].map(|millis| Some(Duration::from_millis(millis)))
+    );
+    assert_eq!(policy.backoff(u32::MAX), Some(Duration::from_secs(1)));
+
+    let policy = policy.with_max_attempts(2);
+    assert_eq!(policy.backoff(1), Some(Duration::from_millis(200)));
+    assert_eq!(policy.backoff(2), None);
+}
+
+/// Forwards TCP connections to a local port, until cut.
+struct Proxy {
+    port: u16,
+    online: Arc<AtomicBool>,
+    connections: Arc<Mutex<Vec<JoinHandle<()>>>>,
+}
+
+impl Proxy {
+    async fn spawn(target: u16) -> std::io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let proxy = Self {
+            port: listener.local_addr()?.port(),
+            online: Arc::new(AtomicBool::new(true)),
+            connections: Arc::default(),
+        };
+
+        let (online, connections) = (proxy.online.clone(), proxy.connections.clone());
+        tokio::spawn(async move {
+            while let Ok((mut inbound, _)) = listener.accept().await {
+                // Refuse connections while cut by closing them right away
+                if !online.load(Ordering::SeqCst) {
+                    continue;
+                }
+                let connection = tokio::spawn(async move {
+                    if let Ok(mut outbound) = TcpStream::connect(("127.0.0.1", target)).await {
+                        let _ = tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await;
+                    }
+                });
+                connections.lock().unwrap().push(connection);
+            }
+        });
+        Ok(proxy)
+    }
+
+    /// Drops all forwarded connections and refuses new ones.
+    fn cut(&self) {
+        self.online.store(false, Ordering::SeqCst);
+        self.connections.lock().unwrap().drain(..).for_each(|connection| connection.abort());
+    }
+
+    fn restore(&self) {
+        self.online.store(true, Ordering::SeqCst);
+    }
+}
+
+#[tokio::test]
+async fn backfills_events_missed_while_disconnected() -> eyre::Result<()> {
+    let Some(node) = TestNode::http(Anvil::new())? else { return Ok(()) };
+    let contract = node.deploy().await?;
+
+    // Stream the events over a WebSocket connection through the proxy
+    let proxy = Proxy::spawn(node.anvil.port()).await?;
+    let ws_url = format!("ws://127.0.0.1:{}", proxy.port);
+    let stream = EventStream::new(Filter::new().address(*contract.address()))
+        .with_finality_depth(3)
+        .with_reconnect_policy(ReconnectPolicy::new(Duration::from_millis(50), Duration::from_millis(200)));
+    let events = stream
+        .subscribe_with_reconnect::<SampleContractEvents, _, _, _, _, _>(move || {
+            ProviderBuilder::new().on_ws(WsConnect::new(ws_url.clone()))
+        })
+        .await?;
+    futures::pin_mut!(events);
+
+    let mut next_observed = async || -> eyre::Result<Arc<EventLog<SampleContractEvents>>> {
+        loop {
+            let event = tokio::time::timeout(Duration::from_secs(20), events.next()).await?.unwrap()?;
+            if let StreamEvent::Observed(log) = event {
+                return Ok(log);
+            }
+        }
+    };
+
+    contract.setValue(U256::from(2)).send().await?.get_receipt().await?;
+    assert!(matches!(next_observed().await?.data, SampleContractEvents::ValueChanged(_)));
+
+    // Events emitted while the connection is down are backfilled once it is back
+    proxy.cut();
+    contract.deposit().value(U256::from(1000)).send().await?.get_receipt().await?;
+    contract.withdraw().send().await?.get_receipt().await?;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    proxy.restore();
+
+    assert!(matches!(next_observed().await?.data, SampleContractEvents::EtherReceived(_)));
+    assert!(matches!(next_observed().await?.data, SampleContractEvents::EtherWithdrawn(_)));
+
+    Ok(())
+}
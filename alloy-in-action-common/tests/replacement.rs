@@ -0,0 +1,68 @@
+//! Fee bumping and replacement of transactions that are not mined.
+
+use std::time::Duration;
+use alloy_in_action_common::replacement::bump_fees;
+use alloy_in_action_common::{Anvil, ReplacementError, ReplacingSender, TestNode};
+use alloy_network::TransactionBuilder;
+use alloy_primitives::U256;
+use alloy_provider::utils::Eip1559Estimation;
+use alloy_provider::Provider;
+use alloy_rpc_types::TransactionRequest;
+
+#[test]
+fn bumps_both_fees_by_at_least_ten_percent() {
+    let fees = Eip1559Estimation { max_fee_per_gas: 1_000, max_priority_fee_per_gas: 101 };
+    assert_eq!(
+        bump_fees(fees, 10),
+        Eip1559Estimation { max_fee_per_gas: 1_100, max_priority_fee_per_gas: 112 }
+    );
+
+    // Zero and tiny fees still increase
+    let fees = Eip1559Estimation { max_fee_per_gas: 1, max_priority_fee_per_gas: 0 };
+    assert_eq!(bump_fees(fees, 10), Eip1559Estimation { max_fee_per_gas: 2, max_priority_fee_per_gas: 1 });
+
+    // The priority fee never exceeds the max fee
+    let fees = Eip1559Estimation { max_fee_per_gas: 100, max_priority_fee_per_gas: 100 };
+    assert_eq!(bump_fees(fees, 50), Eip1559Estimation { max_fee_per_gas: 150, max_priority_fee_per_gas: 150 });
+}
+
+#[tokio::test]
+async fn replaces_until_mined() -> eyre::Result<()> {
+    // Without automatic mining every version stays pending until a block is mined manually
+    let Some(node) = TestNode::http(Anvil::new().arg("--no-mining"))? else { return Ok(()) };
+    let config = &node.config;
+    let provider = &node.provider;
+
+    let sender = ReplacingSender::new()
+        .with_timeout(Duration::from_millis(500))
+        .with_poll_interval(Duration::from_millis(50));
+    let tx = TransactionRequest::default()
+        .with_to(config.signer.address())
+        .with_value(U256::from(1))
+        .with_max_fee_per_gas(10_000_000_000)
+        .with_max_priority_fee_per_gas(1_000_000_000);
+
+    let mine = async {
+        tokio::time::sleep(Duration::from_millis(1_200)).await;
+        provider.client().request_noparams::<String>("evm_mine").await
+    };
+    let (sent, mined) = tokio::join!(sender.send(provider, tx), mine);
+    let sent = sent?;
+    mined?;
+
+    // Only the last version is left in the pool, and it paid the bumped fees
+    assert!(sent.hashes.len() >= 2, "{:?}", sent.hashes);
+    assert!(sent.was_replaced());
+    assert_eq!(sent.tx_hash(), *sent.hashes.last().unwrap());
+    assert!(sent.receipt.effective_gas_price > 1_000_000_000);
+
+    // Without mining the sender gives up after its last replacement
+    let sender = sender.with_max_replacements(1);
+    let tx = TransactionRequest::default().with_to(config.signer.address()).with_value(U256::from(1));
+    match sender.send(provider, tx).await {
+        Err(ReplacementError::NotMined { hashes }) => assert_eq!(hashes.len(), 2),
+        other => panic!("expected NotMined, got {other:?}"),
+    }
+
+    Ok(())
+}
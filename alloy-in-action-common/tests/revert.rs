@@ -0,0 +1,57 @@
+//! Decoding of revert data and JSON-RPC revert responses.
+
+use alloy_in_action_common::{Revert, RevertExt, SampleContract};
+use alloy_json_rpc::ErrorPayload;
+use alloy_primitives::{hex, U256};
+use alloy_sol_types::{PanicKind, SolError};
+use alloy_transport::TransportError;
+use SampleContract::{SampleContractErrors, SampleError};
+
+fn transport_error(message: &str, data: Option<&str>) -> TransportError {
+    let data = data.map(|data| serde_json::value::to_raw_value(data).unwrap());
+    TransportError::ErrorResp(ErrorPayload { code: 3, message: message.to_string().into(), data })
+}
+
+#[test]
+fn decodes_custom_errors() {
+    let data = SampleError { cause: "hello from revert!".into() }.abi_encode();
+    let revert = Revert::<SampleContractErrors>::from_revert_data(&data);
+    assert_eq!(revert, Revert::Custom(SampleContractErrors::SampleError(SampleError { cause: "hello from revert!".into() })));
+}
+
+#[test]
+fn decodes_error_string_and_panic() {
+    let data = alloy_sol_types::Revert::from("not allowed").abi_encode();
+    assert_eq!(Revert::<SampleContractErrors>::from_revert_data(&data), Revert::Error("not allowed".into()));
+
+    let data = alloy_sol_types::Panic { code: U256::from(0x11) }.abi_encode();
+    let revert = Revert::<SampleContractErrors>::from_revert_data(&data);
+    assert_eq!(revert, Revert::Panic { code: U256::from(0x11), kind: Some(PanicKind::UnderOverflow) });
+    assert_eq!(revert.to_string(), "Panic(0x11): arithmetic underflow or overflow");
+}
+
+#[test]
+fn keeps_unknown_selectors() {
+    let data = hex!("deadbeef0000");
+    let revert = Revert::<SampleContractErrors>::from_revert_data(&data);
+    assert!(matches!(revert, Revert::Unknown { selector: Some(selector), .. } if selector == hex!("deadbeef")));
+}
+
+#[test]
+fn decodes_rpc_error_responses() {
+    let data = format!("0x{}", hex::encode(SampleError { cause: "boom".into() }.abi_encode()));
+    let error = transport_error("execution reverted", Some(&data));
+    assert!(matches!(error.revert::<SampleContractErrors>(), Some(Revert::Custom(_))));
+
+    let error = transport_error("EVM error OutOfGas", None);
+    assert_eq!(error.revert::<SampleContractErrors>(), Some(Revert::OutOfGas));
+
+    let error = transport_error("execution reverted", None);
+    assert!(matches!(error.revert::<SampleContractErrors>(), Some(Revert::Unknown { selector: None, .. })));
+
+    let error = transport_error("nonce too low", None);
+    assert_eq!(error.revert::<SampleContractErrors>(), None);
+
+    let error = alloy_contract::Error::from(transport_error("execution reverted", Some(&data)));
+    assert!(error.revert::<SampleContractErrors>().is_some());
+}
@@ -0,0 +1,103 @@
+//! Supervising listener tasks until completion, and draining them.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use alloy_in_action_common::{Completion, SampleContract, StopReason, Supervisor, TaskOutcome};
+use eyre::bail;
+use SampleContract::{EtherReceived, ValueChanged};
+
+#[tokio::test]
+async fn stops_on_completion_after_draining_in_flight_handlers() {
+    let mut supervisor = Supervisor::new()
+        .until(Completion::events::<ValueChanged>(2))
+        .until(Completion::block(10));
+
+    // A listener processing events one by one until asked to stop
+    let progress = supervisor.progress();
+    supervisor.spawn("value listener", |mut shutdown| async move {
+        for block_number in 1.. {
+            tokio::select! {
+                _ = shutdown.requested() => break,
+                _ = tokio::time::sleep(Duration::from_millis(10)) => progress.record::<ValueChanged>(block_number),
+            }
+        }
+        Ok(())
+    });
+
+    // A listener that is in the middle of a slow handler when the shutdown is requested
+    let handled = Arc::new(AtomicBool::new(false));
+    let in_flight = handled.clone();
+    supervisor.spawn("slow listener", |shutdown| async move {
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(!shutdown.is_requested());
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        in_flight.store(true, Ordering::SeqCst);
+        Ok(())
+    });
+
+    let summary = supervisor.run().await;
+    assert_eq!(summary.reason, StopReason::Completed);
+    assert!(summary.events["ValueChanged"] >= 10);
+    assert!(summary.block >= Some(10));
+    assert!(handled.load(Ordering::SeqCst));
+    assert_eq!(
+        summary.tasks,
+        [("value listener".to_string(), TaskOutcome::Finished), ("slow listener".to_string(), TaskOutcome::Finished)]
+    );
+}
+
+#[tokio::test]
+async fn stops_once_any_of_the_alternative_conditions_holds() {
+    let mut supervisor = Supervisor::new()
+        .until_any(Completion::events::<ValueChanged>(100))
+        .until_any(Completion::block(3));
+    let progress = supervisor.progress();
+    supervisor.spawn("value listener", |shutdown| async move {
+        for block_number in 1..=5 {
+            if shutdown.is_requested() {
+                break;
+            }
+            progress.record::<ValueChanged>(block_number);
+            // A retracted event no longer counts
+            progress.retract::<ValueChanged>();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        Ok(())
+    });
+
+    let summary = supervisor.run().await;
+    assert_eq!(summary.reason, StopReason::Completed);
+    assert_eq!(summary.events["ValueChanged"], 0);
+    assert_eq!(summary.block, Some(3));
+}
+
+#[tokio::test]
+async fn aborts_tasks_that_do_not_drain() {
+    let mut supervisor = Supervisor::new()
+        .until(Completion::events::<EtherReceived>(1))
+        .with_drain_timeout(Duration::from_millis(50));
+    let progress = supervisor.progress();
+    supervisor.spawn("deposit listener", |_| async move {
+        progress.record::<EtherReceived>(1);
+        Ok(())
+    });
+    supervisor.spawn("stuck listener", |_| futures::future::pending());
+
+    let summary = supervisor.run().await;
+    assert_eq!(summary.reason, StopReason::Completed);
+    assert_eq!(summary.tasks[0].1, TaskOutcome::Finished);
+    assert_eq!(summary.tasks[1].1, TaskOutcome::Aborted);
+}
+
+#[tokio::test]
+async fn reports_tasks_that_exit_before_completion() {
+    let mut supervisor = Supervisor::new().until(Completion::block(100));
+    supervisor.spawn("failing listener", |_| async move { bail!("subscription closed") });
+    supervisor.spawn("panicking listener", |_| async move { panic!("handler bug") });
+
+    let summary = supervisor.run().await;
+    assert_eq!(summary.reason, StopReason::TasksExited);
+    assert_eq!(summary.tasks[0].1, TaskOutcome::Failed("subscription closed".to_string()));
+    assert_eq!(summary.tasks[1].1, TaskOutcome::Failed("panicked: handler bug".to_string()));
+}
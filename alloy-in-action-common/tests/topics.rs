@@ -0,0 +1,78 @@
+//! Typed filters on indexed event arguments.
+
+use alloy_in_action_common::{encode_topics, Anvil, SampleContract, TestNode};
+use alloy_primitives::{keccak256, Address, B256, I256, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::{Filter, Topic};
+use alloy_sol_types::{sol_data, SolEvent};
+use SampleContract::{EtherReceived, ValueChanged};
+
+#[test]
+fn encodes_indexed_arguments_of_each_type() {
+    let address = Address::repeat_byte(0xab);
+    assert_eq!(encode_topics::<sol_data::Address>([address]), [B256::left_padding_from(address.as_slice())]);
+    assert_eq!(encode_topics::<sol_data::Uint<256>>([U256::from(258)]), [B256::left_padding_from(&[1, 2])]);
+    assert_eq!(encode_topics::<sol_data::Int<256>>([I256::MINUS_ONE]), [B256::repeat_byte(0xff)]);
+    assert_eq!(encode_topics::<sol_data::Bool>([true, false]), [B256::with_last_byte(1), B256::ZERO]);
+    assert_eq!(encode_topics::<sol_data::FixedBytes<32>>([B256::repeat_byte(7)]), [B256::repeat_byte(7)]);
+
+    // Dynamic types are matched by the hash of their value
+    assert_eq!(encode_topics::<sol_data::String>(["alloy".to_string()]), [keccak256("alloy")]);
+    assert_eq!(encode_topics::<sol_data::Bytes>([vec![1u8, 2, 3].into()]), [keccak256([1u8, 2, 3])]);
+}
+
+#[test]
+#[should_panic(expected = "at least one value")]
+fn rejects_an_empty_set_of_values() {
+    encode_topics::<sol_data::Address>([]);
+}
+
+#[test]
+fn builds_filters_from_indexed_arguments() {
+    let (first, second) = (Address::repeat_byte(1), Address::repeat_byte(2));
+    let filter: Filter = ValueChanged::filter()
+        .address(Address::repeat_byte(3))
+        .updater_in([first, second])
+        .old_value(U256::from(1))
+        .into();
+
+    assert_eq!(filter.topics[0], Topic::from(ValueChanged::SIGNATURE_HASH));
+    assert_eq!(
+        filter.topics[1],
+        Topic::from(vec![B256::left_padding_from(&first.0 .0), B256::left_padding_from(&second.0 .0)])
+    );
+    assert_eq!(filter.topics[2], Topic::from(B256::from(U256::from(1))));
+    assert!(filter.topics[3].is_empty());
+    assert_eq!(filter.address.iter().collect::<Vec<_>>(), [&Address::repeat_byte(3)]);
+
+    let filter = EtherReceived::filter().sender(first).into_filter();
+    assert_eq!(filter.topics[0], Topic::from(EtherReceived::SIGNATURE_HASH));
+    assert_eq!(filter.topics[1], Topic::from(B256::left_padding_from(&first.0 .0)));
+}
+
+#[tokio::test]
+async fn matches_logs_on_a_node() -> eyre::Result<()> {
+    let Some(node) = TestNode::http(Anvil::new())? else { return Ok(()) };
+    let config = &node.config;
+    let provider = &node.provider;
+    let contract = node.deploy().await?;
+    for value in [2, 3, 4] {
+        contract.setValue(U256::from(value)).send().await?.get_receipt().await?;
+    }
+
+    // Only the update from 2 to 3, by the signer
+    let filter = ValueChanged::filter()
+        .address(*contract.address())
+        .updater(config.signer.address())
+        .old_value(U256::from(2))
+        .into_filter()
+        .from_block(0);
+    let logs = provider.get_logs(&filter).await?;
+    assert_eq!(logs.len(), 1);
+    assert_eq!(ValueChanged::decode_log_data(logs[0].data(), true)?.newValue, U256::from(3));
+
+    let others = ValueChanged::filter().updater(Address::repeat_byte(1)).into_filter().from_block(0);
+    assert!(provider.get_logs(&others).await?.is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,224 @@
+//! Watching many deployments of a contract.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use alloy_in_action_common::{
+    Anvil, ContractWatcher, Deployment, Deployments, EventLog, EventStream, EventStreamError, SampleContract,
+    StreamEvent, TestNode, WatchedEvent,
+};
+use alloy_primitives::{Address, B256, U256};
+use alloy_rpc_types::Filter;
+use alloy_sol_types::SolEvent;
+use futures::channel::mpsc;
+use futures::{Stream, StreamExt};
+use SampleContract::{SampleContractEvents, ValueChanged};
+
+type Sender = mpsc::UnboundedSender<Result<StreamEvent<u64>, EventStreamError>>;
+
+/// Batches opened by a watcher: their addresses, start block, and the sender feeding their stream.
+type Batches = Arc<Mutex<Vec<(Vec<Address>, Option<u64>, Sender)>>>;
+
+/// Watches `deployments` with streams fed by the test instead of a node.
+fn watch(deployments: &Deployments, batch_size: usize) -> (impl Stream<Item = Result<WatchedEvent<u64>, EventStreamError>>, Batches) {
+    let batches = Batches::default();
+    let opened = batches.clone();
+    let events = ContractWatcher::new(EventStream::new(Filter::new()), deployments.clone())
+        .with_batch_size(batch_size)
+        .watch(move |stream| {
+            let mut addresses: Vec<Address> = stream.filter().address.iter().copied().collect();
+            addresses.sort();
+            let (sender, receiver) = mpsc::unbounded();
+            opened.lock().unwrap().push((addresses, stream.start_block(), sender));
+            async move { Ok(receiver) }
+        });
+    (events, batches)
+}
+
+fn address(byte: u8) -> Address {
+    Address::repeat_byte(byte)
+}
+
+fn observed(address: Address, block_number: u64) -> Result<StreamEvent<u64>, EventStreamError> {
+    Ok(StreamEvent::Observed(Arc::new(EventLog {
+        block_number,
+        block_hash: B256::ZERO,
+        transaction_hash: B256::ZERO,
+        log_index: 0,
+        address,
+        data: block_number,
+    })))
+}
+
+/// The name of the deployment and the block of the next watched event.
+async fn next(events: &mut (impl Stream<Item = Result<WatchedEvent<u64>, EventStreamError>> + Unpin)) -> (String, u64) {
+    let watched = tokio::time::timeout(Duration::from_secs(5), events.next()).await.unwrap().unwrap().unwrap();
+    (watched.deployment.name, watched.event.log().block_number)
+}
+
+/// Lets the watcher pick up a change of the deployments.
+async fn settle() {
+    tokio::time::sleep(Duration::from_millis(20)).await;
+}
+
+#[test]
+fn adds_renames_and_removes_deployments() {
+    let deployments = Deployments::new();
+    assert!(deployments.add(address(2), "bob", None));
+    assert!(deployments.add(address(1), "alice", None));
+    assert!(!deployments.add(address(2), "carol", None));
+    assert_eq!(deployments.addresses(), [address(1), address(2)]);
+    assert_eq!(deployments.get(&address(2)), Some(Deployment { address: address(2), name: "carol".to_string() }));
+
+    assert!(!deployments.add_new(address(2), "dave", None));
+    assert!(deployments.add_new(address(3), "dave", None));
+
+    assert!(deployments.remove(&address(1)));
+    assert!(!deployments.remove(&address(1)));
+    assert_eq!(deployments.len(), 2);
+    assert_eq!(deployments.get(&address(1)), None);
+}
+
+#[tokio::test]
+async fn batches_addresses_and_tags_events() {
+    let deployments = Deployments::new();
+    for (byte, name) in [(1, "a"), (2, "b"), (3, "c")] {
+        deployments.add(address(byte), name, None);
+    }
+    let (events, batches) = watch(&deployments, 2);
+    futures::pin_mut!(events);
+
+    // The first poll opens the batches of the initial deployments
+    let initial = tokio::time::timeout(Duration::from_millis(50), events.next()).await;
+    assert!(initial.is_err());
+    let senders: Vec<Sender> = {
+        let batches = batches.lock().unwrap();
+        let addresses: Vec<_> = batches.iter().map(|(addresses, _, _)| addresses.clone()).collect();
+        assert_eq!(addresses, [vec![address(1), address(2)], vec![address(3)]]);
+        batches.iter().map(|(_, _, sender)| sender.clone()).collect()
+    };
+
+    senders[1].unbounded_send(observed(address(3), 10)).unwrap();
+    assert_eq!(next(&mut events).await, ("c".to_string(), 10));
+    senders[0].unbounded_send(observed(address(2), 11)).unwrap();
+    assert_eq!(next(&mut events).await, ("b".to_string(), 11));
+}
+
+#[tokio::test]
+async fn follows_deployments_added_and_removed_at_runtime() {
+    let deployments = Deployments::new();
+    deployments.add(address(1), "a", None);
+    deployments.add(address(2), "b", None);
+    let (events, batches) = watch(&deployments, 10);
+    let mut events = Box::pin(events);
+    let _ = tokio::time::timeout(Duration::from_millis(20), events.next()).await;
+    let first = batches.lock().unwrap()[0].2.clone();
+
+    // Additions seen at the same time are batched together, next to the running batch
+    let watching = tokio::spawn(async move {
+        let mut seen = Vec::new();
+        while let Some(Ok(watched)) = events.next().await {
+            seen.push((watched.deployment.name, watched.event.log().block_number));
+        }
+        seen
+    });
+    deployments.add(address(3), "c", None);
+    deployments.add(address(4), "d", None);
+    settle().await;
+    let second = {
+        let batches = batches.lock().unwrap();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[1].0, [address(3), address(4)]);
+        batches[1].2.clone()
+    };
+    first.unbounded_send(observed(address(1), 20)).unwrap();
+    second.unbounded_send(observed(address(4), 21)).unwrap();
+    settle().await;
+
+    // Events of removed deployments are dropped, and a batch without deployments is stopped
+    deployments.remove(&address(1));
+    deployments.remove(&address(3));
+    deployments.remove(&address(4));
+    settle().await;
+    first.unbounded_send(observed(address(1), 22)).unwrap();
+    first.unbounded_send(observed(address(2), 23)).unwrap();
+    assert!(second.is_closed());
+    settle().await;
+
+    // Once the deployments are dropped and the batches ended, the watcher ends
+    drop(deployments);
+    drop((first, second));
+    batches.lock().unwrap().clear();
+    let seen = tokio::time::timeout(Duration::from_secs(5), watching).await.unwrap().unwrap();
+    assert_eq!(seen, [("a".to_string(), 20), ("d".to_string(), 21), ("b".to_string(), 23)]);
+}
+
+#[tokio::test]
+async fn backfills_added_deployments_and_reopens_ended_batches() {
+    let deployments = Deployments::new();
+    deployments.add(address(1), "a", None);
+    let (events, batches) = watch(&deployments, 10);
+    let mut events = Box::pin(events);
+    let _ = tokio::time::timeout(Duration::from_millis(20), events.next()).await;
+
+    // Deployments added with a start block are batched apart and followed from it
+    deployments.add(address(2), "b", Some(7));
+    deployments.add(address(3), "c", None);
+    let _ = tokio::time::timeout(Duration::from_millis(20), events.next()).await;
+    let opened: Vec<_> = batches.lock().unwrap().iter().map(|(addresses, start, _)| (addresses.clone(), *start)).collect();
+    assert_eq!(opened, [(vec![address(1)], None), (vec![address(3)], None), (vec![address(2)], Some(7))]);
+
+    // A batch whose stream ended is followed by a new batch once the set changes
+    let (_, _, ended) = batches.lock().unwrap().remove(0);
+    drop(ended);
+    let _ = tokio::time::timeout(Duration::from_millis(20), events.next()).await;
+    deployments.add(address(4), "d", None);
+    let _ = tokio::time::timeout(Duration::from_millis(20), events.next()).await;
+    let reopened = {
+        let batches = batches.lock().unwrap();
+        assert_eq!(batches.len(), 3);
+        assert_eq!(batches[2].0, [address(1), address(4)]);
+        batches[2].2.clone()
+    };
+    reopened.unbounded_send(observed(address(1), 30)).unwrap();
+    assert_eq!(next(&mut events).await, ("a".to_string(), 30));
+}
+
+#[tokio::test]
+async fn watches_deployments_on_a_node() -> eyre::Result<()> {
+    let Some(node) = TestNode::ws(Anvil::new()).await? else { return Ok(()) };
+    let provider = &node.provider;
+    let first = node.deploy().await?;
+    let second = node.deploy().await?;
+    let third = node.deploy().await?;
+
+    let deployments = Deployments::new();
+    deployments.add(*first.address(), "first", None);
+    deployments.add(*second.address(), "second", None);
+    let stream = EventStream::new(Filter::new().event_signature(ValueChanged::SIGNATURE_HASH)).with_finality_depth(1);
+    let events = ContractWatcher::new(stream, deployments.clone())
+        .with_batch_size(1)
+        .subscribe::<SampleContractEvents, _, _>(provider.clone());
+    futures::pin_mut!(events);
+
+    // Start watching, then add the third deployment and remove the first
+    let _ = tokio::time::timeout(Duration::from_millis(200), events.next()).await;
+    deployments.add(*third.address(), "third", None);
+    deployments.remove(first.address());
+    let _ = tokio::time::timeout(Duration::from_millis(200), events.next()).await;
+
+    for contract in [&first, &second, &third] {
+        contract.setValue(U256::from(2)).send().await?.get_receipt().await?;
+    }
+    let mut observed = Vec::new();
+    while observed.len() < 2 {
+        let watched = tokio::time::timeout(Duration::from_secs(10), events.next()).await?.unwrap()?;
+        if let StreamEvent::Observed(log) = &watched.event {
+            assert_eq!(log.address, watched.deployment.address);
+            observed.push(watched.deployment.name);
+        }
+    }
+    observed.sort();
+    assert_eq!(observed, ["second", "third"]);
+
+    Ok(())
+}
@@ -0,0 +1,231 @@
+use alloy_in_action_common::{http_provider, ws_provider, Config, ConfigOverrides, ReceiptExt, RevertExt, SampleContract};
+use alloy_primitives::utils::{format_ether, parse_units};
+use alloy_primitives::{Address, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::{BlockNumberOrTag, Filter, TransactionReceipt};
+use alloy_sol_types::{SolEvent, SolEventInterface};
+use clap::{Parser, Subcommand};
+use eyre::{bail, eyre, Result};
+use futures::StreamExt;
+use SampleContract::{EtherReceived, EtherWithdrawn, SampleContractErrors, SampleContractEvents, ValueChanged};
+
+/// Deploy and interact with SampleContract instances.
+#[derive(Debug, Parser)]
+#[command(name = "sample-contract", version)]
+struct Cli {
+    #[command(flatten)]
+    config: ConfigOverrides,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Deploy a new SampleContract
+    Deploy {
+        /// Value assigned by the constructor
+        #[arg(long, default_value = "1")]
+        initial_value: U256,
+    },
+    /// Read the contract's current value
+    GetValue {
+        #[command(flatten)]
+        contract: ContractArgs,
+    },
+    /// Update the contract's value
+    SetValue {
+        #[command(flatten)]
+        contract: ContractArgs,
+        /// New value to store
+        value: U256,
+    },
+    /// Deposit Ether into the contract
+    Deposit {
+        #[command(flatten)]
+        contract: ContractArgs,
+        /// Amount with an optional unit, e.g. `1.5ether`, `2gwei` or `1000` (wei)
+        #[arg(long, value_parser = parse_amount)]
+        amount: U256,
+    },
+    /// Withdraw the contract's entire balance to the signer
+    Withdraw {
+        #[command(flatten)]
+        contract: ContractArgs,
+    },
+    /// Show the contract and signer balances
+    Balance {
+        #[command(flatten)]
+        contract: ContractArgs,
+    },
+    /// Stream the contract's events over WebSocket until interrupted
+    WatchEvents {
+        #[command(flatten)]
+        contract: ContractArgs,
+    },
+}
+
+#[derive(Debug, clap::Args)]
+struct ContractArgs {
+    /// Address of the deployed SampleContract
+    #[arg(long, env = "SAMPLE_CONTRACT_ADDRESS")]
+    address: Address,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    // Load the configuration (config file, root .env and command-line overrides)
+    let config = Config::load_with(&cli.config)?;
+
+    // Initialize tracing subscriber for logging
+    tracing_subscriber::fmt::init();
+
+    match cli.command {
+        Command::Deploy { initial_value } => deploy(&config, initial_value).await,
+        Command::GetValue { contract } => get_value(&config, contract.address).await,
+        Command::SetValue { contract, value } => set_value(&config, contract.address, value).await,
+        Command::Deposit { contract, amount } => deposit(&config, contract.address, amount).await,
+        Command::Withdraw { contract } => withdraw(&config, contract.address).await,
+        Command::Balance { contract } => balance(&config, contract.address).await,
+        Command::WatchEvents { contract } => watch_events(&config, contract.address).await,
+    }
+}
+
+async fn deploy(config: &Config, initial_value: U256) -> Result<()> {
+    let provider = http_provider(config);
+    let contract = SampleContract::deploy(&provider, initial_value).await?;
+    println!(
+        "📦 Contract deployed at address {} with initial value: {}",
+        contract.address(), initial_value
+    );
+    Ok(())
+}
+
+async fn get_value(config: &Config, address: Address) -> Result<()> {
+    let provider = http_provider(config);
+    let contract = SampleContract::new(address, &provider);
+    let current_value = contract.getValue().call().await.map_err(revert_report)?.currentValue;
+    println!("🔍 Current value: {}", current_value);
+    Ok(())
+}
+
+async fn set_value(config: &Config, address: Address, value: U256) -> Result<()> {
+    let provider = http_provider(config);
+    let contract = SampleContract::new(address, &provider);
+    let receipt = contract.setValue(value).send().await.map_err(revert_report)?.get_receipt().await?;
+    print_receipt(&receipt)
+}
+
+async fn deposit(config: &Config, address: Address, amount: U256) -> Result<()> {
+    let provider = http_provider(config);
+    let contract = SampleContract::new(address, &provider);
+    let receipt = contract.deposit().value(amount).send().await.map_err(revert_report)?.get_receipt().await?;
+    print_receipt(&receipt)
+}
+
+async fn withdraw(config: &Config, address: Address) -> Result<()> {
+    let provider = http_provider(config);
+    let contract = SampleContract::new(address, &provider);
+    let receipt = contract.withdraw().send().await.map_err(revert_report)?.get_receipt().await?;
+    print_receipt(&receipt)
+}
+
+async fn balance(config: &Config, address: Address) -> Result<()> {
+    let provider = http_provider(config);
+    let contract = SampleContract::new(address, &provider);
+    let contract_balance = contract.getBalance().call().await.map_err(revert_report)?.balance;
+    println!("🔍 Contract balance: {} Ξ", format_ether(contract_balance));
+    let signer_balance = provider.get_balance(config.signer.address()).await?;
+    println!("🔍 Signer balance: {} Ξ", format_ether(signer_balance));
+    Ok(())
+}
+
+async fn watch_events(config: &Config, address: Address) -> Result<()> {
+    let provider = ws_provider(config).await?;
+
+    // Create a combined filter for all SampleContract events starting from the latest block
+    let events_filter = Filter::new()
+        .address(address)
+        .event_signature(vec![
+            ValueChanged::SIGNATURE_HASH,
+            EtherReceived::SIGNATURE_HASH,
+            EtherWithdrawn::SIGNATURE_HASH
+        ])
+        .from_block(BlockNumberOrTag::Latest);
+
+    let mut events_stream = provider.subscribe_logs(&events_filter).await?.into_stream();
+    println!("👂 Listening for events on {}... Press Ctrl+C to exit.", address);
+
+    while let Some(log) = events_stream.next().await {
+        match SampleContractEvents::decode_log(log.as_ref(), true) {
+            Ok(event) => print_event(&event.data),
+            Err(_) => eprintln!("⚠️ Unknown event received."),
+        }
+    }
+
+    bail!("event subscription closed")
+}
+
+/// Reports a failed contract call with its decoded revert reason, if it reverted.
+fn revert_report(error: alloy_contract::Error) -> eyre::Report {
+    match error.revert::<SampleContractErrors>() {
+        Some(revert) => eyre!("contract reverted: {revert}"),
+        None => error.into(),
+    }
+}
+
+/// Prints the transaction hash and the decoded SampleContract events of a receipt.
+fn print_receipt(receipt: &TransactionReceipt) -> Result<()> {
+    if !receipt.status() {
+        bail!("transaction {:#x} reverted", receipt.transaction_hash);
+    }
+    println!("✅ Transaction confirmed ({:#x}).", receipt.transaction_hash);
+
+    let events = receipt.decode_events::<SampleContractEvents>();
+    for event in events.iter() {
+        print_event(event);
+    }
+    for log in &events.undecoded {
+        eprintln!("⚠️ Unknown event received from {}: {}", log.address, log.error);
+    }
+    Ok(())
+}
+
+fn print_event(event: &SampleContractEvents) {
+    match event {
+        SampleContractEvents::ValueChanged(e) => {
+            println!(
+                "⚡️ ValueChanged   - updater: {}, oldValue: {}, newValue: {}",
+                e.updater, e.oldValue, e.newValue
+            );
+        }
+        SampleContractEvents::EtherReceived(e) => {
+            println!(
+                "⚡️ EtherReceived  - sender: {}, amount: {} Ξ, newBalance: {} Ξ",
+                e.sender, format_ether(e.amount), format_ether(e.newBalance)
+            );
+        }
+        SampleContractEvents::EtherWithdrawn(e) => {
+            println!(
+                "⚡️ EtherWithdrawn - recipient: {}, amount: {} Ξ, remainingBalance: {} Ξ",
+                e.recipient, format_ether(e.amount), format_ether(e.remainingBalance)
+            );
+        }
+    }
+}
+
+/// Parses an Ether amount such as `1.5ether`, `2 gwei` or `1000` (wei).
+fn parse_amount(amount: &str) -> Result<U256, String> {
+    let amount = amount.trim();
+    let split = amount
+        .find(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(amount.len());
+    let (value, unit) = amount.split_at(split);
+    let unit = if unit.is_empty() { "wei" } else { unit };
+
+    parse_units(value.trim(), unit)
+        .map(Into::into)
+        .map_err(|error| format!("invalid amount `{amount}`: {error}"))
+}
@@ -0,0 +1,129 @@
+//! HTTP/JSON API over the indexed events.
+//!
+//! - `GET /contracts/{address}/value-history`: the contract's `ValueChanged` events,
+//! - `GET /contracts/{address}/value?block=`: its value at the end of a block (default latest),
+//! - `GET /contracts/{address}/deposits?sender=`: its `EtherReceived` events, optionally of one
+//!   sender,
+//! - `GET /contracts/{address}/totals`: the Ether deposited and withdrawn per account,
+//! - `GET /events?type=&contract=&account=&from_block=&to_block=`: all indexed events matching
+//!   the given filters.
+//!
+//! Amounts are decimal strings. Malformed addresses or numbers are answered with
+//! `400 Bad Request`, database errors with `500 Internal Server Error` and a JSON `error`.
+
+use std::sync::{Arc, Mutex};
+use alloy_primitives::{Address, U256};
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use crate::database::{AccountTotals, Database, EventQuery, IndexedEvent, ValueChange};
+
+type SharedDatabase = Arc<Mutex<Database>>;
+
+/// Builds the API's routes over `database`.
+pub fn router(database: Database) -> Router {
+    Router::new()
+        .route("/contracts/:address/value-history", get(value_history))
+        .route("/contracts/:address/value", get(value))
+        .route("/contracts/:address/deposits", get(deposits))
+        .route("/contracts/:address/totals", get(totals))
+        .route("/events", get(events))
+        .with_state(Arc::new(Mutex::new(database)))
+}
+
+#[derive(Debug, Deserialize)]
+struct ValueQuery {
+    block: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct Value {
+    block: Option<u64>,
+    #[serde(serialize_with = "crate::database::decimal")]
+    value: U256,
+}
+
+#[derive(Debug, Deserialize)]
+struct DepositsQuery {
+    sender: Option<Address>,
+}
+
+async fn value_history(
+    State(database): State<SharedDatabase>,
+    Path(address): Path<Address>,
+) -> Result<Json<Vec<ValueChange>>, ApiError> {
+    Ok(Json(lock(&database).value_history(address)?))
+}
+
+async fn value(
+    State(database): State<SharedDatabase>,
+    Path(address): Path<Address>,
+    Query(query): Query<ValueQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let block_number = query.block.unwrap_or(u64::MAX);
+    match lock(&database).value_at(address, block_number)? {
+        Some(value) => Ok(Json(Value { block: query.block, value })),
+        None => Err(ApiError::NotFound(format!("no value changes indexed for {address}"))),
+    }
+}
+
+async fn deposits(
+    State(database): State<SharedDatabase>,
+    Path(address): Path<Address>,
+    Query(query): Query<DepositsQuery>,
+) -> Result<Json<Vec<IndexedEvent>>, ApiError> {
+    let query = EventQuery {
+        contract: Some(address),
+        name: Some("EtherReceived".to_string()),
+        account: query.sender,
+        ..Default::default()
+    };
+    Ok(Json(lock(&database).events(&query)?))
+}
+
+async fn totals(
+    State(database): State<SharedDatabase>,
+    Path(address): Path<Address>,
+) -> Result<Json<Vec<AccountTotals>>, ApiError> {
+    Ok(Json(lock(&database).account_totals(address)?))
+}
+
+async fn events(
+    State(database): State<SharedDatabase>,
+    Query(query): Query<EventQuery>,
+) -> Result<Json<Vec<IndexedEvent>>, ApiError> {
+    Ok(Json(lock(&database).events(&query)?))
+}
+
+fn lock(database: &SharedDatabase) -> std::sync::MutexGuard<'_, Database> {
+    database.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Errors answered by the API.
+#[derive(Debug)]
+enum ApiError {
+    /// Querying the database failed.
+    Database(rusqlite::Error),
+    /// Nothing is indexed for the request.
+    NotFound(String),
+}
+
+impl From<rusqlite::Error> for ApiError {
+    fn from(error: rusqlite::Error) -> Self {
+        ApiError::Database(error)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, message) = match self {
+            ApiError::Database(error) => (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+            ApiError::NotFound(message) => (StatusCode::NOT_FOUND, message),
+        };
+        (status, Json(json!({ "error": message }))).into_response()
+    }
+}
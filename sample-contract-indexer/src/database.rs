@@ -0,0 +1,501 @@
+//! SQLite storage of indexed SampleContract events and the state derived from them.
+//!
+//! Every observed event is stored in the `events` table with its block number and hash,
+//! transaction hash and log index. Applying an event also updates the derived tables:
+//!
+//! - `value_history` holds one row per `ValueChanged` event,
+//! - `account_totals` holds the Ether deposited and withdrawn per contract and account.
+//!
+//! A retracted event is removed again and its effect on the derived tables rolled back. Once an
+//! event is final it is flagged as such and the contract's checkpoint saved in the same
+//! transaction, so a restarted indexer rolls back the events that were not final yet and
+//! resumes right after the checkpoint.
+
+use std::path::Path;
+use alloy_in_action_common::{Checkpoint, EventLog, SampleContract, SqliteCheckpointStore, StreamEvent};
+use alloy_primitives::{Address, TxHash, B256, U256};
+use rusqlite::types::{Type, Value};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row, Transaction};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::json;
+use SampleContract::SampleContractEvents;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS events (
+        id INTEGER PRIMARY KEY,
+        contract TEXT NOT NULL,
+        block_number INTEGER NOT NULL,
+        block_hash TEXT NOT NULL,
+        transaction_hash TEXT NOT NULL,
+        log_index INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        account TEXT NOT NULL,
+        amount TEXT,
+        arguments TEXT NOT NULL,
+        finalized INTEGER NOT NULL DEFAULT 0,
+        UNIQUE (block_hash, log_index)
+    );
+    CREATE INDEX IF NOT EXISTS events_position ON events (contract, block_number, log_index);
+
+    CREATE TABLE IF NOT EXISTS value_history (
+        event_id INTEGER PRIMARY KEY,
+        contract TEXT NOT NULL,
+        block_number INTEGER NOT NULL,
+        log_index INTEGER NOT NULL,
+        transaction_hash TEXT NOT NULL,
+        updater TEXT NOT NULL,
+        old_value TEXT NOT NULL,
+        new_value TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS value_history_position ON value_history (contract, block_number, log_index);
+
+    CREATE TABLE IF NOT EXISTS account_totals (
+        contract TEXT NOT NULL,
+        account TEXT NOT NULL,
+        deposited TEXT NOT NULL,
+        withdrawn TEXT NOT NULL,
+        deposits INTEGER NOT NULL,
+        withdrawals INTEGER NOT NULL,
+        PRIMARY KEY (contract, account)
+    );
+";
+
+/// A `ValueChanged` event in the value history of a contract.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ValueChange {
+    pub block_number: u64,
+    pub log_index: u64,
+    pub transaction_hash: TxHash,
+    pub updater: Address,
+    #[serde(serialize_with = "decimal")]
+    pub old_value: U256,
+    #[serde(serialize_with = "decimal")]
+    pub new_value: U256,
+}
+
+/// The Ether an account deposited into and withdrew from a contract.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct AccountTotals {
+    pub account: Address,
+    #[serde(serialize_with = "decimal")]
+    pub deposited: U256,
+    #[serde(serialize_with = "decimal")]
+    pub withdrawn: U256,
+    /// Number of `EtherReceived` events.
+    pub deposits: u64,
+    /// Number of `EtherWithdrawn` events.
+    pub withdrawals: u64,
+}
+
+/// An event as stored in the `events` table.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct IndexedEvent {
+    pub contract: Address,
+    pub block_number: u64,
+    pub block_hash: B256,
+    pub transaction_hash: TxHash,
+    pub log_index: u64,
+    /// Name of the event, e.g. `EtherReceived`.
+    pub name: String,
+    /// The event's arguments by their Solidity names, amounts as decimal strings.
+    pub arguments: serde_json::Value,
+    /// Whether the event's block is buried deep enough to be final.
+    pub finalized: bool,
+}
+
+/// Selects indexed events; unset fields match every event.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct EventQuery {
+    /// Contract that emitted the event.
+    pub contract: Option<Address>,
+    /// Name of the event, e.g. `EtherWithdrawn`.
+    #[serde(rename = "type")]
+    pub name: Option<String>,
+    /// The updater, sender or recipient of the event.
+    pub account: Option<Address>,
+    /// First block to include.
+    pub from_block: Option<u64>,
+    /// Last block to include.
+    pub to_block: Option<u64>,
+}
+
+/// An indexed event as needed to roll it back.
+struct StoredEvent {
+    id: i64,
+    contract: String,
+    name: String,
+    account: String,
+    amount: Option<String>,
+}
+
+/// The indexer's SQLite database.
+#[derive(Debug)]
+pub struct Database {
+    connection: Connection,
+}
+
+impl Database {
+    /// Opens (or creates) the database at `path`.
+    ///
+    /// The database is switched to write-ahead logging, so the query API can read it while the
+    /// indexer writes.
+    pub fn open(path: impl AsRef<Path>) -> rusqlite::Result<Self> {
+        let connection = Connection::open(path)?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        Self::with_connection(connection)
+    }
+
+    /// Uses a private in-memory database, e.g. in tests.
+    pub fn in_memory() -> rusqlite::Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(connection: Connection) -> rusqlite::Result<Self> {
+        connection.execute_batch(SCHEMA)?;
+        SqliteCheckpointStore::create_table(&connection)?;
+        Ok(Self { connection })
+    }
+
+    /// Name of the checkpoint under which the events of `contract` are indexed.
+    pub fn subscription(contract: Address) -> String {
+        format!("sample-contract:{contract:#x}")
+    }
+
+    /// Returns the position of the last final event indexed for `contract`.
+    pub fn checkpoint(&self, contract: Address) -> rusqlite::Result<Option<Checkpoint>> {
+        SqliteCheckpointStore::load_from(&self.connection, &Self::subscription(contract))
+    }
+
+    /// Applies a state transition of an event in one transaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - `Observed` stores the event and updates the derived tables, `Retracted` rolls
+    ///   that back, and `Finalized` marks the event final and advances the checkpoint of its
+    ///   contract. Observing an already stored event again has no effect.
+    pub fn apply(&mut self, event: &StreamEvent<SampleContractEvents>) -> rusqlite::Result<()> {
+        let transaction = self.connection.transaction()?;
+        match event {
+            StreamEvent::Observed(log) => insert_event(&transaction, log)?,
+            StreamEvent::Retracted(log) => {
+                let stored = transaction
+                    .query_row(
+                        "SELECT id, contract, name, account, amount FROM events WHERE block_hash = ?1 AND log_index = ?2",
+                        params![format!("{:#x}", log.block_hash), log.log_index],
+                        stored_event,
+                    )
+                    .optional()?;
+                if let Some(stored) = stored {
+                    remove_event(&transaction, &stored)?;
+                }
+            }
+            StreamEvent::Finalized(log) => {
+                transaction.execute(
+                    "UPDATE events SET finalized = 1 WHERE block_hash = ?1 AND log_index = ?2",
+                    params![format!("{:#x}", log.block_hash), log.log_index],
+                )?;
+                SqliteCheckpointStore::save_to(&transaction, &Self::subscription(log.address), Checkpoint::of(log))?;
+            }
+        }
+        transaction.commit()
+    }
+
+    /// Removes all events that are not final yet and rolls back their effects.
+    ///
+    /// Called on startup: the blocks of those events may have been reorganized away while the
+    /// indexer was not running, and they are observed again when it resumes after the
+    /// checkpoint.
+    ///
+    /// # Returns
+    ///
+    /// * `rusqlite::Result<usize>` - The number of removed events.
+    pub fn rollback_pending(&mut self) -> rusqlite::Result<usize> {
+        let transaction = self.connection.transaction()?;
+        let pending = transaction
+            .prepare("SELECT id, contract, name, account, amount FROM events WHERE finalized = 0")?
+            .query_map((), stored_event)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        for stored in &pending {
+            remove_event(&transaction, stored)?;
+        }
+        transaction.commit()?;
+        Ok(pending.len())
+    }
+
+    /// Returns the `ValueChanged` events of `contract` in chain order.
+    pub fn value_history(&self, contract: Address) -> rusqlite::Result<Vec<ValueChange>> {
+        self.connection
+            .prepare(
+                "SELECT block_number, log_index, transaction_hash, updater, old_value, new_value FROM value_history
+                 WHERE contract = ?1 ORDER BY block_number, log_index",
+            )?
+            .query_map(params![format!("{contract:#x}")], value_change)?
+            .collect()
+    }
+
+    /// Returns the value `contract` held at the end of block `block_number`.
+    ///
+    /// # Returns
+    ///
+    /// * `rusqlite::Result<Option<U256>>` - The new value of the last change up to the block,
+    ///   the old value of the first change after it if there is none, or `None` if no change
+    ///   of the contract is indexed.
+    pub fn value_at(&self, contract: Address, block_number: u64) -> rusqlite::Result<Option<U256>> {
+        let contract = format!("{contract:#x}");
+        // SQLite integers are signed, and no block is indexed beyond the largest one they hold
+        let block_number = i64::try_from(block_number).unwrap_or(i64::MAX);
+        let last_change = self
+            .connection
+            .query_row(
+                "SELECT new_value FROM value_history WHERE contract = ?1 AND block_number <= ?2
+                 ORDER BY block_number DESC, log_index DESC LIMIT 1",
+                params![contract, block_number],
+                |row| parse_column::<U256>(row, 0),
+            )
+            .optional()?;
+        if last_change.is_some() {
+            return Ok(last_change);
+        }
+        self.connection
+            .query_row(
+                "SELECT old_value FROM value_history WHERE contract = ?1 AND block_number > ?2
+                 ORDER BY block_number, log_index LIMIT 1",
+                params![contract, block_number],
+                |row| parse_column::<U256>(row, 0),
+            )
+            .optional()
+    }
+
+    /// Returns the events matching `query` in chain order.
+    pub fn events(&self, query: &EventQuery) -> rusqlite::Result<Vec<IndexedEvent>> {
+        let mut conditions = vec!["1 = 1"];
+        let mut values = Vec::new();
+        if let Some(contract) = query.contract {
+            conditions.push("contract = ?");
+            values.push(Value::Text(format!("{contract:#x}")));
+        }
+        if let Some(name) = &query.name {
+            conditions.push("name = ?");
+            values.push(Value::Text(name.clone()));
+        }
+        if let Some(account) = query.account {
+            conditions.push("account = ?");
+            values.push(Value::Text(format!("{account:#x}")));
+        }
+        if let Some(from_block) = query.from_block {
+            conditions.push("block_number >= ?");
+            values.push(Value::Integer(from_block.try_into().unwrap_or(i64::MAX)));
+        }
+        if let Some(to_block) = query.to_block {
+            conditions.push("block_number <= ?");
+            values.push(Value::Integer(to_block.try_into().unwrap_or(i64::MAX)));
+        }
+
+        let sql = format!(
+            "SELECT contract, block_number, block_hash, transaction_hash, log_index, name, arguments, finalized FROM events
+             WHERE {} ORDER BY block_number, log_index",
+            conditions.join(" AND ")
+        );
+        self.connection.prepare(&sql)?.query_map(params_from_iter(values), indexed_event)?.collect()
+    }
+
+    /// Returns the deposit and withdrawal totals of every account of `contract`.
+    pub fn account_totals(&self, contract: Address) -> rusqlite::Result<Vec<AccountTotals>> {
+        self.connection
+            .prepare(
+                "SELECT account, deposited, withdrawn, deposits, withdrawals FROM account_totals
+                 WHERE contract = ?1 ORDER BY account",
+            )?
+            .query_map(params![format!("{contract:#x}")], account_totals)?
+            .collect()
+    }
+}
+
+/// Stores an observed event and applies it to the derived tables, unless it is already stored.
+fn insert_event(transaction: &Transaction<'_>, log: &EventLog<SampleContractEvents>) -> rusqlite::Result<()> {
+    let contract = format!("{:#x}", log.address);
+    let (name, account, amount, arguments) = match &log.data {
+        SampleContractEvents::ValueChanged(e) => (
+            "ValueChanged",
+            e.updater,
+            None,
+            json!({ "updater": e.updater, "oldValue": e.oldValue.to_string(), "newValue": e.newValue.to_string() }),
+        ),
+        SampleContractEvents::EtherReceived(e) => (
+            "EtherReceived",
+            e.sender,
+            Some(e.amount),
+            json!({ "sender": e.sender, "amount": e.amount.to_string(), "newBalance": e.newBalance.to_string() }),
+        ),
+        SampleContractEvents::EtherWithdrawn(e) => (
+            "EtherWithdrawn",
+            e.recipient,
+            Some(e.amount),
+            json!({ "recipient": e.recipient, "amount": e.amount.to_string(), "remainingBalance": e.remainingBalance.to_string() }),
+        ),
+    };
+    let inserted = transaction.execute(
+        "INSERT OR IGNORE INTO events
+         (contract, block_number, block_hash, transaction_hash, log_index, name, account, amount, arguments)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            contract,
+            log.block_number,
+            format!("{:#x}", log.block_hash),
+            format!("{:#x}", log.transaction_hash),
+            log.log_index,
+            name,
+            format!("{account:#x}"),
+            amount.map(|amount| amount.to_string()),
+            arguments.to_string(),
+        ],
+    )?;
+    // The event was stored before, e.g. by a previous run
+    if inserted == 0 {
+        return Ok(());
+    }
+    let event_id = transaction.last_insert_rowid();
+
+    match &log.data {
+        SampleContractEvents::ValueChanged(e) => {
+            transaction.execute(
+                "INSERT INTO value_history
+                 (event_id, contract, block_number, log_index, transaction_hash, updater, old_value, new_value)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    event_id,
+                    contract,
+                    log.block_number,
+                    log.log_index,
+                    format!("{:#x}", log.transaction_hash),
+                    format!("{:#x}", e.updater),
+                    e.oldValue.to_string(),
+                    e.newValue.to_string(),
+                ],
+            )?;
+        }
+        SampleContractEvents::EtherReceived(e) => update_totals(transaction, &contract, &format!("{:#x}", e.sender), name, e.amount, true)?,
+        SampleContractEvents::EtherWithdrawn(e) => update_totals(transaction, &contract, &format!("{:#x}", e.recipient), name, e.amount, true)?,
+    }
+    Ok(())
+}
+
+/// Deletes a stored event and rolls back its effect on the derived tables.
+fn remove_event(transaction: &Transaction<'_>, stored: &StoredEvent) -> rusqlite::Result<()> {
+    transaction.execute("DELETE FROM value_history WHERE event_id = ?1", params![stored.id])?;
+    if let Some(amount) = &stored.amount {
+        let amount = amount.parse::<U256>().map_err(|error| conversion_error(4, error))?;
+        update_totals(transaction, &stored.contract, &stored.account, &stored.name, amount, false)?;
+    }
+    transaction.execute("DELETE FROM events WHERE id = ?1", params![stored.id])?;
+    Ok(())
+}
+
+/// Adds (or, when rolling back, subtracts) a deposit or withdrawal to the account's totals.
+fn update_totals(
+    transaction: &Transaction<'_>,
+    contract: &str,
+    account: &str,
+    name: &str,
+    amount: U256,
+    apply: bool,
+) -> rusqlite::Result<()> {
+    let mut totals = transaction
+        .query_row(
+            "SELECT account, deposited, withdrawn, deposits, withdrawals FROM account_totals
+             WHERE contract = ?1 AND account = ?2",
+            params![contract, account],
+            account_totals,
+        )
+        .optional()?
+        .unwrap_or_default();
+
+    let (total, count) = match name {
+        "EtherReceived" => (&mut totals.deposited, &mut totals.deposits),
+        _ => (&mut totals.withdrawn, &mut totals.withdrawals),
+    };
+    if apply {
+        *total += amount;
+        *count += 1;
+    } else {
+        *total -= amount;
+        *count -= 1;
+    }
+
+    transaction.execute(
+        "INSERT INTO account_totals (contract, account, deposited, withdrawn, deposits, withdrawals)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+         ON CONFLICT (contract, account) DO UPDATE SET deposited = ?3, withdrawn = ?4, deposits = ?5, withdrawals = ?6",
+        params![
+            contract,
+            account,
+            totals.deposited.to_string(),
+            totals.withdrawn.to_string(),
+            totals.deposits,
+            totals.withdrawals,
+        ],
+    )?;
+    Ok(())
+}
+
+fn stored_event(row: &Row<'_>) -> rusqlite::Result<StoredEvent> {
+    Ok(StoredEvent {
+        id: row.get(0)?,
+        contract: row.get(1)?,
+        name: row.get(2)?,
+        account: row.get(3)?,
+        amount: row.get(4)?,
+    })
+}
+
+fn indexed_event(row: &Row<'_>) -> rusqlite::Result<IndexedEvent> {
+    Ok(IndexedEvent {
+        contract: parse_column(row, 0)?,
+        block_number: row.get(1)?,
+        block_hash: parse_column(row, 2)?,
+        transaction_hash: parse_column(row, 3)?,
+        log_index: row.get(4)?,
+        name: row.get(5)?,
+        arguments: parse_column(row, 6)?,
+        finalized: row.get(7)?,
+    })
+}
+
+fn value_change(row: &Row<'_>) -> rusqlite::Result<ValueChange> {
+    Ok(ValueChange {
+        block_number: row.get(0)?,
+        log_index: row.get(1)?,
+        transaction_hash: parse_column(row, 2)?,
+        updater: parse_column(row, 3)?,
+        old_value: parse_column(row, 4)?,
+        new_value: parse_column(row, 5)?,
+    })
+}
+
+fn account_totals(row: &Row<'_>) -> rusqlite::Result<AccountTotals> {
+    Ok(AccountTotals {
+        account: parse_column(row, 0)?,
+        deposited: parse_column(row, 1)?,
+        withdrawn: parse_column(row, 2)?,
+        deposits: row.get(3)?,
+        withdrawals: row.get(4)?,
+    })
+}
+
+/// Parses a text column holding an address, hash or decimal amount.
+fn parse_column<V>(row: &Row<'_>, index: usize) -> rusqlite::Result<V>
+where
+    V: std::str::FromStr,
+    V::Err: std::error::Error + Send + Sync + 'static,
+{
+    row.get::<_, String>(index)?.parse().map_err(|error| conversion_error(index, error))
+}
+
+fn conversion_error(index: usize, error: impl std::error::Error + Send + Sync + 'static) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(error))
+}
+
+/// Serializes an amount as a decimal string, as it is stored.
+pub(crate) fn decimal<S: Serializer>(value: &U256, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
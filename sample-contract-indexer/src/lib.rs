@@ -0,0 +1,11 @@
+//! Indexes the events of SampleContract deployments into a local SQLite database.
+//!
+//! The [`Database`] stores the decoded `ValueChanged`, `EtherReceived` and `EtherWithdrawn`
+//! events and maintains the value history and the per-account deposit and withdrawal totals
+//! derived from them, rolling both back when an event is retracted by a reorg. The [`api`]
+//! serves them as JSON over HTTP.
+
+pub mod api;
+pub mod database;
+
+pub use database::{AccountTotals, Database, EventQuery, IndexedEvent, ValueChange};
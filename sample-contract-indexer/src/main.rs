@@ -0,0 +1,207 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use alloy_in_action_common::event_stream::DEFAULT_POLL_INTERVAL;
+use alloy_in_action_common::{http_provider, ws_provider, Config, ConfigOverrides, EventStream, EventStreamError, SampleContract, StreamEvent};
+use alloy_primitives::utils::format_ether;
+use alloy_primitives::Address;
+use alloy_rpc_types::Filter;
+use alloy_sol_types::SolEvent;
+use clap::{Parser, Subcommand};
+use eyre::{bail, Result};
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use sample_contract_indexer::{api, Database};
+use SampleContract::{EtherReceived, EtherWithdrawn, SampleContractEvents, ValueChanged};
+
+/// The state transitions of the indexed events.
+type Events = BoxStream<'static, Result<StreamEvent<SampleContractEvents>, EventStreamError>>;
+
+/// Index SampleContract events into SQLite and query the derived state.
+#[derive(Debug, Parser)]
+#[command(name = "sample-contract-indexer", version)]
+struct Cli {
+    #[command(flatten)]
+    config: ConfigOverrides,
+
+    /// Path of the SQLite database
+    #[arg(long, global = true, default_value = "sample-contract-indexer.db")]
+    database: PathBuf,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Index the contract's events until interrupted, resuming after the last final event
+    Index {
+        #[command(flatten)]
+        contract: ContractArgs,
+        /// Block to start from when the contract was not indexed before
+        #[arg(long, default_value = "0")]
+        from_block: u64,
+        /// Number of blocks an event must be buried under before it is final
+        #[arg(long, default_value = "12")]
+        finality_depth: u64,
+    },
+    /// Show the indexed value history, or the value at a block
+    Value {
+        #[command(flatten)]
+        contract: ContractArgs,
+        /// Block at whose end to show the value
+        #[arg(long)]
+        block: Option<u64>,
+    },
+    /// Show the Ether deposited and withdrawn per account
+    Totals {
+        #[command(flatten)]
+        contract: ContractArgs,
+    },
+    /// Serve the indexed events as JSON over HTTP until interrupted
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: SocketAddr,
+    },
+}
+
+#[derive(Debug, clap::Args)]
+struct ContractArgs {
+    /// Address of the deployed SampleContract
+    #[arg(long, env = "SAMPLE_CONTRACT_ADDRESS")]
+    address: Address,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    // Initialize tracing subscriber for logging
+    tracing_subscriber::fmt::init();
+
+    let mut database = Database::open(&cli.database)?;
+    match cli.command {
+        Command::Index { contract, from_block, finality_depth } => {
+            // Load the configuration (config file, root .env and command-line overrides)
+            let config = Config::load_with(&cli.config)?;
+            tokio::select! {
+                result = index(&config, &mut database, contract.address, from_block, finality_depth) => result,
+                _ = tokio::signal::ctrl_c() => {
+                    println!("👋 Interrupted, events that are not final yet are re-indexed on the next run.");
+                    Ok(())
+                }
+            }
+        }
+        Command::Value { contract, block: Some(block) } => {
+            match database.value_at(contract.address, block)? {
+                Some(value) => println!("🔍 Value at block {}: {}", block, value),
+                None => println!("🔍 No value changes indexed for {}.", contract.address),
+            }
+            Ok(())
+        }
+        Command::Value { contract, block: None } => {
+            for change in database.value_history(contract.address)? {
+                println!(
+                    "📜 Block {:>6} - {} -> {} by {} ({:#x})",
+                    change.block_number, change.old_value, change.new_value, change.updater, change.transaction_hash
+                );
+            }
+            Ok(())
+        }
+        Command::Totals { contract } => {
+            for totals in database.account_totals(contract.address)? {
+                println!(
+                    "💰 {} - deposited: {} Ξ ({}x), withdrawn: {} Ξ ({}x)",
+                    totals.account,
+                    format_ether(totals.deposited),
+                    totals.deposits,
+                    format_ether(totals.withdrawn),
+                    totals.withdrawals
+                );
+            }
+            Ok(())
+        }
+        Command::Serve { listen } => {
+            let listener = tokio::net::TcpListener::bind(listen).await?;
+            println!("🌐 Serving the indexed events on http://{}... Press Ctrl+C to exit.", listener.local_addr()?);
+            axum::serve(listener, api::router(database))
+                .with_graceful_shutdown(async {
+                    let _ = tokio::signal::ctrl_c().await;
+                })
+                .await?;
+            Ok(())
+        }
+    }
+}
+
+/// Streams the contract's events into the database, over WebSocket or else by polling over HTTP.
+async fn index(config: &Config, database: &mut Database, address: Address, from_block: u64, finality_depth: u64) -> Result<()> {
+    // Events that were not final when the last run stopped may have been reorganized away
+    let rolled_back = database.rollback_pending()?;
+    if rolled_back > 0 {
+        println!("↩️ Rolled back {} event(s) that were not final yet.", rolled_back);
+    }
+
+    // Resume right after the last final event, or start from the given block
+    let filter = Filter::new().address(address).event_signature(vec![
+        ValueChanged::SIGNATURE_HASH,
+        EtherReceived::SIGNATURE_HASH,
+        EtherWithdrawn::SIGNATURE_HASH,
+    ]);
+    let stream = EventStream::new(filter).with_finality_depth(finality_depth);
+    let stream = match database.checkpoint(address)? {
+        Some(checkpoint) => {
+            println!("📍 Resuming after {}.", checkpoint);
+            stream.resume_after(checkpoint)
+        }
+        None => stream.from_block(from_block),
+    };
+
+    // Prefer WebSocket subscriptions, fall back to polling over HTTP when no WebSocket endpoint
+    // is configured or it cannot be reached
+    let events: Events = match ws_provider(config).await {
+        Ok(_) => {
+            let reconnect_config = config.clone();
+            stream
+                .subscribe_with_reconnect::<SampleContractEvents, _, _, _, _, _>(move || {
+                    let config = reconnect_config.clone();
+                    async move { ws_provider(&config).await }
+                })
+                .await?
+                .boxed()
+        }
+        Err(e) => {
+            let poll_interval = config.poll_interval().unwrap_or(DEFAULT_POLL_INTERVAL);
+            println!("🔌 WebSocket unavailable ({}), polling over HTTP every {:?}.", e, poll_interval);
+            stream
+                .with_poll_interval(poll_interval)
+                .poll::<SampleContractEvents, _, _>(http_provider(config))
+                .await?
+                .boxed()
+        }
+    };
+    println!("👂 Indexing events of {} into the database... Press Ctrl+C to exit.", address);
+    consume(database, events).await
+}
+
+/// Applies every state transition to the database.
+async fn consume(database: &mut Database, mut events: Events) -> Result<()> {
+    while let Some(event) = events.next().await {
+        let event = event?;
+        database.apply(&event)?;
+
+        let log = event.log();
+        let name = match &log.data {
+            SampleContractEvents::ValueChanged(_) => "ValueChanged",
+            SampleContractEvents::EtherReceived(_) => "EtherReceived",
+            SampleContractEvents::EtherWithdrawn(_) => "EtherWithdrawn",
+        };
+        match event {
+            StreamEvent::Observed(_) => println!("📥 Indexed {} in block {} (log {}).", name, log.block_number, log.log_index),
+            StreamEvent::Retracted(_) => println!("↩️ Rolled back {} in block {} (log {}).", name, log.block_number, log.log_index),
+            StreamEvent::Finalized(_) => println!("✅ Finalized {} in block {} (log {}).", name, log.block_number, log.log_index),
+        }
+    }
+
+    bail!("event stream ended")
+}
@@ -0,0 +1,108 @@
+//! Querying the indexed events over the HTTP API.
+
+use std::sync::Arc;
+use alloy_in_action_common::{EventLog, SampleContract, StreamEvent};
+use alloy_primitives::utils::parse_ether;
+use alloy_primitives::{Address, B256, U256};
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use http_body_util::BodyExt;
+use sample_contract_indexer::{api, Database};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+use SampleContract::{EtherReceived, EtherWithdrawn, SampleContractEvents, ValueChanged};
+
+const CONTRACT: Address = Address::repeat_byte(0xc0);
+const ALICE: Address = Address::repeat_byte(0xa1);
+const BOB: Address = Address::repeat_byte(0xb0);
+
+fn observed(block_number: u64, data: SampleContractEvents) -> StreamEvent<SampleContractEvents> {
+    StreamEvent::Observed(Arc::new(EventLog {
+        block_number,
+        block_hash: B256::with_last_byte(block_number as u8),
+        transaction_hash: B256::with_last_byte(0x80 | block_number as u8),
+        log_index: 0,
+        address: CONTRACT,
+        data,
+    }))
+}
+
+/// An API over an in-memory database with two value changes, two deposits and a withdrawal.
+fn api() -> eyre::Result<Router> {
+    let mut database = Database::in_memory()?;
+    let one = parse_ether("1")?;
+    let events = [
+        SampleContractEvents::ValueChanged(ValueChanged { updater: ALICE, oldValue: U256::from(1), newValue: U256::from(2) }),
+        SampleContractEvents::EtherReceived(EtherReceived { sender: ALICE, amount: one, newBalance: one }),
+        SampleContractEvents::EtherReceived(EtherReceived { sender: BOB, amount: one, newBalance: one * U256::from(2) }),
+        SampleContractEvents::ValueChanged(ValueChanged { updater: BOB, oldValue: U256::from(2), newValue: U256::from(3) }),
+        SampleContractEvents::EtherWithdrawn(EtherWithdrawn { recipient: BOB, amount: one * U256::from(2), remainingBalance: U256::ZERO }),
+    ];
+    for (block_number, event) in (1..).zip(events) {
+        database.apply(&observed(block_number, event))?;
+    }
+    Ok(api::router(database))
+}
+
+async fn get(api: &Router, uri: &str) -> eyre::Result<(StatusCode, Value)> {
+    let response = api.clone().oneshot(Request::get(uri).body(Body::empty())?).await?;
+    let status = response.status();
+    let body = response.into_body().collect().await?.to_bytes();
+    Ok((status, serde_json::from_slice(&body).unwrap_or(Value::Null)))
+}
+
+#[tokio::test]
+async fn serves_value_history_and_value_at_block() -> eyre::Result<()> {
+    let api = api()?;
+
+    let (status, history) = get(&api, &format!("/contracts/{CONTRACT}/value-history")).await?;
+    assert_eq!(status, StatusCode::OK);
+    let values: Vec<_> = history.as_array().unwrap().iter().map(|change| change["new_value"].clone()).collect();
+    assert_eq!(values, [json!("2"), json!("3")]);
+    assert_eq!(history[1]["block_number"], json!(4));
+
+    let (_, value) = get(&api, &format!("/contracts/{CONTRACT}/value?block=3")).await?;
+    assert_eq!(value, json!({ "block": 3, "value": "2" }));
+    let (_, value) = get(&api, &format!("/contracts/{CONTRACT}/value")).await?;
+    assert_eq!(value["value"], json!("3"));
+
+    // Blocks beyond what SQLite can store are past every indexed block
+    let (status, value) = get(&api, &format!("/contracts/{CONTRACT}/value?block={}", u64::MAX)).await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(value["value"], json!("3"));
+
+    let (status, error) = get(&api, &format!("/contracts/{ALICE}/value")).await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(error["error"].is_string());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn serves_deposits_totals_and_filtered_events() -> eyre::Result<()> {
+    let api = api()?;
+
+    let (_, deposits) = get(&api, &format!("/contracts/{CONTRACT}/deposits")).await?;
+    assert_eq!(deposits.as_array().unwrap().len(), 2);
+    let (_, deposits) = get(&api, &format!("/contracts/{CONTRACT}/deposits?sender={BOB}")).await?;
+    assert_eq!(deposits.as_array().unwrap().len(), 1);
+    assert_eq!(deposits[0]["arguments"]["amount"], json!(parse_ether("1")?.to_string()));
+
+    let (_, totals) = get(&api, &format!("/contracts/{CONTRACT}/totals")).await?;
+    let bob = totals.as_array().unwrap().iter().find(|totals| totals["account"] == json!(BOB)).unwrap();
+    assert_eq!((bob["deposits"].clone(), bob["withdrawn"].clone()), (json!(1), json!(parse_ether("2")?.to_string())));
+
+    let (_, events) = get(&api, "/events?type=EtherWithdrawn&from_block=2").await?;
+    assert_eq!(events.as_array().unwrap().len(), 1);
+    assert_eq!(events[0]["name"], json!("EtherWithdrawn"));
+    let (_, events) = get(&api, "/events?from_block=2&to_block=3").await?;
+    assert_eq!(events.as_array().unwrap().len(), 2);
+
+    let (status, _) = get(&api, "/events?from_block=latest").await?;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = get(&api, "/contracts/0xnot-an-address/totals").await?;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    Ok(())
+}
@@ -0,0 +1,120 @@
+//! Indexing events into the database and rolling them back.
+
+use std::sync::Arc;
+use std::time::Duration;
+use alloy_in_action_common::anvil::spawn_for_test;
+use alloy_in_action_common::{ws_provider, Anvil, Checkpoint, EventLog, EventStream, SampleContract, StreamEvent};
+use alloy_primitives::utils::parse_ether;
+use alloy_primitives::{Address, B256, U256};
+use alloy_rpc_types::Filter;
+use futures::StreamExt;
+use sample_contract_indexer::{AccountTotals, Database};
+use SampleContract::{EtherReceived, EtherWithdrawn, SampleContractEvents, ValueChanged};
+
+const CONTRACT: Address = Address::repeat_byte(0xc0);
+const ALICE: Address = Address::repeat_byte(0xa1);
+
+fn log(block_number: u64, log_index: u64, data: SampleContractEvents) -> Arc<EventLog<SampleContractEvents>> {
+    Arc::new(EventLog {
+        block_number,
+        block_hash: B256::with_last_byte(block_number as u8),
+        transaction_hash: B256::with_last_byte(0x80 | log_index as u8),
+        log_index,
+        address: CONTRACT,
+        data,
+    })
+}
+
+fn value_changed(block_number: u64, old_value: u64, new_value: u64) -> Arc<EventLog<SampleContractEvents>> {
+    let event = ValueChanged { updater: ALICE, oldValue: U256::from(old_value), newValue: U256::from(new_value) };
+    log(block_number, 0, SampleContractEvents::ValueChanged(event))
+}
+
+#[test]
+fn derives_state_and_rolls_back_retracted_events() -> eyre::Result<()> {
+    let mut database = Database::in_memory()?;
+    let one = parse_ether("1")?;
+    let deposit = log(6, 0, SampleContractEvents::EtherReceived(EtherReceived { sender: ALICE, amount: one, newBalance: one }));
+    let withdrawal = log(
+        7,
+        1,
+        SampleContractEvents::EtherWithdrawn(EtherWithdrawn { recipient: ALICE, amount: one, remainingBalance: U256::ZERO }),
+    );
+    for event in [value_changed(5, 1, 2), deposit.clone(), withdrawal, value_changed(8, 2, 3)] {
+        database.apply(&StreamEvent::Observed(event))?;
+    }
+
+    // The value before the first indexed change is its old value
+    assert_eq!(database.value_at(CONTRACT, 4)?, Some(U256::from(1)));
+    assert_eq!(database.value_at(CONTRACT, 7)?, Some(U256::from(2)));
+    assert_eq!(database.value_at(CONTRACT, 100)?, Some(U256::from(3)));
+    assert_eq!(database.value_at(ALICE, 100)?, None);
+    assert_eq!(
+        database.account_totals(CONTRACT)?,
+        [AccountTotals { account: ALICE, deposited: one, withdrawn: one, deposits: 1, withdrawals: 1 }]
+    );
+
+    // Observing an event again has no effect, retracting it rolls it back
+    database.apply(&StreamEvent::Observed(deposit.clone()))?;
+    database.apply(&StreamEvent::Retracted(deposit))?;
+    database.apply(&StreamEvent::Retracted(value_changed(8, 2, 3)))?;
+    assert_eq!(
+        database.account_totals(CONTRACT)?,
+        [AccountTotals { account: ALICE, deposited: U256::ZERO, withdrawn: one, deposits: 0, withdrawals: 1 }]
+    );
+    let history = database.value_history(CONTRACT)?;
+    assert_eq!(history.iter().map(|change| change.new_value.to::<u64>()).collect::<Vec<_>>(), [2]);
+    assert_eq!(database.value_at(CONTRACT, 100)?, Some(U256::from(2)));
+
+    Ok(())
+}
+
+#[test]
+fn restart_rolls_back_events_that_are_not_final() -> eyre::Result<()> {
+    let directory = tempfile::tempdir()?;
+    let path = directory.path().join("indexer.db");
+    let mut database = Database::open(&path)?;
+    database.apply(&StreamEvent::Observed(value_changed(5, 1, 2)))?;
+    database.apply(&StreamEvent::Observed(value_changed(6, 2, 3)))?;
+    database.apply(&StreamEvent::Finalized(value_changed(5, 1, 2)))?;
+    drop(database);
+
+    let mut database = Database::open(&path)?;
+    assert_eq!(database.checkpoint(CONTRACT)?, Some(Checkpoint::new(5, 0)));
+    assert_eq!(database.rollback_pending()?, 1);
+    assert_eq!(database.value_history(CONTRACT)?.len(), 1);
+    assert_eq!(database.value_at(CONTRACT, 100)?, Some(U256::from(2)));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn indexes_contract_events() -> eyre::Result<()> {
+    let Some(anvil) = spawn_for_test(Anvil::new()) else { return Ok(()) };
+    let config = anvil.config()?;
+    let provider = ws_provider(&config).await?;
+    let contract = SampleContract::deploy(&provider, U256::from(1)).await?;
+    let set_value = contract.setValue(U256::from(2)).send().await?.get_receipt().await?;
+    contract.deposit().value(parse_ether("1")?).send().await?.get_receipt().await?;
+
+    let stream = EventStream::new(Filter::new().address(*contract.address())).from_block(0).with_finality_depth(1);
+    let events = stream.subscribe::<SampleContractEvents, _, _>(provider.clone()).await?;
+    futures::pin_mut!(events);
+    let mut database = Database::in_memory()?;
+    let mut finalized = 0;
+    while finalized < 2 {
+        let event = tokio::time::timeout(Duration::from_secs(10), events.next()).await?.unwrap()?;
+        database.apply(&event)?;
+        finalized += matches!(event, StreamEvent::Finalized(_)) as usize;
+    }
+
+    let block_number = set_value.block_number.unwrap();
+    assert_eq!(database.value_at(*contract.address(), block_number - 1)?, Some(U256::from(1)));
+    assert_eq!(database.value_at(*contract.address(), block_number)?, Some(U256::from(2)));
+    let totals = database.account_totals(*contract.address())?;
+    assert_eq!(totals.len(), 1);
+    assert_eq!((totals[0].account, totals[0].deposited), (config.signer.address(), parse_ether("1")?));
+    assert!(database.checkpoint(*contract.address())?.is_some_and(|checkpoint| checkpoint.block_number > block_number));
+
+    Ok(())
+}